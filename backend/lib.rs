@@ -2,9 +2,12 @@ use candid::{CandidType, Nat, Principal};
 use ic_cdk::api::management_canister::http_request::{
     http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod,
 };
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::Duration;
 
 // MemoryMind Enhanced State with Personal Knowledge Graph
 #[derive(Serialize, Deserialize, Clone, CandidType, Default)]
@@ -25,6 +28,37 @@ struct State {
     ai_content_storage: HashMap<String, AIContent>,
     canister_metrics: CanisterMetrics,
     subscription_tiers: HashMap<Principal, SubscriptionTier>,
+    
+    // Controller-managed prompt templates
+    prompt_templates: PromptTemplateStore,
+    
+    // Derived per-user retrieval indexes, rebuilt from memory_nodes when missing
+    memory_indexes: HashMap<Principal, MemoryIndex>,
+    
+    // Ask the model for extra tags on new memories (costs one outcall per turn)
+    model_tagging_enabled: bool,
+    
+    // Ask the model about user turns the sentiment lexicon is unsure of (one outcall per such turn)
+    model_sentiment_enabled: bool,
+    
+    // Uploaded files that can be attached to prompts
+    attachments: HashMap<Principal, HashMap<String, Attachment>>,
+    pending_uploads: HashMap<String, PendingUpload>,
+    next_upload_id: u64,
+    
+    // Decay walks users in principal order, as many per run as the instruction budget allows
+    decay_cursor: Option<Principal>,
+    
+    // Reminders walk users the same way, picking up where the previous tick ran out of budget
+    reminder_cursor: Option<Principal>,
+    
+    // Consolidation walks users in principal order, a batch per run
+    consolidation_cursor: Option<Principal>,
+    consolidation_started_at: u64, // 0 when no run is in progress
+    
+    // Background embedding walks users with unembedded memories the same way
+    embedding_cursor: Option<Principal>,
+    embedding_started_at: u64, // 0 when no run is in progress
 }
 
 // MemoryMind Core: Personal Knowledge Graph
@@ -36,6 +70,30 @@ struct PersonalKnowledgeGraph {
     learning_patterns: LearningHistory,
     context_threads: HashMap<String, ConversationContext>,
     last_updated: u64,
+    #[serde(default)]
+    next_node_seq: u64, // monotonic counter behind memory node ids
+    #[serde(default)]
+    tag_vocabulary: HashMap<String, u32>, // normalized tag -> number of nodes using it
+    #[serde(default)]
+    pending_confirmations: Vec<PendingConfirmation>, // contradictions waiting for the user to confirm
+    #[serde(default)]
+    entities: HashMap<String, EntityRecord>, // entity key -> registry entry
+    #[serde(default)]
+    last_sentiment_check_in: u64, // when the assistant last checked in about a mood shift
+    #[serde(default)]
+    pending_goal_update: Option<PendingGoalUpdate>,
+    #[serde(default)]
+    last_decay_at: u64, // when the decay timer last processed this graph
+    #[serde(default)]
+    notifications: Vec<Notification>, // reminder inbox, oldest first
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct PendingConfirmation {
+    fact_key: String,
+    current_node: String,
+    proposed_node: String,
+    asked_at: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, CandidType, Default)]
@@ -60,6 +118,30 @@ struct UserProfile {
     knowledge_domains: HashMap<String, f32>, // domain -> expertise level
     conversation_patterns: ConversationPatterns,
     response_preferences: ResponsePreferences,
+    #[serde(default)]
+    field_provenance: HashMap<String, FieldProvenance>, // profile field -> where its value came from
+    #[serde(default)]
+    domain_confidence: HashMap<String, f32>, // domain -> how settled its knowledge_domains level is
+    #[serde(default)]
+    style_evidence: StyleEvidence,
+    #[serde(default)]
+    next_item_seq: u64, // counter behind goal, task, event, notification and person ids
+    #[serde(default)]
+    reminder_settings: ReminderSettings,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType, PartialEq)]
+enum ProfileSource {
+    Explicit,  // set by the user through update_user_profile
+    Extracted, // learned from conversation
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct FieldProvenance {
+    source: ProfileSource,
+    confidence: f32,
+    memory_id: Option<String>, // memory the value was learned from
+    updated_at: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, CandidType)]
@@ -73,9 +155,19 @@ struct MemoryNode {
     access_count: u32,
     tags: Vec<String>,
     related_conversations: Vec<String>,
+    #[serde(default)]
+    embedding: Option<Vec<f32>>, // cached output of the embedding provider
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    fact_key: Option<String>, // what the fact is about, e.g. "employer" or "preference:coffee"
+    #[serde(default)]
+    superseded_by: Option<String>,
+    #[serde(default)]
+    version: u32,
 }
 
-#[derive(Serialize, Deserialize, Clone, CandidType)]
+#[derive(Serialize, Deserialize, Clone, CandidType, PartialEq)]
 enum MemoryNodeType {
     Fact,
     Preference,
@@ -84,6 +176,9 @@ enum MemoryNodeType {
     Experience,
     Knowledge,
     Context,
+    Document,
+    Summary,
+    Entity,
 }
 
 #[derive(Serialize, Deserialize, Clone, CandidType)]
@@ -95,7 +190,7 @@ struct KnowledgeEdge {
     created_at: u64,
 }
 
-#[derive(Serialize, Deserialize, Clone, CandidType)]
+#[derive(Serialize, Deserialize, Clone, CandidType, PartialEq, Debug)]
 enum RelationshipType {
     Related,
     CausedBy,
@@ -104,6 +199,8 @@ enum RelationshipType {
     OppositeOf,
     ExampleOf,
     UsedFor,
+    Supersedes,
+    Mentions,
 }
 
 #[derive(Serialize, Deserialize, Clone, CandidType, Default)]
@@ -111,8 +208,8 @@ struct LearningHistory {
     interaction_count: u32,
     topics_discussed: HashMap<String, u32>,
     preferred_response_length: ResponseLength,
-    question_asking_frequency: f32,
-    learning_speed: f32,
+    question_asking_frequency: f32, // share of turns that ask a question
+    learning_speed: f32, // new memories learned per turn, exponentially smoothed
     last_major_update: u64,
 }
 
@@ -146,6 +243,9 @@ struct EnhancedChatMessage {
     cycles_cost: Option<u64>,
     content_stored_on_chain: Option<bool>,
     ii_verified: Option<bool>,
+    
+    #[serde(default)]
+    attachment_ids: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, CandidType)]
@@ -198,9 +298,22 @@ struct CommunicationStyle {
     humor_preference: bool,
     technical_level: TechnicalLevel,
     emoji_usage: bool,
+    #[serde(default)]
+    locked_fields: Vec<String>, // fields the user fixed; inference leaves them alone
 }
 
-#[derive(Serialize, Deserialize, Clone, CandidType)]
+// Smoothed evidence from the user's messages behind the inferred CommunicationStyle
+#[derive(Serialize, Deserialize, Clone, CandidType, Default)]
+struct StyleEvidence {
+    messages: u32,
+    formality: f32, // -1 (casual) to 1 (formal)
+    emoji: f32,     // share of recent messages with emoji
+    humor: f32,     // share of recent messages with jokes or laughter
+    words_per_message: f32,
+    detail_feedback_at: u32, // message count when the user last asked for shorter or longer answers, 0 if never
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType, PartialEq, Debug)]
 enum FormalityLevel {
     VeryFormal,
     Formal,
@@ -214,7 +327,7 @@ impl Default for FormalityLevel {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, CandidType)]
+#[derive(Serialize, Deserialize, Clone, CandidType, PartialEq, Debug)]
 enum DetailLevel {
     Brief,
     Moderate,
@@ -228,7 +341,7 @@ impl Default for DetailLevel {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, CandidType)]
+#[derive(Serialize, Deserialize, Clone, CandidType, Debug)]
 enum TechnicalLevel {
     Beginner,
     Intermediate,
@@ -263,25 +376,102 @@ struct PersonalGoal {
     target_date: Option<u64>,
     progress: f32,
     importance: f32,
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    milestones: Vec<Milestone>,
+    #[serde(default)]
+    status: TaskStatus,
+    #[serde(default)]
+    created_at: u64,
+    #[serde(default)]
+    updated_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct Milestone {
+    description: String,
+    completed: bool,
+    completed_at: Option<u64>,
+}
+
+// Goal progress inferred from conversation, applied once the user confirms
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct PendingGoalUpdate {
+    goal_id: String,
+    milestone: Option<u32>, // index of the milestone to complete; None updates the goal itself
+    progress: f32,
+    evidence: String,
+    asked_at: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, CandidType)]
 struct ImportantEvent {
     event: String,
-    date: u64,
+    date: i64, // first occurrence (UTC midnight of the calendar date), negative before 1970
     importance: f32,
     category: String,
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    recurrence: EventRecurrence,
+    #[serde(default)]
+    lead_days: Option<Vec<u32>>, // overrides the user's ReminderSettings.lead_days
+    #[serde(default)]
+    reminded_until: u64, // latest reminder time already delivered
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType, PartialEq, Default)]
+enum EventRecurrence {
+    #[default]
+    Once,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct ReminderSettings {
+    enabled: bool,
+    lead_days: Vec<u32>, // days before an event to send a reminder; 0 is the day itself
+    utc_offset_minutes: i32,
+}
+
+impl Default for ReminderSettings {
+    fn default() -> Self {
+        ReminderSettings { enabled: true, lead_days: vec![7, 1], utc_offset_minutes: 0 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct Notification {
+    id: String,
+    event_id: String,
+    occurs_on: u64, // the occurrence the reminder is about
+    message: EnhancedChatMessage,
+    read: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, CandidType)]
 struct PersonalRelationship {
     name: String,
     relationship_type: String,
-    context: String,
+    context: String, // latest thing the user said about them
     importance: f32,
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    entity_key: Option<String>, // their entry in the entity registry, once the extractor has seen them
+    #[serde(default)]
+    memory_ids: Vec<String>, // memories stored from messages that mention them
+    #[serde(default)]
+    mention_count: u32,
+    #[serde(default)]
+    first_mentioned: u64,
+    #[serde(default)]
+    last_mentioned: u64,
 }
 
-#[derive(Serialize, Deserialize, Clone, CandidType)]
+#[derive(Serialize, Deserialize, Clone, CandidType, Default)]
 struct WorkContext {
     job_title: Option<String>,
     company: Option<String>,
@@ -292,10 +482,18 @@ struct WorkContext {
 
 #[derive(Serialize, Deserialize, Clone, CandidType, Default)]
 struct ConversationPatterns {
-    avg_session_length: f32,
+    avg_session_length: f32, // minutes
     common_topics: Vec<String>,
     question_types: HashMap<String, u32>,
-    time_patterns: Vec<u32>, // Hours when user is most active
+    time_patterns: Vec<u32>, // messages per hour of day (UTC), 24 entries
+    #[serde(default)]
+    session_count: u32,
+    #[serde(default)]
+    session_started_at: u64,
+    #[serde(default)]
+    last_active_at: u64,
+    #[serde(default)]
+    completed_session_nanos: u64, // total length of every session before the current one
 }
 
 #[derive(Serialize, Deserialize, Clone, CandidType, Default)]
@@ -312,11 +510,18 @@ struct Task {
     description: String,
     status: TaskStatus,
     created_at: u64,
-    due_date: Option<u64>,
+    due_date: Option<u64>, // start of the due day (UTC)
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    updated_at: u64,
+    #[serde(default)]
+    completed_at: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize, Clone, CandidType)]
+#[derive(Serialize, Deserialize, Clone, CandidType, PartialEq, Default)]
 enum TaskStatus {
+    #[default]
     Active,
     Completed,
     Paused,
@@ -330,7 +535,29 @@ struct Entity {
     context: String,
 }
 
+// Per-user registry entry; every entity also has a graph node that memories point at with Mentions edges
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct EntityRecord {
+    name: String,
+    entity_type: EntityType,
+    aliases: Vec<String>,
+    node_id: String,
+    mention_count: u32,
+    first_seen: u64,
+    last_seen: u64,
+    last_context: String,
+    thread_ids: Vec<String>,
+    message_timestamps: Vec<u64>, // timestamps of the stored messages that mentioned it
+}
+
 #[derive(Serialize, Deserialize, Clone, CandidType)]
+struct EntityDetail {
+    entity: EntityRecord,
+    memories: Vec<MemoryNode>,
+    conversations: Vec<EnhancedChatMessage>,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType, PartialEq, Debug)]
 enum EntityType {
     Person,
     Company,
@@ -395,6 +622,258 @@ struct UserDashboard {
     days_since_first_interaction: u64,
 }
 
+// Per-user retrieval indexes over memory nodes
+#[derive(Serialize, Deserialize, Clone, CandidType, Default)]
+struct MemoryIndex {
+    vectors: HashMap<String, Vec<f32>>, // node id -> unit-length embedding
+    postings: HashMap<String, HashMap<String, u32>>, // stemmed term -> node id -> term frequency
+    doc_lengths: HashMap<String, u32>, // node id -> indexed term count
+    doc_terms: HashMap<String, Vec<String>>, // node id -> distinct terms it has postings under
+    word_sets: HashMap<String, HashSet<String>>, // node id -> normalized words compared for duplicates
+    total_terms: u64,
+    adjacency: HashMap<String, Vec<AdjacentEdge>>, // node id -> edges touching it, in both directions
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct AdjacentEdge {
+    neighbor: String,
+    relationship_type: RelationshipType,
+    strength: f32,
+    outgoing: bool,
+    created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct GraphNeighbor {
+    node: MemoryNode,
+    depth: u32,
+    edge: KnowledgeEdge, // edge through which the node was first reached
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct Subgraph {
+    nodes: Vec<MemoryNode>,
+    edges: Vec<KnowledgeEdge>,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct NodeCentrality {
+    node: MemoryNode,
+    degree: u32,
+    weighted_degree: f32,
+    pagerank: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct ScoredMemory {
+    node: MemoryNode,
+    score: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct TopicCount {
+    topic: String,
+    count: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct LearningInsights {
+    interaction_count: u32,
+    top_topics: Vec<TopicCount>,
+    question_types: Vec<TopicCount>, // topic is the question type
+    question_asking_frequency: f32,
+    learning_speed: f32,
+    hourly_activity: Vec<u32>, // messages per hour of day (UTC)
+    peak_hours: Vec<u32>,
+    session_count: u32,
+    avg_session_minutes: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+enum TrendBucket {
+    Day,
+    Week, // starting Monday, UTC
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct TimeRange {
+    start: u64,
+    end: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType, Default)]
+struct SentimentCounts {
+    positive: u32,
+    neutral: u32,
+    negative: u32,
+    excited: u32,
+    frustrated: u32,
+    curious: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct SentimentBucket {
+    start: u64,
+    counts: SentimentCounts,
+    average_score: f32, // -1.0 (negative) to 1.0 (positive)
+    dominant: Sentiment,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct ThreadSentiment {
+    thread_id: String,
+    topic: String,
+    counts: SentimentCounts,
+    average_score: f32,
+    dominant: Sentiment,
+    last_message_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct SentimentShift {
+    bucket_start: u64,
+    previous_score: f32,
+    score: f32,
+    change: f32,
+    suggestion: String, // check-in the assistant can offer
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct SentimentTrends {
+    buckets: Vec<SentimentBucket>, // only buckets with messages, oldest first
+    threads: Vec<ThreadSentiment>, // most recently active first
+    shifts: Vec<SentimentShift>,
+}
+
+// Small images and documents users can attach to prompts
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct Attachment {
+    id: String,
+    file_name: String,
+    mime_type: String,
+    data: Vec<u8>,
+    uploaded_at: u64,
+    facts_extracted: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct AttachmentInfo {
+    id: String,
+    file_name: String,
+    mime_type: String,
+    size_bytes: u64,
+    uploaded_at: u64,
+    facts_extracted: bool,
+}
+
+// Chunked upload in progress; chunks must arrive in order
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct PendingUpload {
+    owner: Principal,
+    file_name: String,
+    mime_type: String,
+    total_size: u64,
+    data: Vec<u8>,
+    next_chunk: u32,
+    started_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct MemoryInput {
+    content: String,
+    node_type: MemoryNodeType,
+    tags: Vec<String>,
+    importance_score: Option<f32>,
+    pinned: Option<bool>,
+}
+
+// A memory that repeats an existing one is folded into it; `node` is then that existing memory
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct AddedMemory {
+    node: MemoryNode,
+    merged: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct MemoryUpdate {
+    content: Option<String>,
+    node_type: Option<MemoryNodeType>,
+    tags: Option<Vec<String>>,
+    importance_score: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct TagCount {
+    tag: String,
+    count: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct DocumentInfo {
+    id: String,
+    title: String,
+    passage_count: u32,
+    created_at: u64,
+}
+
+// Prompt templates: versioned per persona and managed by controllers
+#[derive(Serialize, Deserialize, Clone, CandidType, PartialEq, Eq, Hash, Debug)]
+enum PromptTemplateKind {
+    ContextualResponse,
+    InquiryFirst,
+    PartialAnswer,
+    LearningOpportunity,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct PromptTemplate {
+    persona: String,
+    kind: PromptTemplateKind,
+    version: u32,
+    body: String,
+    note: Option<String>,
+    created_at: u64,
+    created_by: Principal,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType, Default)]
+struct PromptTemplateStore {
+    templates: Vec<PromptTemplate>,
+    active_versions: HashMap<String, u32>, // "persona/kind" -> active version
+    default_persona: Option<String>,
+    user_personas: HashMap<Principal, String>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct PromptPreviewRequest {
+    persona: Option<String>,
+    kind: PromptTemplateKind,
+    body: Option<String>, // render an unsaved draft instead of the active version
+    question: String,
+    user: Option<Principal>,
+}
+
+#[derive(Serialize)]
+struct EmbedContentRequest {
+    model: String,
+    content: Content,
+}
+
+#[derive(Serialize)]
+struct BatchEmbedContentsRequest {
+    requests: Vec<EmbedContentRequest>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchEmbedContentsResponse {
+    embeddings: Vec<ContentEmbedding>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ContentEmbedding {
+    values: Vec<f32>,
+}
+
 // Gemini API structures (unchanged)
 #[derive(Serialize)]
 struct GeminiRequest {
@@ -408,7 +887,33 @@ struct Content {
 
 #[derive(Serialize)]
 struct Part {
-    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(rename = "inlineData", skip_serializing_if = "Option::is_none")]
+    inline_data: Option<InlineData>,
+}
+
+#[derive(Serialize)]
+struct InlineData {
+    #[serde(rename = "mimeType")]
+    mime_type: String,
+    data: String, // base64
+}
+
+impl Part {
+    fn text(text: String) -> Self {
+        Part { text: Some(text), inline_data: None }
+    }
+    
+    fn inline(attachment: &Attachment) -> Self {
+        Part {
+            text: None,
+            inline_data: Some(InlineData {
+                mime_type: attachment.mime_type.clone(),
+                data: BASE64.encode(&attachment.data),
+            }),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -435,6 +940,29 @@ thread_local! {
     static STATE: RefCell<State> = RefCell::new(State::default());
 }
 
+// Background jobs. Timers do not survive upgrades, so they are armed again after each one.
+
+const DECAY_INTERVAL_SECS: u64 = 24 * 60 * 60; // how often each user's memories decay
+const DECAY_TICK_SECS: u64 = 60 * 60;
+const DECAY_INSTRUCTION_BUDGET: u64 = 2_000_000_000;
+
+#[ic_cdk::init]
+fn init() {
+    start_timers();
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    start_timers();
+}
+
+fn start_timers() {
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(DECAY_TICK_SECS), decay_memories);
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(CONSOLIDATION_INTERVAL_SECS), || ic_cdk::spawn(run_consolidation()));
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(EMBEDDING_INTERVAL_SECS), || ic_cdk::spawn(run_embedding_backlog()));
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(REMINDER_INTERVAL_SECS), send_due_reminders);
+}
+
 // MemoryMind Core Functions
 
 #[ic_cdk::query]
@@ -463,42 +991,130 @@ async fn memory_mind_prompt(
     prompt_text: String,
     context_thread_id: Option<String>,
     _learn_from_response: Option<bool>,
+) -> Result<String, String> {
+    respond_to_prompt(ic_cdk::caller(), prompt_text, context_thread_id, Vec::new()).await
+}
+
+// Same as memory_mind_prompt, with previously uploaded attachments shown to the model
+#[ic_cdk::update]
+async fn memory_mind_prompt_with_attachments(
+    prompt_text: String,
+    context_thread_id: Option<String>,
+    attachment_ids: Vec<String>,
 ) -> Result<String, String> {
     let caller = ic_cdk::caller();
-    
+    let attachments = load_attachments(caller, &attachment_ids)?;
+    respond_to_prompt(caller, prompt_text, context_thread_id, attachments).await
+}
+
+async fn respond_to_prompt(
+    caller: Principal,
+    prompt_text: String,
+    context_thread_id: Option<String>,
+    attachments: Vec<Attachment>,
+) -> Result<String, String> {
     // Initialize user's knowledge graph if first time
     ensure_user_knowledge_graph(caller);
     
+    // A yes/no reply settles the contradiction we asked about last turn
+    resolve_pending_confirmation(caller, &prompt_text);
+    
+    let sentiment = analyze_sentiment(&prompt_text).await;
+    
+    // Embed the prompt for semantic retrieval; keyword matching is used when the provider is unavailable
+    // or there are no memory vectors to compare against
+    let query_embedding = if has_memory_vectors(caller) { embed_query(&prompt_text).await } else { None };
+    
     // Extract context and memories
-    let (user_context, relevant_memories) = get_user_context_and_memories(caller, &prompt_text, context_thread_id.clone());
+    let (user_context, relevant_memories) = get_user_context_and_memories(caller, &prompt_text, context_thread_id.clone(), query_embedding);
     
-    // Determine response strategy
-    let response_strategy = determine_response_strategy(caller, &prompt_text, &relevant_memories);
+    // Determine response strategy; the model has to see attachments, so those always get an answer,
+    // and an uncertain contradiction of something we know is confirmed before anything else
+    let response_strategy = if !attachments.is_empty() {
+        ResponseStrategy::ConfidentAnswer {
+            confidence: 0.7,
+            sources: relevant_memories.iter().map(|m| m.id.clone()).collect(),
+        }
+    } else if let Some(confirmation) = fact_confirmation_strategy(caller, &prompt_text) {
+        confirmation
+    } else if let Some(confirmation) = goal_progress_strategy(caller, &prompt_text) {
+        confirmation
+    } else {
+        // A frustrated user gets an answer rather than another question
+        match determine_response_strategy(caller, &prompt_text, &relevant_memories) {
+            ResponseStrategy::InquiryFirst { .. } | ResponseStrategy::LearningOpportunity { .. }
+                if matches!(sentiment, Sentiment::Frustrated) =>
+            {
+                ResponseStrategy::ConfidentAnswer {
+                    confidence: 0.6,
+                    sources: relevant_memories.iter().map(|m| m.id.clone()).collect(),
+                }
+            },
+            strategy => strategy,
+        }
+    };
     
-    match &response_strategy {
+    let response = match &response_strategy {
         ResponseStrategy::InquiryFirst { question, why_asking } => {
             // Ask for clarification instead of assuming
-            let response = format!("🤔 {}\n\n({})", question, why_asking);
-            save_conversation_with_learning(caller, prompt_text.clone(), response.clone(), context_thread_id, response_strategy).await;
-            Ok(response)
+            render_prompt(
+                caller,
+                PromptTemplateKind::InquiryFirst,
+                &prompt_text,
+                &user_context,
+                &relevant_memories,
+                &[("inquiry", question.clone()), ("why_asking", why_asking.clone())],
+            )
         },
         ResponseStrategy::PartialAnswer { known_info, clarification_needed } => {
-            let response = format!("Based on what I know about you: {}\n\n❓ {}", known_info, clarification_needed);
-            save_conversation_with_learning(caller, prompt_text.clone(), response.clone(), context_thread_id, response_strategy).await;
-            Ok(response)
+            render_prompt(
+                caller,
+                PromptTemplateKind::PartialAnswer,
+                &prompt_text,
+                &user_context,
+                &relevant_memories,
+                &[("known_info", known_info.clone()), ("clarification", clarification_needed.clone())],
+            )
         },
         ResponseStrategy::ConfidentAnswer { confidence: _, sources: _ } => {
-            // Generate AI response with full context
-            let ai_response = generate_contextual_ai_response(caller, prompt_text.clone(), user_context, relevant_memories).await?;
-            save_conversation_with_learning(caller, prompt_text.clone(), ai_response.clone(), context_thread_id, response_strategy).await;
-            Ok(ai_response)
+            // Generate AI response with full context, citing any documents it drew on
+            let cited_documents = document_titles_for(caller, &relevant_memories);
+            let mut ai_response = generate_contextual_ai_response(caller, prompt_text.clone(), user_context, relevant_memories.clone(), &attachments, &sentiment).await?;
+            if !cited_documents.is_empty() {
+                ai_response.push_str(&format!("\n\n📄 Sources: {}", cited_documents.join(", ")));
+            }
+            ai_response
         },
         ResponseStrategy::LearningOpportunity { suggestion } => {
-            let response = format!("💡 {}\n\nWould you like me to remember this for future conversations?", suggestion);
-            save_conversation_with_learning(caller, prompt_text.clone(), response.clone(), context_thread_id, response_strategy).await;
-            Ok(response)
+            render_prompt(
+                caller,
+                PromptTemplateKind::LearningOpportunity,
+                &prompt_text,
+                &user_context,
+                &relevant_memories,
+                &[("suggestion", suggestion.clone())],
+            )
         },
-    }
+    };
+    
+    let attachment_ids = attachments.iter().map(|a| a.id.clone()).collect();
+    let referenced_memories = relevant_memories.iter().map(|m| m.id.clone()).collect();
+    let stored_ids = save_conversation_with_learning(
+        caller,
+        prompt_text,
+        response.clone(),
+        context_thread_id,
+        response_strategy,
+        referenced_memories,
+        attachment_ids,
+        sentiment,
+    ).await;
+    suggest_tags_with_model(caller, &stored_ids).await;
+    
+    // Facts found in attachments become memories the first time each attachment is used
+    extract_facts_from_attachments(caller, &attachments).await;
+    
+    Ok(response)
 }
 
 fn ensure_user_knowledge_graph(user: Principal) {
@@ -512,13 +1128,27 @@ fn ensure_user_knowledge_graph(user: Principal) {
                 learning_patterns: LearningHistory::default(),
                 context_threads: HashMap::new(),
                 last_updated: ic_cdk::api::time(),
+                next_node_seq: 0,
+                tag_vocabulary: HashMap::new(),
+                pending_confirmations: Vec::new(),
+                entities: HashMap::new(),
+                last_sentiment_check_in: 0,
+                pending_goal_update: None,
+                last_decay_at: 0,
+                notifications: Vec::new(),
             };
             state.personal_knowledge_graphs.insert(user, new_graph);
         }
+        ensure_memory_index(&mut state, user);
     });
 }
 
-fn get_user_context_and_memories(user: Principal, prompt: &str, _context_thread_id: Option<String>) -> (String, Vec<MemoryNode>) {
+fn get_user_context_and_memories(
+    user: Principal,
+    prompt: &str,
+    _context_thread_id: Option<String>,
+    query_embedding: Option<Vec<f32>>,
+) -> (String, Vec<MemoryNode>) {
     STATE.with(|state| {
         let state = state.borrow();
         let graph = state.personal_knowledge_graphs.get(&user);
@@ -536,22 +1166,63 @@ fn get_user_context_and_memories(user: Principal, prompt: &str, _context_thread_
                     context.push_str(&format!("Interests: {}\n", kg.user_profile.interests.join(", ")));
                 }
                 
-                if !kg.user_profile.goals.is_empty() {
+                let active_goals: Vec<&PersonalGoal> = kg.user_profile.goals.iter()
+                    .filter(|goal| goal.status == TaskStatus::Active)
+                    .collect();
+                if !active_goals.is_empty() {
                     context.push_str("Current goals:\n");
-                    for goal in &kg.user_profile.goals {
+                    for goal in active_goals {
                         context.push_str(&format!("- {} ({}% complete)\n", goal.goal, (goal.progress * 100.0) as u32));
                     }
                 }
                 
-                // Find relevant memories based on keyword matching (simple implementation)
-                let relevant_memories: Vec<MemoryNode> = kg.memory_nodes.values()
-                    .filter(|node| {
-                        let prompt_lower = prompt.to_lowercase();
-                        node.content.to_lowercase().contains(&prompt_lower) ||
-                        node.tags.iter().any(|tag| prompt_lower.contains(&tag.to_lowercase()))
-                    })
-                    .take(5) // Limit to 5 most relevant
-                    .cloned()
+                let upcoming = upcoming_events(&kg.user_profile, ic_cdk::api::time(), UPCOMING_CONTEXT_DAYS);
+                if !upcoming.is_empty() {
+                    context.push_str("Upcoming dates:\n");
+                    for upcoming in upcoming {
+                        context.push_str(&format!("- {} ({})\n", upcoming.event.event, days_until_phrase(upcoming.days_until)));
+                    }
+                }
+                
+                let people = mentioned_people(&kg.user_profile.relationships, prompt);
+                if !people.is_empty() {
+                    context.push_str("People mentioned:\n");
+                    for person in people.into_iter().map(|i| &kg.user_profile.relationships[i]) {
+                        context.push_str(&format!("- {} (user's {}): {}\n", person.name, person.relationship_type, truncate_chars(&person.context, 200)));
+                    }
+                }
+                
+                // Semantic ranking first, then BM25 fills any remaining slots (or all of them offline)
+                let index = memory_index_for(&state, user);
+                let mut selected: Vec<String> = match &query_embedding {
+                    Some(query) => rank_memories_semantically(kg, Some(&index), query)
+                        .into_iter()
+                        .take(RETRIEVAL_LIMIT)
+                        .map(|(id, _)| id)
+                        .collect(),
+                    None => Vec::new(),
+                };
+                // Superseded versions stay searchable through search_memories but never reach the model
+                let mut lexical: Vec<(String, f32)> = bm25_search(&index, prompt).into_iter()
+                    .filter(|(id, _)| kg.memory_nodes.get(id).is_some_and(|node| node.superseded_by.is_none()))
+                    .collect();
+                for (id, score) in lexical.iter_mut() {
+                    if let Some(node) = kg.memory_nodes.get(id) {
+                        *score *= 0.5 + 0.5 * memory_strength(node);
+                    }
+                }
+                lexical.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+                for (id, _) in lexical {
+                    if selected.len() >= RETRIEVAL_LIMIT {
+                        break;
+                    }
+                    if !selected.contains(&id) {
+                        selected.push(id);
+                    }
+                }
+                
+                let relevant_memories = selected.iter()
+                    .filter_map(|id| kg.memory_nodes.get(id).cloned())
                     .collect();
                 
                 (context, relevant_memories)
@@ -561,328 +1232,6410 @@ fn get_user_context_and_memories(user: Principal, prompt: &str, _context_thread_
     })
 }
 
-fn determine_response_strategy(user: Principal, prompt: &str, relevant_memories: &[MemoryNode]) -> ResponseStrategy {
-    STATE.with(|state| {
-        let state = state.borrow();
-        let graph = state.personal_knowledge_graphs.get(&user);
-        
-        // Check if this is a new user with minimal information
-        if let Some(kg) = graph {
-            // For new users, still use AI but with a learning focus
-            if kg.user_profile.name.is_none() && kg.memory_nodes.is_empty() {
-                // Instead of hardcoded response, use AI with learning opportunity
-                return ResponseStrategy::ConfidentAnswer {
-                    confidence: 0.7,
-                    sources: Vec::new(),
-                };
-            }
-            
-            // Check if prompt is asking about something we don't know
-            if prompt.to_lowercase().contains("my") || prompt.to_lowercase().contains("i ") {
-                if relevant_memories.is_empty() && !contains_personal_context(prompt) {
-                    return ResponseStrategy::InquiryFirst {
-                        question: format!("I want to give you the most helpful response. Could you provide a bit more context about {}?", extract_topic_from_prompt(prompt)),
-                        why_asking: "This helps me understand your specific situation and give you better advice".to_string(),
-                    };
-                }
-            }
-            
-            // If we have some relevant memories, we can give a confident answer
-            if !relevant_memories.is_empty() {
-                return ResponseStrategy::ConfidentAnswer {
-                    confidence: 0.8,
-                    sources: relevant_memories.iter().map(|m| m.id.clone()).collect(),
-                };
+// Semantic retrieval
+
+const RETRIEVAL_LIMIT: usize = 5;
+const MIN_SEMANTIC_SIMILARITY: f32 = 0.35;
+const EMBEDDING_BATCH_SIZE: usize = 16;
+const EMBEDDING_INTERVAL_SECS: u64 = 5 * 60;
+const EMBEDDING_USERS_PER_RUN: usize = 5; // one outcall each
+const EMBEDDING_MODEL: &str = "models/text-embedding-004";
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+fn normalize_vector(values: &[f32]) -> Option<Vec<f32>> {
+    let norm = values.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > f32::EPSILON {
+        Some(values.iter().map(|v| v / norm).collect())
+    } else {
+        None
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// Top-k cosine search blended with memory strength so stale, low-value memories lose ties
+fn rank_memories_semantically(
+    kg: &PersonalKnowledgeGraph,
+    index: Option<&MemoryIndex>,
+    query: &[f32],
+) -> Vec<(String, f32)> {
+    let query = match normalize_vector(query) {
+        Some(query) => query,
+        None => return Vec::new(),
+    };
+    
+    let mut scored: Vec<(String, f32)> = kg.memory_nodes.values()
+        .filter(|node| node.superseded_by.is_none())
+        .filter_map(|node| {
+            let similarity = match index.and_then(|index| index.vectors.get(&node.id)) {
+                Some(vector) => dot(&query, vector),
+                None => dot(&query, &normalize_vector(node.embedding.as_ref()?)?),
+            };
+            if similarity < MIN_SEMANTIC_SIMILARITY {
+                return None;
             }
-            
-            // If it's a learning opportunity
-            if is_learning_opportunity(prompt) {
-                return ResponseStrategy::LearningOpportunity {
-                    suggestion: "I can help you with this and remember your preferences for next time".to_string(),
-                };
+            let score = similarity * 0.7 + memory_strength(node) * 0.3;
+            Some((node.id.clone(), score))
+        })
+        .collect();
+    
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    scored
+}
+
+// Memory strength: importance that fades while a memory goes unused and recovers whenever it is used
+
+const BASE_STABILITY_DAYS: f32 = 7.0;
+const REINFORCEMENT_BOOST: f32 = 0.1;
+const IMPORTANCE_DECAY_PER_DAY: f32 = 0.01;
+const MIN_IMPORTANCE: f32 = 0.05;
+const MAX_DECAY_CATCH_UP_DAYS: f32 = 30.0;
+
+// How many days a memory stays well remembered; each recall makes it last longer
+fn memory_stability_days(node: &MemoryNode) -> f32 {
+    BASE_STABILITY_DAYS * (1.0 + (node.access_count.max(1) as f32).ln())
+}
+
+// Retrieval weight of a memory. Forgetting is applied to importance_score by the decay timer and
+// undone by reinforcement, so the stored importance is used as is rather than decayed a second time.
+fn memory_strength(node: &MemoryNode) -> f32 {
+    node.importance_score.clamp(0.0, 1.0)
+}
+
+fn reinforce_memories(state: &mut State, user: Principal, node_ids: &[String]) {
+    let now = ic_cdk::api::time();
+    if let Some(kg) = state.personal_knowledge_graphs.get_mut(&user) {
+        for node_id in node_ids {
+            if let Some(node) = kg.memory_nodes.get_mut(node_id) {
+                node.access_count = node.access_count.saturating_add(1);
+                node.last_accessed = now;
+                node.importance_score = (node.importance_score + REINFORCEMENT_BOOST * (1.0 - node.importance_score)).clamp(0.0, 1.0);
             }
         }
+    }
+}
+
+// Periodic job: lowers the stored importance of memories that have not been used since the user's
+// last decay. Frequently recalled memories decay more slowly; pinned memories are left alone.
+// Users are due once a day; each tick handles as many as fit in the instruction budget, resuming
+// after the last user handled.
+fn decay_memories() {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let now = ic_cdk::api::time();
+        let interval = DECAY_INTERVAL_SECS * 1_000_000_000;
+        let mut due: Vec<Principal> = state.personal_knowledge_graphs.iter()
+            .filter(|(_, kg)| now.saturating_sub(kg.last_decay_at) >= interval)
+            .map(|(user, _)| *user)
+            .collect();
+        due.sort();
+        let start = state.decay_cursor
+            .map(|cursor| due.iter().position(|user| *user > cursor).unwrap_or(0))
+            .unwrap_or(0);
         
-        // Default to confident answer for general questions
-        ResponseStrategy::ConfidentAnswer {
-            confidence: 0.6,
-            sources: Vec::new(),
+        for user in due.iter().cycle().skip(start).take(due.len()) {
+            if ic_cdk::api::instruction_counter() > DECAY_INSTRUCTION_BUDGET {
+                break;
+            }
+            state.decay_cursor = Some(*user);
+            let kg = match state.personal_knowledge_graphs.get_mut(user) {
+                Some(kg) => kg,
+                None => continue,
+            };
+            let last_run = match kg.last_decay_at {
+                0 => now.saturating_sub(interval),
+                last_run => last_run,
+            };
+            let elapsed_days = (now.saturating_sub(last_run) as f32 / NANOS_PER_DAY as f32).min(MAX_DECAY_CATCH_UP_DAYS);
+            kg.last_decay_at = now;
+            for node in kg.memory_nodes.values_mut() {
+                if node.pinned || node.last_accessed > last_run || node.importance_score <= MIN_IMPORTANCE {
+                    continue;
+                }
+                let rate = IMPORTANCE_DECAY_PER_DAY * BASE_STABILITY_DAYS / memory_stability_days(node);
+                node.importance_score = (node.importance_score * (-rate * elapsed_days).exp()).max(MIN_IMPORTANCE);
+            }
         }
-    })
+    });
 }
 
-fn contains_personal_context(prompt: &str) -> bool {
-    let personal_indicators = ["my name is", "i am", "i work", "i like", "i prefer", "i usually"];
-    let prompt_lower = prompt.to_lowercase();
-    personal_indicators.iter().any(|indicator| prompt_lower.contains(indicator))
-}
+// Periodic consolidation: recent experiences and facts are clustered and rolled up into summary nodes
 
-fn extract_topic_from_prompt(prompt: &str) -> String {
-    // Simple topic extraction - in a real implementation, this would be more sophisticated
-    if prompt.len() > 50 {
-        format!("{}...", &prompt[..50])
-    } else {
-        prompt.to_string()
+const CONSOLIDATION_INTERVAL_SECS: u64 = 6 * 60 * 60;
+const CONSOLIDATION_USERS_PER_RUN: usize = 20;
+const CONSOLIDATION_WINDOW_NANOS: u64 = 30 * NANOS_PER_DAY;
+const CONSOLIDATION_INSTRUCTION_BUDGET: u64 = 2_000_000_000; // per message, well under the execution limit
+const CONSOLIDATION_MIN_CYCLES: u64 = 500_000_000_000; // below this reserve summaries are written without the model
+const MAX_CONSOLIDATION_OUTCALLS: usize = 5;
+const MAX_CONSOLIDATION_CANDIDATES: usize = 100;
+const MIN_CLUSTER_SIZE: usize = 3;
+const MAX_CLUSTER_SIZE: usize = 12;
+const CLUSTER_TAG_OVERLAP: f32 = 0.3;
+const CLUSTER_EMBEDDING_SIMILARITY: f32 = 0.8;
+
+// Groups recent Experience/Fact nodes that are not yet part of a summary. Two nodes belong together when
+// they share enough tags, have close embeddings, or are already linked; clusters are connected components.
+fn cluster_recent_memories(kg: &PersonalKnowledgeGraph, index: &MemoryIndex, now: u64) -> Vec<Vec<String>> {
+    let already_summarized = |id: &str| index.adjacency.get(id).into_iter().flatten().any(|a| {
+        a.outgoing && a.relationship_type == RelationshipType::PartOf
+    });
+    let mut candidates: Vec<&MemoryNode> = kg.memory_nodes.values()
+        .filter(|node| matches!(node.node_type, MemoryNodeType::Experience | MemoryNodeType::Fact))
+        .filter(|node| node.superseded_by.is_none() && now.saturating_sub(node.created_at) <= CONSOLIDATION_WINDOW_NANOS)
+        .filter(|node| !already_summarized(&node.id))
+        .collect();
+    candidates.sort_by_key(|node| std::cmp::Reverse(node.created_at));
+    candidates.truncate(MAX_CONSOLIDATION_CANDIDATES);
+    
+    let linked = |a: &MemoryNode, b: &MemoryNode| {
+        tag_overlap(&a.tags, &b.tags) >= CLUSTER_TAG_OVERLAP
+            || matches!((index.vectors.get(&a.id), index.vectors.get(&b.id)), (Some(x), Some(y)) if dot(x, y) >= CLUSTER_EMBEDDING_SIMILARITY)
+            || index.adjacency.get(&a.id).into_iter().flatten().any(|edge| edge.neighbor == b.id)
+    };
+    
+    let mut assigned = vec![false; candidates.len()];
+    let mut clusters = Vec::new();
+    for start in 0..candidates.len() {
+        if assigned[start] {
+            continue;
+        }
+        assigned[start] = true;
+        let mut members = vec![start];
+        let mut next = 0;
+        while next < members.len() {
+            let current = members[next];
+            next += 1;
+            for other in 0..candidates.len() {
+                if !assigned[other] && linked(candidates[current], candidates[other]) {
+                    assigned[other] = true;
+                    members.push(other);
+                }
+            }
+        }
+        if members.len() >= MIN_CLUSTER_SIZE {
+            let mut members: Vec<&MemoryNode> = members.into_iter().map(|i| candidates[i]).collect();
+            members.sort_by(|a, b| b.importance_score.partial_cmp(&a.importance_score).unwrap_or(std::cmp::Ordering::Equal));
+            members.truncate(MAX_CLUSTER_SIZE);
+            clusters.push(members.into_iter().map(|node| node.id.clone()).collect());
+        }
     }
+    clusters
 }
 
-fn is_learning_opportunity(_prompt: &str) -> bool {
-    // Determine if this is a good opportunity to learn something new about the user
-    true // Simplified for now
+// Fallback when the model is unavailable or the cycles reserve is low
+fn extractive_summary(members: &[MemoryNode], tags: &[String]) -> String {
+    let about = if tags.is_empty() { String::new() } else { format!(" about {}", tags.join(", ")) };
+    let points: Vec<String> = members.iter().map(|node| truncate_chars(&node.content, 120)).collect();
+    format!("Summary of {} related memories{}: {}", members.len(), about, points.join("; "))
 }
 
-async fn generate_contextual_ai_response(
-    _user: Principal,
-    prompt: String,
-    user_context: String,
-    relevant_memories: Vec<MemoryNode>,
-) -> Result<String, String> {
-    let api_key = STATE.with(|state| {
-        let state = state.borrow();
-        state.api_key.clone()
-    });
-    
-    if api_key.is_empty() {
-        return Err("API key is not set".to_string());
+fn cluster_tags(members: &[MemoryNode]) -> Vec<String> {
+    let mut counts: HashMap<&String, usize> = HashMap::new();
+    for tag in members.iter().flat_map(|node| &node.tags) {
+        *counts.entry(tag).or_insert(0) += 1;
     }
+    let mut tags: Vec<(&String, usize)> = counts.into_iter().filter(|(_, count)| *count >= 2).collect();
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    tags.into_iter().take(3).map(|(tag, _)| tag.clone()).collect()
+}
+
+// Consolidates one user's recent memories; returns (summaries written, model calls made)
+async fn consolidate_user(user: Principal, outcall_budget: usize) -> (u32, usize) {
+    let (clusters, api_key) = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if !state.personal_knowledge_graphs.contains_key(&user) {
+            return (Vec::new(), String::new());
+        }
+        ensure_memory_index(&mut state, user);
+        let kg = &state.personal_knowledge_graphs[&user];
+        let clusters: Vec<Vec<MemoryNode>> = cluster_recent_memories(kg, &state.memory_indexes[&user], ic_cdk::api::time())
+            .into_iter()
+            .map(|ids| ids.iter().filter_map(|id| kg.memory_nodes.get(id).cloned()).collect())
+            .collect();
+        (clusters, state.api_key.clone())
+    });
     
-    // Build enhanced prompt with user context and memories
-    let mut enhanced_prompt = String::new();
-    
-    enhanced_prompt.push_str("You are MemoryMind, a personal AI assistant that learns and remembers everything about the user. ");
-    enhanced_prompt.push_str("Use the following context to provide a personalized response:\n\n");
-    
-    if !user_context.trim().is_empty() {
-        enhanced_prompt.push_str(&format!("USER CONTEXT:\n{}\n", user_context));
+    let mut written = 0;
+    let mut outcalls = 0;
+    for members in clusters {
+        let tags = cluster_tags(&members);
+        let use_model = !api_key.is_empty() && outcalls < outcall_budget && ic_cdk::api::canister_balance() >= CONSOLIDATION_MIN_CYCLES;
+        let content = if use_model {
+            outcalls += 1;
+            let mut prompt = String::from(
+                "Summarize what these related notes say about the user in one or two sentences, \
+                 written in the third person. Answer with the summary only.\n\n",
+            );
+            for node in &members {
+                prompt.push_str(&format!("- {}\n", truncate_chars(&node.content, 300)));
+            }
+            match call_gemini_api(prompt, api_key.clone()).await {
+                Ok(summary) if !summary.trim().is_empty() => truncate_chars(summary.trim(), 600),
+                Ok(_) => extractive_summary(&members, &tags),
+                Err(e) => {
+                    ic_cdk::println!("Consolidation summary failed, using extractive summary: {}", e);
+                    extractive_summary(&members, &tags)
+                }
+            }
+        } else {
+            extractive_summary(&members, &tags)
+        };
+        
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            // Members may have been merged or deleted while the model was answering
+            let member_ids: Vec<String> = members.iter()
+                .filter(|node| state.personal_knowledge_graphs.get(&user).is_some_and(|kg| kg.memory_nodes.contains_key(&node.id)))
+                .map(|node| node.id.clone())
+                .collect();
+            if member_ids.len() < MIN_CLUSTER_SIZE {
+                return;
+            }
+            let importance = members.iter().map(|node| node.importance_score).fold(0.0, f32::max);
+            let id = allocate_memory_id(&mut state, user);
+            let mut summary = MemoryNode::new(id.clone(), content, MemoryNodeType::Summary, importance);
+            summary.tags = tags;
+            insert_memory_node(&mut state, user, summary);
+            for member_id in member_ids {
+                add_knowledge_edge(&mut state, user, KnowledgeEdge {
+                    from_node: member_id,
+                    to_node: id.clone(),
+                    relationship_type: RelationshipType::PartOf,
+                    strength: 1.0,
+                    created_at: ic_cdk::api::time(),
+                });
+            }
+            written += 1;
+        });
     }
     
-    if !relevant_memories.is_empty() {
-        enhanced_prompt.push_str("RELEVANT MEMORIES:\n");
-        for memory in &relevant_memories {
-            enhanced_prompt.push_str(&format!("- {} ({})\n", memory.content, memory.node_type.to_string()));
+    STATE.with(|state| {
+        if let Some(kg) = state.borrow_mut().personal_knowledge_graphs.get_mut(&user) {
+            kg.learning_patterns.last_major_update = ic_cdk::api::time();
         }
-        enhanced_prompt.push_str("\n");
-    }
-    
-    enhanced_prompt.push_str(&format!("USER QUESTION: {}\n\n", prompt));
-    enhanced_prompt.push_str("Provide a helpful, personalized response that references relevant context and memories when appropriate. ");
-    enhanced_prompt.push_str("Be conversational and show that you remember previous interactions.");
-    
-    call_gemini_api(enhanced_prompt, api_key).await
+    });
+    (written, outcalls)
 }
 
-async fn save_conversation_with_learning(
-    user: Principal,
-    user_message: String,
-    ai_response: String,
-    context_thread_id: Option<String>,
-    response_strategy: ResponseStrategy,
-) {
-    STATE.with(|state| {
+// Timer job: consolidates the next batch of users whose graphs changed since their last consolidation
+async fn run_consolidation() {
+    let users = STATE.with(|state| {
         let mut state = state.borrow_mut();
-        
-        // Extract facts from user message
-        let extracted_facts = extract_facts_from_message(&user_message);
-        
-        // Update knowledge graph
-        if let Some(kg) = state.personal_knowledge_graphs.get_mut(&user) {
-            // Learn from extracted facts
-            for fact in &extracted_facts {
-                if fact.should_remember {
-                    let memory_node = MemoryNode {
-                        id: format!("memory_{}_{}", user.to_text(), ic_cdk::api::time()),
-                        content: fact.fact.clone(),
-                        node_type: match fact.fact_type {
-                            FactType::PersonalInfo => MemoryNodeType::Fact,
-                            FactType::Preference => MemoryNodeType::Preference,
-                            FactType::Goal => MemoryNodeType::Goal,
-                            FactType::Relationship => MemoryNodeType::Relationship,
-                            FactType::Experience => MemoryNodeType::Experience,
-                            FactType::Knowledge => MemoryNodeType::Knowledge,
-                        },
-                        importance_score: fact.confidence,
-                        created_at: ic_cdk::api::time(),
-                        last_accessed: ic_cdk::api::time(),
-                        access_count: 1,
-                        tags: Vec::new(),
-                        related_conversations: Vec::new(),
-                    };
-                    kg.memory_nodes.insert(memory_node.id.clone(), memory_node);
-                }
-            }
-            
-            // Update learning patterns
-            kg.learning_patterns.interaction_count += 1;
-            kg.last_updated = ic_cdk::api::time();
+        // A run that trapped part-way never cleared its marker, so an old one is ignored
+        let now = ic_cdk::api::time();
+        if state.consolidation_started_at != 0
+            && now.saturating_sub(state.consolidation_started_at) < CONSOLIDATION_INTERVAL_SECS * 1_000_000_000
+        {
+            return Vec::new();
         }
         
-        // Save conversation
-        let enhanced_message = EnhancedChatMessage {
-            role: "assistant".to_string(),
-            content: ai_response,
-            timestamp: ic_cdk::api::time(),
-            provider: "gemini".to_string(),
-            context_thread_id,
-            extracted_facts,
-            referenced_memories: Vec::new(),
-            learned_preferences: Vec::new(),
-            user_sentiment: Some(Sentiment::Neutral),
-            response_strategy: Some(response_strategy),
-            cycles_cost: Some(0),
-            content_stored_on_chain: Some(false),
-            ii_verified: Some(true),
-        };
-        
-        let conversation = state.conversations.entry(user).or_insert_with(Vec::new);
-        conversation.push(enhanced_message);
+        let mut active: Vec<Principal> = state.personal_knowledge_graphs.iter()
+            .filter(|(_, kg)| kg.last_updated > kg.learning_patterns.last_major_update)
+            .map(|(user, _)| *user)
+            .collect();
+        active.sort();
+        // Resume after the last user handled, wrapping around
+        let start = state.consolidation_cursor
+            .map(|cursor| active.iter().position(|user| *user > cursor).unwrap_or(0))
+            .unwrap_or(0);
+        let batch: Vec<Principal> = active.iter().cycle().skip(start).take(active.len().min(CONSOLIDATION_USERS_PER_RUN)).copied().collect();
         
-        // Update metrics
-        state.canister_metrics.total_queries += 1;
-        state.canister_metrics.learning_events += 1;
+        state.consolidation_cursor = batch.last().copied().or(state.consolidation_cursor);
+        if !batch.is_empty() {
+            state.consolidation_started_at = now;
+        }
+        batch
     });
+    
+    let mut outcalls = 0;
+    let mut summaries = 0;
+    for user in users {
+        if ic_cdk::api::instruction_counter() > CONSOLIDATION_INSTRUCTION_BUDGET {
+            break;
+        }
+        let (written, used) = consolidate_user(user, MAX_CONSOLIDATION_OUTCALLS.saturating_sub(outcalls)).await;
+        summaries += written;
+        outcalls += used;
+    }
+    
+    STATE.with(|state| state.borrow_mut().consolidation_started_at = 0);
+    if summaries > 0 {
+        ic_cdk::println!("Consolidation wrote {} summaries using {} model calls", summaries, outcalls);
+    }
 }
 
-fn extract_facts_from_message(message: &str) -> Vec<ExtractedFact> {
-    let mut facts = Vec::new();
-    let message_lower = message.to_lowercase();
+// Runs consolidation for one user right away instead of waiting for the timer
+#[ic_cdk::update]
+async fn consolidate_memories(user: Principal) -> Result<u32, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    if STATE.with(|state| !state.borrow().personal_knowledge_graphs.contains_key(&user)) {
+        return Err("User knowledge graph not found".to_string());
+    }
+    let (written, _) = consolidate_user(user, MAX_CONSOLIDATION_OUTCALLS).await;
+    Ok(written)
+}
+
+fn index_memory_embedding(state: &mut State, user: Principal, node_id: &str, embedding: Vec<f32>) {
+    let normalized = normalize_vector(&embedding);
+    if let Some(node) = state.personal_knowledge_graphs.get_mut(&user).and_then(|kg| kg.memory_nodes.get_mut(node_id)) {
+        node.embedding = Some(embedding);
+    } else {
+        return;
+    }
     
-    // Simple fact extraction based on patterns
-    if message_lower.contains("my name is") {
-        if let Some(name) = extract_name_from_message(message) {
-            facts.push(ExtractedFact {
-                fact: format!("User's name is {}", name),
-                confidence: 0.9,
-                fact_type: FactType::PersonalInfo,
-                should_remember: true,
-            });
+    let index = ensure_memory_index(state, user);
+    match normalized {
+        Some(vector) => { index.vectors.insert(node_id.to_string(), vector); },
+        None => { index.vectors.remove(node_id); },
+    }
+}
+
+fn build_memory_index(kg: &PersonalKnowledgeGraph) -> MemoryIndex {
+    let mut index = MemoryIndex::default();
+    for node in kg.memory_nodes.values() {
+        if let Some(vector) = node.embedding.as_deref().and_then(normalize_vector) {
+            index.vectors.insert(node.id.clone(), vector);
         }
+        index_node_terms(&mut index, node);
+    }
+    for edge in &kg.relationships {
+        index_edge(&mut index, edge);
+    }
+    index
+}
+
+// Returns the user's retrieval index, building it from the stored nodes if it does not exist yet.
+// Update calls only: state changes made in a query are discarded, so the build would be repeated.
+fn ensure_memory_index(state: &mut State, user: Principal) -> &mut MemoryIndex {
+    if !state.memory_indexes.contains_key(&user) {
+        let index = state.personal_knowledge_graphs.get(&user).map(build_memory_index).unwrap_or_default();
+        state.memory_indexes.insert(user, index);
+    }
+    state.memory_indexes.get_mut(&user).expect("index was just inserted")
+}
+
+// Read-only access for queries: the stored index, or a throwaway build when no update call has
+// stored one yet
+fn memory_index_for(state: &State, user: Principal) -> Cow<'_, MemoryIndex> {
+    match (state.memory_indexes.get(&user), state.personal_knowledge_graphs.get(&user)) {
+        (Some(index), _) => Cow::Borrowed(index),
+        (None, Some(kg)) => Cow::Owned(build_memory_index(kg)),
+        (None, None) => Cow::Owned(MemoryIndex::default()),
     }
+}
+
+// All node writes go through these two helpers so the retrieval indexes and tag vocabulary stay in sync.
+// New nodes are tagged automatically; returns the node as stored.
+fn insert_memory_node(state: &mut State, user: Principal, mut node: MemoryNode) -> Option<MemoryNode> {
+    let kg = state.personal_knowledge_graphs.get_mut(&user)?;
     
-    if message_lower.contains("i work") || message_lower.contains("i'm a") {
-        facts.push(ExtractedFact {
-            fact: message.to_string(),
-            confidence: 0.7,
-            fact_type: FactType::PersonalInfo,
-            should_remember: true,
-        });
+    let is_new = !kg.memory_nodes.contains_key(&node.id);
+    let previous_tags = match kg.memory_nodes.get(&node.id) {
+        Some(existing) => existing.tags.clone(),
+        None => {
+            node.tags.extend(generate_tags(&node.content));
+            Vec::new()
+        }
+    };
+    node.tags = canonicalize_tags(&kg.tag_vocabulary, &node.tags);
+    node.tags.truncate(MAX_MEMORY_TAGS); // generated tags only fill the room the caller's tags leave
+    update_tag_vocabulary(&mut kg.tag_vocabulary, &previous_tags, &node.tags);
+    if !matches!(node.node_type, MemoryNodeType::Document | MemoryNodeType::Entity) {
+        node.fact_key = fact_key_for(&node.content).map(|(key, _)| key);
     }
     
-    if message_lower.contains("i like") || message_lower.contains("i prefer") {
-        facts.push(ExtractedFact {
-            fact: message.to_string(),
-            confidence: 0.8,
-            fact_type: FactType::Preference,
-            should_remember: true,
-        });
+    let index = ensure_memory_index(state, user);
+    unindex_node(index, &node.id);
+    index_node_terms(index, &node);
+    if let Some(vector) = node.embedding.as_deref().and_then(normalize_vector) {
+        index.vectors.insert(node.id.clone(), vector);
     }
     
-    if message_lower.contains("my goal") || message_lower.contains("i want to") {
-        facts.push(ExtractedFact {
-            fact: message.to_string(),
-            confidence: 0.8,
-            fact_type: FactType::Goal,
-            should_remember: true,
-        });
+    let kg = state.personal_knowledge_graphs.get_mut(&user)?;
+    // Link to existing memories before inserting so the node is not compared with itself
+    let inferred_edges = if is_new { infer_relationships(kg, &node) } else { Vec::new() };
+    kg.memory_nodes.insert(node.id.clone(), node.clone());
+    kg.last_updated = ic_cdk::api::time();
+    
+    if is_new {
+        state.canister_metrics.knowledge_nodes_created += 1;
+    }
+    for edge in inferred_edges {
+        add_knowledge_edge(state, user, edge);
     }
+    Some(node)
+}
+
+// Removes a node together with every edge that references it
+fn remove_memory_node(state: &mut State, user: Principal, node_id: &str) -> Option<MemoryNode> {
+    let kg = state.personal_knowledge_graphs.get_mut(&user)?;
+    let removed = kg.memory_nodes.remove(node_id)?;
+    kg.relationships.retain(|edge| edge.from_node != node_id && edge.to_node != node_id);
+    kg.entities.retain(|_, entity| entity.node_id != node_id);
+    update_tag_vocabulary(&mut kg.tag_vocabulary, &removed.tags, &[]);
+    kg.last_updated = ic_cdk::api::time();
     
-    facts
+    if let Some(index) = state.memory_indexes.get_mut(&user) {
+        unindex_node(index, node_id);
+        unindex_node_edges(index, node_id);
+    }
+    Some(removed)
 }
 
-fn extract_name_from_message(message: &str) -> Option<String> {
-    // Simple name extraction - in production, this would be more sophisticated
-    if let Some(pos) = message.to_lowercase().find("my name is") {
-        let after_phrase = &message[pos + 11..];
-        let name = after_phrase.split_whitespace().next()?;
-        Some(name.trim_end_matches(&['.', ',', '!', '?'][..]).to_string())
-    } else {
-        None
+// Per-user monotonic ids; time-based ids collided when several facts were stored in one call
+fn allocate_memory_id(state: &mut State, user: Principal) -> String {
+    let kg = state.personal_knowledge_graphs.get_mut(&user).expect("knowledge graph must exist before allocating ids");
+    loop {
+        kg.next_node_seq += 1;
+        let id = format!("memory_{}_{}", user.to_text(), kg.next_node_seq);
+        if !kg.memory_nodes.contains_key(&id) {
+            return id;
+        }
     }
 }
 
-// Backward compatibility functions
-#[ic_cdk::update]
-async fn prompt(prompt_text: String) -> Result<String, String> {
-    memory_mind_prompt(prompt_text, None, Some(true)).await
+// Duplicate detection and merging
+
+const DUPLICATE_TEXT_SIMILARITY: f32 = 0.8;
+const DUPLICATE_EMBEDDING_SIMILARITY: f32 = 0.95;
+
+fn normalized_word_set(text: &str) -> HashSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(stem)
+        .collect()
+}
+
+// Jaccard similarity over normalized, stemmed words (stop words kept, so "I like X" and "I hate X" differ)
+fn text_similarity(a: &str, b: &str) -> f32 {
+    word_set_similarity(&normalized_word_set(a), &normalized_word_set(b))
+}
+
+fn word_set_similarity(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    a.intersection(b).count() as f32 / a.union(b).count() as f32
+}
+
+// Document passages keep their own identity even when their text repeats elsewhere
+fn is_document_passage(state: &State, user: Principal, node_id: &str) -> bool {
+    let kg = match state.personal_knowledge_graphs.get(&user) {
+        Some(kg) => kg,
+        None => return false,
+    };
+    let is_document = |id: &str| kg.memory_nodes.get(id).is_some_and(|node| node.node_type == MemoryNodeType::Document);
+    match state.memory_indexes.get(&user) {
+        Some(index) => index.adjacency.get(node_id).into_iter().flatten()
+            .any(|a| a.outgoing && a.relationship_type == RelationshipType::PartOf && is_document(&a.neighbor)),
+        None => kg.relationships.iter()
+            .any(|edge| edge.from_node == node_id && edge.relationship_type == RelationshipType::PartOf && is_document(&edge.to_node)),
+    }
+}
+
+fn can_merge(state: &State, user: Principal, a: &MemoryNode, b: &MemoryNode) -> bool {
+    a.id != b.id
+        && a.superseded_by.is_none()
+        && b.superseded_by.is_none()
+        && a.node_type == b.node_type
+        && !matches!(a.node_type, MemoryNodeType::Document | MemoryNodeType::Entity)
+        && !is_document_passage(state, user, &a.id)
+        && !is_document_passage(state, user, &b.id)
+}
+
+// Existing memory whose text is near-identical to the candidate, preferring the closest match. Only
+// memories sharing an indexed term can reach the threshold, and their word sets are cached in the index.
+fn find_text_duplicate(state: &State, user: Principal, node: &MemoryNode) -> Option<String> {
+    let kg = state.personal_knowledge_graphs.get(&user)?;
+    let index = state.memory_indexes.get(&user)?;
+    let words = normalized_word_set(&node.content);
+    let candidates: HashSet<&String> = tokenize(&node.content).iter()
+        .filter_map(|term| index.postings.get(term))
+        .flat_map(|docs| docs.keys())
+        .collect();
+    candidates.into_iter()
+        .filter_map(|id| Some((kg.memory_nodes.get(id)?, index.word_sets.get(id)?)))
+        .filter(|(existing, _)| can_merge(state, user, existing, node))
+        .map(|(existing, existing_words)| (existing.id.clone(), word_set_similarity(existing_words, &words)))
+        .filter(|(_, similarity)| *similarity >= DUPLICATE_TEXT_SIMILARITY)
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(id, _)| id)
+}
+
+fn find_embedding_duplicate(state: &State, user: Principal, node_id: &str) -> Option<String> {
+    let kg = state.personal_knowledge_graphs.get(&user)?;
+    let index = state.memory_indexes.get(&user)?;
+    let node = kg.memory_nodes.get(node_id)?;
+    let vector = index.vectors.get(node_id)?;
+    index.vectors.iter()
+        .filter_map(|(id, other)| Some((kg.memory_nodes.get(id)?, dot(vector, other))))
+        .filter(|(existing, similarity)| *similarity >= DUPLICATE_EMBEDDING_SIMILARITY && can_merge(state, user, existing, node))
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(existing, _)| existing.id.clone())
+}
+
+fn absorb_memory(kept: &mut MemoryNode, merged: MemoryNode) {
+    kept.access_count = kept.access_count.saturating_add(merged.access_count);
+    kept.importance_score = kept.importance_score.max(merged.importance_score);
+    kept.created_at = kept.created_at.min(merged.created_at);
+    kept.last_accessed = kept.last_accessed.max(merged.last_accessed);
+    kept.pinned |= merged.pinned;
+    for tag in merged.tags {
+        if !kept.tags.contains(&tag) {
+            kept.tags.push(tag);
+        }
+    }
+    for conversation in merged.related_conversations {
+        if !kept.related_conversations.contains(&conversation) {
+            kept.related_conversations.push(conversation);
+        }
+    }
+}
+
+// Folds the merged nodes into the kept one: access counts are summed, tags and conversations are
+// unioned, and their edges are re-pointed at the kept node before they are removed.
+fn merge_memory_nodes(state: &mut State, user: Principal, keep_id: &str, merge_ids: &[String]) -> Option<MemoryNode> {
+    let mut kept = state.personal_knowledge_graphs.get(&user)?.memory_nodes.get(keep_id)?.clone();
+    
+    for merge_id in merge_ids.iter().filter(|id| id.as_str() != keep_id) {
+        let merged = match state.personal_knowledge_graphs.get(&user).and_then(|kg| kg.memory_nodes.get(merge_id)) {
+            Some(node) => node.clone(),
+            None => continue,
+        };
+        absorb_memory(&mut kept, merged);
+        
+        let edges: Vec<KnowledgeEdge> = ensure_memory_index(state, user).adjacency.get(merge_id).into_iter().flatten()
+            .map(|a| a.to_edge(merge_id))
+            .collect();
+        remove_memory_node(state, user, merge_id);
+        for mut edge in edges {
+            if edge.from_node == *merge_id {
+                edge.from_node = kept.id.clone();
+            } else {
+                edge.to_node = kept.id.clone();
+            }
+            add_knowledge_edge(state, user, edge);
+        }
+    }
+    
+    insert_memory_node(state, user, kept)
+}
+
+// Inserts a learned memory, folding it into an existing near-identical one instead of adding a copy,
+// and versioning it against facts it contradicts. Unconfident or pinned contradictions are kept side by
+// side until the user confirms. Returns the node that now holds the memory.
+fn store_memory_node(state: &mut State, user: Principal, node: MemoryNode, confident: bool) -> Option<MemoryNode> {
+    ensure_memory_index(state, user);
+    // A duplicate is folded into the existing node, so the copy is never indexed, tagged or counted
+    let existing = find_text_duplicate(state, user, &node)
+        .and_then(|id| state.personal_knowledge_graphs.get(&user)?.memory_nodes.get(&id).cloned());
+    let stored = match existing {
+        Some(mut existing) => {
+            absorb_memory(&mut existing, node);
+            insert_memory_node(state, user, existing)?
+        }
+        None => insert_memory_node(state, user, node)?,
+    };
+    
+    let conflicts = conflicting_facts(state, user, &stored);
+    if conflicts.is_empty() {
+        return Some(stored);
+    }
+    let needs_confirmation = !confident || conflicts.iter().any(|node| node.pinned);
+    for old in conflicts {
+        if needs_confirmation {
+            add_knowledge_edge(state, user, KnowledgeEdge {
+                from_node: stored.id.clone(),
+                to_node: old.id.clone(),
+                relationship_type: RelationshipType::OppositeOf,
+                strength: 1.0,
+                created_at: ic_cdk::api::time(),
+            });
+            if let Some(kg) = state.personal_knowledge_graphs.get_mut(&user) {
+                kg.pending_confirmations.push(PendingConfirmation {
+                    fact_key: old.fact_key.clone().unwrap_or_default(),
+                    current_node: old.id,
+                    proposed_node: stored.id.clone(),
+                    asked_at: ic_cdk::api::time(),
+                });
+                let excess = kg.pending_confirmations.len().saturating_sub(MAX_PENDING_CONFIRMATIONS);
+                kg.pending_confirmations.drain(..excess);
+            }
+        } else {
+            supersede_fact(state, user, &old.id, &stored.id);
+        }
+    }
+    state.personal_knowledge_graphs.get(&user)?.memory_nodes.get(&stored.id).cloned()
+}
+
+#[ic_cdk::update]
+fn merge_memories(user: Principal, node_ids: Vec<String>) -> Result<MemoryNode, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    let mut unique_ids: Vec<String> = Vec::new();
+    for id in node_ids {
+        if !unique_ids.contains(&id) {
+            unique_ids.push(id);
+        }
+    }
+    if unique_ids.len() < 2 {
+        return Err("At least two memories are needed to merge".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        for id in &unique_ids {
+            match kg.memory_nodes.get(id) {
+                Some(node) if matches!(node.node_type, MemoryNodeType::Document | MemoryNodeType::Entity) => {
+                    return Err("Documents and entities cannot be merged".to_string());
+                }
+                Some(_) => {}
+                None => return Err(format!("Memory {} not found", id)),
+            }
+        }
+        
+        // The first id is kept; the rest are folded into it
+        merge_memory_nodes(&mut state, user, &unique_ids[0], &unique_ids[1..])
+            .ok_or_else(|| "Memory not found".to_string())
+    })
+}
+
+// Contradictions and fact versioning
+
+const FACT_CONFIRMATION_CONFIDENCE: f32 = 0.6;
+const MAX_PENDING_CONFIRMATIONS: usize = 10;
+const CONFIRMATION_WINDOW_SECS: u64 = 30 * 60; // a yes/no only answers a question asked this recently
+
+// Statements whose subject can only hold one value at a time; later ones replace earlier ones
+const SINGLE_VALUED_FACTS: [(&str, &str); 11] = [
+    ("my name is ", "name"),
+    ("i work at ", "employer"),
+    ("i work for ", "employer"),
+    ("i'm working at ", "employer"),
+    ("i live in ", "location"),
+    ("i'm based in ", "location"),
+    ("i moved to ", "location"),
+    ("i work as a ", "occupation"),
+    ("i work as an ", "occupation"),
+    ("my birthday is ", "birthday"),
+    ("i'm learning ", "learning"),
+];
+
+const PREFERENCE_PHRASES: [(&str, &str); 7] = [
+    ("i don't like ", "dislike"),
+    ("i do not like ", "dislike"),
+    ("i dislike ", "dislike"),
+    ("i hate ", "dislike"),
+    ("i like ", "like"),
+    ("i love ", "like"),
+    ("i prefer ", "like"),
+];
+
+const AFFIRMATIVE_REPLIES: [&str; 5] = ["yes", "yeah", "yep", "correct", "that's right"];
+const NEGATIVE_REPLIES: [&str; 4] = ["no", "nope", "not really", "that's wrong"];
+
+// The phrase's object, up to the end of the clause
+fn fact_object(text: &str) -> String {
+    let clause = text.split(['.', ',', '!', '?', ';']).next().unwrap_or("");
+    let clause = clause.split(" and ").next().unwrap_or("").split(" but ").next().unwrap_or("");
+    clause.trim().trim_start_matches("the ").trim().to_string()
+}
+
+// What a statement is about and the value it asserts, e.g. ("employer", "acme") or ("preference:coffee", "like")
+fn fact_key_for(content: &str) -> Option<(String, String)> {
+    let lower = content.to_lowercase().replace('’', "'");
+    
+    for (phrase, key) in SINGLE_VALUED_FACTS {
+        if let Some(pos) = lower.find(phrase) {
+            let value = fact_object(&lower[pos + phrase.len()..]);
+            if !value.is_empty() {
+                return Some((key.to_string(), value));
+            }
+        }
+    }
+    for (phrase, polarity) in PREFERENCE_PHRASES {
+        if let Some(pos) = lower.find(phrase) {
+            let topic: Vec<String> = tokenize(&fact_object(&lower[pos + phrase.len()..])).into_iter().take(3).collect();
+            if !topic.is_empty() {
+                return Some((format!("preference:{}", topic.join(" ")), polarity.to_string()));
+            }
+        }
+    }
+    None
+}
+
+// Current facts with the same key as the node but a different value
+fn conflicting_facts(state: &State, user: Principal, node: &MemoryNode) -> Vec<MemoryNode> {
+    let (key, value) = match fact_key_for(&node.content) {
+        Some(fact) => fact,
+        None => return Vec::new(),
+    };
+    let kg = match state.personal_knowledge_graphs.get(&user) {
+        Some(kg) => kg,
+        None => return Vec::new(),
+    };
+    kg.memory_nodes.values()
+        .filter(|other| other.id != node.id && other.superseded_by.is_none() && other.fact_key.as_deref() == Some(key.as_str()))
+        .filter(|other| match fact_key_for(&other.content) {
+            Some((_, other_value)) if key.starts_with("preference:") => other_value != value,
+            Some((_, other_value)) => text_similarity(&other_value, &value) < 0.5,
+            None => false,
+        })
+        .cloned()
+        .collect()
+}
+
+// Marks the old fact as replaced by the new one; the old node stays as history behind a Supersedes edge
+fn supersede_fact(state: &mut State, user: Principal, old_id: &str, new_id: &str) {
+    let kg = match state.personal_knowledge_graphs.get_mut(&user) {
+        Some(kg) => kg,
+        None => return,
+    };
+    let old_version = match kg.memory_nodes.get_mut(old_id) {
+        Some(old) => {
+            old.superseded_by = Some(new_id.to_string());
+            old.pinned = false;
+            old.version.max(1)
+        }
+        None => return,
+    };
+    if let Some(new) = kg.memory_nodes.get_mut(new_id) {
+        new.version = new.version.max(old_version + 1);
+    }
+    kg.pending_confirmations.retain(|pending| {
+        !(pending.current_node == old_id && pending.proposed_node == new_id)
+            && !(pending.current_node == new_id && pending.proposed_node == old_id)
+    });
+    
+    for (from, to) in [(new_id, old_id), (old_id, new_id)] {
+        remove_knowledge_edges(state, user, from, to, Some(&RelationshipType::OppositeOf));
+    }
+    add_knowledge_edge(state, user, KnowledgeEdge {
+        from_node: new_id.to_string(),
+        to_node: old_id.to_string(),
+        relationship_type: RelationshipType::Supersedes,
+        strength: 1.0,
+        created_at: ic_cdk::api::time(),
+    });
+}
+
+// A question to ask before storing facts from this message that would silently replace ones we are
+// not confident about (hedged statements, or pinned memories)
+fn fact_confirmation_strategy(user: Principal, message: &str) -> Option<ResponseStrategy> {
+    STATE.with(|state| {
+        let state = state.borrow();
+        for fact in extract_facts_from_message(message).into_iter().filter(|fact| fact.should_remember) {
+            let candidate = MemoryNode::new(String::new(), fact.fact.clone(), MemoryNodeType::Fact, fact.confidence);
+            let conflicts = conflicting_facts(&state, user, &candidate);
+            let unconfident = fact.confidence < FACT_CONFIRMATION_CONFIDENCE;
+            if let Some(old) = conflicts.iter().find(|old| unconfident || old.pinned) {
+                return Some(ResponseStrategy::InquiryFirst {
+                    question: format!("Earlier you told me \"{}\". Has that changed? (yes/no)", old.content),
+                    why_asking: "I don't want to replace something I know about you unless you're sure".to_string(),
+                });
+            }
+        }
+        None
+    })
+}
+
+// A bare yes or no; anything longer is a new message rather than an answer
+fn confirmation_reply(reply: &str) -> Option<bool> {
+    let reply = reply.trim().to_lowercase().replace('’', "'");
+    let reply = reply.trim_end_matches(['.', '!']).trim();
+    if AFFIRMATIVE_REPLIES.contains(&reply) {
+        Some(true)
+    } else if NEGATIVE_REPLIES.contains(&reply) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn confirmation_open(asked_at: u64, now: u64) -> bool {
+    now.saturating_sub(asked_at) <= CONFIRMATION_WINDOW_SECS * 1_000_000_000
+}
+
+// Applies a yes/no reply to the question asked last: for a contradiction yes promotes the new fact and
+// no drops it, for inferred goal progress yes applies it. Both are only asked about on the turn after
+// they were found, so any other reply, or one arriving too late, settles them as a no.
+fn resolve_pending_confirmation(user: Principal, reply: &str) {
+    let confirmed = confirmation_reply(reply);
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let now = ic_cdk::api::time();
+        let kg = match state.personal_knowledge_graphs.get_mut(&user) {
+            Some(kg) => kg,
+            None => return,
+        };
+        let goal_update = kg.pending_goal_update.take();
+        let pending = std::mem::take(&mut kg.pending_confirmations);
+        let goal_asked_last = goal_update.as_ref().map(|update| update.asked_at) > pending.last().map(|pending| pending.asked_at);
+        if let Some(update) = goal_update.filter(|update| goal_asked_last && confirmed == Some(true) && confirmation_open(update.asked_at, now)) {
+            apply_goal_update(&mut kg.user_profile, &update, now);
+        }
+        
+        let (promoted, dropped) = settle_confirmations(pending, confirmed.filter(|_| !goal_asked_last), now);
+        for pending in promoted {
+            supersede_fact(&mut state, user, &pending.current_node, &pending.proposed_node);
+        }
+        for node_id in dropped {
+            remove_memory_node(&mut state, user, &node_id);
+        }
+    });
+}
+
+// Splits pending contradictions into those a yes promotes and the proposed nodes to drop. Only the
+// latest statement can be confirmed (it may contradict several older facts at once); everything
+// else keeps the existing fact, so a contradiction never leaves two current versions behind.
+fn settle_confirmations(pending: Vec<PendingConfirmation>, confirmed: Option<bool>, now: u64) -> (Vec<PendingConfirmation>, Vec<String>) {
+    let accepted = pending.last()
+        .filter(|latest| confirmed == Some(true) && confirmation_open(latest.asked_at, now))
+        .map(|latest| latest.proposed_node.clone());
+    let (promoted, rejected): (Vec<_>, Vec<_>) = pending.into_iter()
+        .partition(|pending| Some(&pending.proposed_node) == accepted.as_ref());
+    let mut dropped: Vec<String> = Vec::new();
+    for pending in rejected {
+        if !dropped.contains(&pending.proposed_node) {
+            dropped.push(pending.proposed_node);
+        }
+    }
+    (promoted, dropped)
+}
+
+// Every version of a fact, newest first
+#[ic_cdk::query]
+fn get_fact_history(user: Principal, node_id: String) -> Result<Vec<MemoryNode>, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let kg = state.personal_knowledge_graphs.get(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let index = memory_index_for(&state, user);
+        
+        let mut current = kg.memory_nodes.get(&node_id).ok_or_else(|| "Memory not found".to_string())?;
+        let mut seen = HashSet::from([current.id.clone()]);
+        while let Some(newer) = current.superseded_by.as_ref().and_then(|id| kg.memory_nodes.get(id)) {
+            if !seen.insert(newer.id.clone()) {
+                break;
+            }
+            current = newer;
+        }
+        
+        let mut history = vec![current.clone()];
+        let mut seen = HashSet::from([current.id.clone()]);
+        while let Some(older) = index.adjacency.get(&history[history.len() - 1].id).into_iter().flatten()
+            .find(|a| a.outgoing && a.relationship_type == RelationshipType::Supersedes && !seen.contains(&a.neighbor))
+            .and_then(|a| kg.memory_nodes.get(&a.neighbor))
+        {
+            seen.insert(older.id.clone());
+            history.push(older.clone());
+        }
+        Ok(history)
+    })
+}
+
+// Manually settles a contradiction: `outdated_node` becomes an earlier version of `current_node`
+#[ic_cdk::update]
+fn resolve_fact_conflict(user: Principal, current_node: String, outdated_node: String) -> Result<MemoryNode, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    if current_node == outdated_node {
+        return Err("A memory cannot supersede itself".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        for id in [&current_node, &outdated_node] {
+            match kg.memory_nodes.get(id) {
+                Some(node) if matches!(node.node_type, MemoryNodeType::Document | MemoryNodeType::Entity) => {
+                    return Err("Documents and entities cannot be versioned".to_string());
+                }
+                Some(_) => {}
+                None => return Err(format!("Memory {} not found", id)),
+            }
+        }
+        
+        supersede_fact(&mut state, user, &outdated_node, &current_node);
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        kg.last_updated = ic_cdk::api::time();
+        let current = kg.memory_nodes.get_mut(&current_node)
+            .ok_or_else(|| "Memory not found".to_string())?;
+        current.superseded_by = None;
+        Ok(current.clone())
+    })
+}
+
+// Whether semantic retrieval has anything to rank for the user
+fn has_memory_vectors(user: Principal) -> bool {
+    STATE.with(|state| {
+        let state = state.borrow();
+        match state.memory_indexes.get(&user) {
+            Some(index) => !index.vectors.is_empty(),
+            None => state.personal_knowledge_graphs.get(&user)
+                .is_some_and(|kg| kg.memory_nodes.values().any(|node| node.embedding.is_some())),
+        }
+    })
+}
+
+async fn embed_query(text: &str) -> Option<Vec<f32>> {
+    let api_key = STATE.with(|state| state.borrow().api_key.clone());
+    if api_key.is_empty() {
+        return None;
+    }
+    
+    match call_gemini_embeddings(vec![text.to_string()], api_key).await {
+        Ok(mut embeddings) => embeddings.pop(),
+        Err(e) => {
+            ic_cdk::println!("Query embedding failed, using keyword retrieval: {}", e);
+            None
+        }
+    }
+}
+
+// Computes embeddings for nodes that do not have one cached yet, one batch per call.
+// Returns how many nodes were embedded.
+async fn embed_pending_memories(user: Principal) -> usize {
+    let (api_key, pending) = STATE.with(|state| {
+        let state = state.borrow();
+        let pending: Vec<(String, String)> = state.personal_knowledge_graphs.get(&user)
+            .map(|kg| kg.memory_nodes.values()
+                .filter(|node| node.embedding.is_none() && node.node_type != MemoryNodeType::Entity)
+                .take(EMBEDDING_BATCH_SIZE)
+                .map(|node| (node.id.clone(), node.content.clone()))
+                .collect())
+            .unwrap_or_default();
+        (state.api_key.clone(), pending)
+    });
+    
+    if api_key.is_empty() || pending.is_empty() {
+        return 0;
+    }
+    
+    let texts = pending.iter().map(|(_, content)| content.clone()).collect();
+    match call_gemini_embeddings(texts, api_key).await {
+        Ok(embeddings) => STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let count = embeddings.len();
+            for ((node_id, _), embedding) in pending.into_iter().zip(embeddings) {
+                index_memory_embedding(&mut state, user, &node_id, embedding);
+                // Paraphrases that slipped past the text check are caught once both have vectors
+                if let Some(existing_id) = find_embedding_duplicate(&state, user, &node_id) {
+                    merge_memory_nodes(&mut state, user, &existing_id, &[node_id]);
+                }
+            }
+            count
+        }),
+        Err(e) => {
+            ic_cdk::println!("Memory embedding failed: {}", e);
+            0
+        }
+    }
+}
+
+// Timer job: embeds one batch for each of a few users with unembedded memories, resuming after
+// the last user handled so every user gets a turn
+async fn run_embedding_backlog() {
+    let users = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        // A run that trapped part-way never cleared its marker, so an old one is ignored
+        let now = ic_cdk::api::time();
+        if state.api_key.is_empty()
+            || (state.embedding_started_at != 0
+                && now.saturating_sub(state.embedding_started_at) < EMBEDDING_INTERVAL_SECS * 1_000_000_000)
+        {
+            return Vec::new();
+        }
+        
+        // Indexed documents without a vector still need one; users without an index yet are embedded
+        // once a prompt builds it
+        let mut pending: Vec<Principal> = state.memory_indexes.iter()
+            .filter(|(_, index)| index.vectors.len() < index.doc_lengths.len())
+            .map(|(user, _)| *user)
+            .collect();
+        pending.sort();
+        let start = state.embedding_cursor
+            .map(|cursor| pending.iter().position(|user| *user > cursor).unwrap_or(0))
+            .unwrap_or(0);
+        let batch: Vec<Principal> = pending.iter().cycle().skip(start).take(pending.len().min(EMBEDDING_USERS_PER_RUN)).copied().collect();
+        
+        state.embedding_cursor = batch.last().copied().or(state.embedding_cursor);
+        if !batch.is_empty() {
+            state.embedding_started_at = now;
+        }
+        batch
+    });
+    
+    for user in users {
+        embed_pending_memories(user).await;
+    }
+    STATE.with(|state| state.borrow_mut().embedding_started_at = 0);
+}
+
+// Lexical retrieval (BM25)
+
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+const STOP_WORDS: [&str; 64] = [
+    "a", "about", "all", "am", "an", "and", "any", "are", "as", "at", "be", "been", "but", "by",
+    "can", "could", "did", "do", "does", "for", "from", "had", "has", "have", "he", "her", "him",
+    "how", "i", "if", "in", "into", "is", "it", "its", "me", "my", "no", "not", "of", "on", "or",
+    "our", "she", "so", "that", "the", "their", "them", "then", "there", "they", "this", "to",
+    "was", "we", "were", "what", "when", "which", "who", "why", "will", "with",
+];
+
+// Light suffix stripping so "likes"/"liked"/"liking" share a stem without a full Porter stemmer
+fn stem(word: &str) -> String {
+    if word.chars().count() <= 3 || !word.is_ascii() {
+        return word.to_string();
+    }
+    let mut stem = word.to_string();
+    
+    // Plurals
+    if stem.ends_with("sses") {
+        stem.truncate(stem.len() - 2);
+    } else if stem.ends_with("ies") {
+        stem.truncate(stem.len() - 3);
+        stem.push('y');
+    } else if stem.ends_with('s') && !stem.ends_with("ss") && !stem.ends_with("us") && !stem.ends_with("is") {
+        stem.pop();
+    }
+    
+    // Derivational and verb suffixes
+    for suffix in ["ingly", "edly", "ing", "ed", "ly", "ness", "ment"] {
+        if stem.len() >= suffix.len() + 3 && stem.ends_with(suffix) {
+            stem.truncate(stem.len() - suffix.len());
+            if suffix.starts_with("ing") || suffix.starts_with("ed") {
+                let bytes = stem.as_bytes();
+                let last = bytes[bytes.len() - 1];
+                if bytes[bytes.len() - 2] == last && !b"aeiouylsz".contains(&last) {
+                    stem.pop(); // "running" -> "run"
+                }
+            }
+            break;
+        }
+    }
+    
+    if stem.len() > 3 && stem.ends_with('e') {
+        stem.pop();
+    }
+    stem
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 2 && !STOP_WORDS.contains(word))
+        .map(stem)
+        .collect()
+}
+
+fn index_node_terms(index: &mut MemoryIndex, node: &MemoryNode) {
+    // Entity nodes are only names; retrieval reaches their memories instead
+    if node.node_type == MemoryNodeType::Entity {
+        return;
+    }
+    index.word_sets.insert(node.id.clone(), normalized_word_set(&node.content));
+    let mut terms = tokenize(&node.content);
+    for tag in &node.tags {
+        terms.extend(tokenize(tag));
+    }
+    
+    index.doc_lengths.insert(node.id.clone(), terms.len() as u32);
+    index.total_terms += terms.len() as u64;
+    for term in &terms {
+        *index.postings.entry(term.clone()).or_default().entry(node.id.clone()).or_insert(0) += 1;
+    }
+    terms.sort();
+    terms.dedup();
+    index.doc_terms.insert(node.id.clone(), terms);
+}
+
+fn unindex_node(index: &mut MemoryIndex, node_id: &str) {
+    index.vectors.remove(node_id);
+    index.word_sets.remove(node_id);
+    if let Some(length) = index.doc_lengths.remove(node_id) {
+        index.total_terms = index.total_terms.saturating_sub(length as u64);
+    }
+    for term in index.doc_terms.remove(node_id).unwrap_or_default() {
+        if let Some(docs) = index.postings.get_mut(&term) {
+            docs.remove(node_id);
+            if docs.is_empty() {
+                index.postings.remove(&term);
+            }
+        }
+    }
+}
+
+// Returns (node id, score) pairs ordered by descending BM25 score
+fn bm25_search(index: &MemoryIndex, query: &str) -> Vec<(String, f32)> {
+    let doc_count = index.doc_lengths.len() as f32;
+    if doc_count == 0.0 {
+        return Vec::new();
+    }
+    let avg_length = (index.total_terms as f32 / doc_count).max(1.0);
+    
+    let mut query_terms = tokenize(query);
+    query_terms.sort();
+    query_terms.dedup();
+    
+    let mut scores: HashMap<&str, f32> = HashMap::new();
+    for term in &query_terms {
+        if let Some(docs) = index.postings.get(term) {
+            let df = docs.len() as f32;
+            let idf = (1.0 + (doc_count - df + 0.5) / (df + 0.5)).ln();
+            for (node_id, tf) in docs {
+                let tf = *tf as f32;
+                let length = index.doc_lengths.get(node_id).copied().unwrap_or(0) as f32;
+                let norm = tf + BM25_K1 * (1.0 - BM25_B + BM25_B * length / avg_length);
+                *scores.entry(node_id).or_insert(0.0) += idf * tf * (BM25_K1 + 1.0) / norm;
+            }
+        }
+    }
+    
+    let mut ranked: Vec<(String, f32)> = scores.into_iter()
+        .map(|(id, score)| (id.to_string(), score))
+        .collect();
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    ranked
+}
+
+fn determine_response_strategy(user: Principal, prompt: &str, relevant_memories: &[MemoryNode]) -> ResponseStrategy {
+    STATE.with(|state| {
+        let state = state.borrow();
+        let graph = state.personal_knowledge_graphs.get(&user);
+        
+        // Check if this is a new user with minimal information
+        if let Some(kg) = graph {
+            // For new users, still use AI but with a learning focus
+            if kg.user_profile.name.is_none() && kg.memory_nodes.is_empty() {
+                // Instead of hardcoded response, use AI with learning opportunity
+                return ResponseStrategy::ConfidentAnswer {
+                    confidence: 0.7,
+                    sources: Vec::new(),
+                };
+            }
+            
+            // Check if prompt is asking about something we don't know
+            if prompt.to_lowercase().contains("my") || prompt.to_lowercase().contains("i ") {
+                if relevant_memories.is_empty() && !contains_personal_context(prompt) {
+                    return ResponseStrategy::InquiryFirst {
+                        question: format!("I want to give you the most helpful response. Could you provide a bit more context about {}?", extract_topic_from_prompt(prompt)),
+                        why_asking: "This helps me understand your specific situation and give you better advice".to_string(),
+                    };
+                }
+            }
+            
+            // If we have some relevant memories, we can give a confident answer
+            if !relevant_memories.is_empty() {
+                return ResponseStrategy::ConfidentAnswer {
+                    confidence: 0.8,
+                    sources: relevant_memories.iter().map(|m| m.id.clone()).collect(),
+                };
+            }
+            
+            // If it's a learning opportunity
+            if is_learning_opportunity(prompt) {
+                return ResponseStrategy::LearningOpportunity {
+                    suggestion: "I can help you with this and remember your preferences for next time".to_string(),
+                };
+            }
+        }
+        
+        // Default to confident answer for general questions
+        ResponseStrategy::ConfidentAnswer {
+            confidence: 0.6,
+            sources: Vec::new(),
+        }
+    })
+}
+
+fn contains_personal_context(prompt: &str) -> bool {
+    let personal_indicators = ["my name is", "i am", "i work", "i like", "i prefer", "i usually"];
+    let prompt_lower = prompt.to_lowercase();
+    personal_indicators.iter().any(|indicator| prompt_lower.contains(indicator))
+}
+
+fn extract_topic_from_prompt(prompt: &str) -> String {
+    // Simple topic extraction - in a real implementation, this would be more sophisticated
+    truncate_chars(prompt, 50)
+}
+
+fn is_learning_opportunity(_prompt: &str) -> bool {
+    // Determine if this is a good opportunity to learn something new about the user
+    true // Simplified for now
+}
+
+async fn generate_contextual_ai_response(
+    user: Principal,
+    prompt: String,
+    user_context: String,
+    relevant_memories: Vec<MemoryNode>,
+    attachments: &[Attachment],
+    sentiment: &Sentiment,
+) -> Result<String, String> {
+    let api_key = STATE.with(|state| {
+        let state = state.borrow();
+        state.api_key.clone()
+    });
+    
+    if api_key.is_empty() {
+        return Err("API key is not set".to_string());
+    }
+    
+    let check_in = STATE.with(|state| take_sentiment_check_in(&mut state.borrow_mut(), user, ic_cdk::api::time()));
+    
+    // Build enhanced prompt with user context and memories from the active template
+    let enhanced_prompt = render_prompt(
+        user,
+        PromptTemplateKind::ContextualResponse,
+        &prompt,
+        &user_context,
+        &relevant_memories,
+        &[("tone", tone_guidance(sentiment).to_string()), ("check_in", check_in.unwrap_or_default())],
+    );
+    
+    let mut parts = vec![Part::text(enhanced_prompt)];
+    parts.extend(attachments.iter().map(Part::inline));
+    call_gemini_api_with_parts(parts, api_key).await
+}
+
+// Prompt templates
+
+const DEFAULT_PERSONA: &str = "memorymind";
+const MAX_TEMPLATE_BYTES: usize = 8 * 1024;
+const TEMPLATE_HISTORY_MESSAGES: usize = 5;
+
+// Every placeholder a template may reference, either as {{name}} or as a {{#name}}...{{/name}} section
+const TEMPLATE_PLACEHOLDERS: [&str; 20] = [
+    "name",
+    "preferred_name",
+    "interests",
+    "goals",
+    "expertise_areas",
+    "expertise_levels",
+    "communication_style",
+    "work_context",
+    "user_context",
+    "memories",
+    "history",
+    "question",
+    "topic",
+    "inquiry",
+    "why_asking",
+    "known_info",
+    "clarification",
+    "suggestion",
+    "tone",
+    "check_in",
+];
+
+fn builtin_template(kind: &PromptTemplateKind) -> &'static str {
+    match kind {
+        PromptTemplateKind::ContextualResponse => concat!(
+            "You are MemoryMind, a personal AI assistant that learns and remembers everything about the user. ",
+            "Use the following context to provide a personalized response:\n\n",
+            "{{#user_context}}USER CONTEXT:\n{{user_context}}\n{{/user_context}}",
+            "{{#memories}}RELEVANT MEMORIES:\n{{memories}}\n{{/memories}}",
+            "{{#expertise_levels}}USER EXPERTISE (pitch explanations at these levels):\n{{expertise_levels}}\n{{/expertise_levels}}",
+            "{{#communication_style}}COMMUNICATION STYLE:\n{{communication_style}}\n{{/communication_style}}",
+            "USER QUESTION: {{question}}\n\n",
+            "{{#tone}}TONE: {{tone}}\n\n{{/tone}}",
+            "{{#check_in}}If it fits naturally, gently check in with the user: {{check_in}}\n\n{{/check_in}}",
+            "Provide a helpful, personalized response that references relevant context and memories when appropriate. ",
+            "Be conversational and show that you remember previous interactions.",
+        ),
+        PromptTemplateKind::InquiryFirst => "🤔 {{inquiry}}\n\n({{why_asking}})",
+        PromptTemplateKind::PartialAnswer => "Based on what I know about you: {{known_info}}\n\n❓ {{clarification}}",
+        PromptTemplateKind::LearningOpportunity => {
+            "💡 {{suggestion}}\n\nWould you like me to remember this for future conversations?"
+        }
+    }
+}
+
+fn template_key(persona: &str, kind: &PromptTemplateKind) -> String {
+    format!("{}/{:?}", persona, kind)
+}
+
+fn normalize_persona(persona: &str) -> Result<String, String> {
+    let persona = persona.trim().to_lowercase();
+    if persona.is_empty() || persona.len() > 32 {
+        return Err("Persona must be between 1 and 32 characters".to_string());
+    }
+    if !persona.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err("Persona may only contain letters, digits, '-' and '_'".to_string());
+    }
+    Ok(persona)
+}
+
+fn persona_for_user(store: &PromptTemplateStore, user: &Principal) -> String {
+    store.user_personas.get(user)
+        .or(store.default_persona.as_ref())
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_PERSONA.to_string())
+}
+
+fn persona_exists(store: &PromptTemplateStore, persona: &str) -> bool {
+    persona == DEFAULT_PERSONA || store.templates.iter().any(|t| t.persona == persona)
+}
+
+// Resolves the active body for a persona, falling back to the default persona and then the built-in text
+fn active_template_body(store: &PromptTemplateStore, persona: &str, kind: &PromptTemplateKind) -> String {
+    for candidate in [persona, DEFAULT_PERSONA] {
+        if let Some(version) = store.active_versions.get(&template_key(candidate, kind)) {
+            if let Some(template) = store.templates.iter()
+                .find(|t| t.persona == candidate && &t.kind == kind && t.version == *version)
+            {
+                return template.body.clone();
+            }
+        }
+    }
+    builtin_template(kind).to_string()
+}
+
+fn validate_template(body: &str) -> Result<(), String> {
+    if body.trim().is_empty() {
+        return Err("Template body cannot be empty".to_string());
+    }
+    if body.len() > MAX_TEMPLATE_BYTES {
+        return Err(format!("Template body exceeds {} bytes", MAX_TEMPLATE_BYTES));
+    }
+    
+    let mut open_sections: Vec<&str> = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| "Unclosed '{{' in template".to_string())?;
+        let tag = after[..end].trim();
+        rest = &after[end + 2..];
+        
+        let name = tag.trim_start_matches(['#', '^', '/']);
+        if !TEMPLATE_PLACEHOLDERS.contains(&name) {
+            return Err(format!("Unknown placeholder '{}'", name));
+        }
+        if tag.starts_with('#') || tag.starts_with('^') {
+            open_sections.push(name);
+        } else if tag.starts_with('/') && open_sections.pop() != Some(name) {
+            return Err(format!("Section '{}' closed without being opened", name));
+        }
+    }
+    
+    match open_sections.pop() {
+        Some(name) => Err(format!("Section '{}' is never closed", name)),
+        None => Ok(()),
+    }
+}
+
+// Mustache-style rendering: {{name}} substitutes, {{#name}}..{{/name}} renders only when the value
+// is non-empty and {{^name}}..{{/name}} only when it is empty
+fn render_template(body: &str, values: &HashMap<String, String>) -> String {
+    let mut output = String::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = match after.find("}}") {
+            Some(end) => end,
+            None => {
+                output.push_str(&rest[start..]);
+                return output;
+            }
+        };
+        let tag = after[..end].trim();
+        rest = &after[end + 2..];
+        
+        if tag.starts_with('#') || tag.starts_with('^') {
+            let name = &tag[1..];
+            let closing = format!("{{{{/{}}}}}", name);
+            let (inner, remainder) = match rest.find(&closing) {
+                Some(pos) => (&rest[..pos], &rest[pos + closing.len()..]),
+                None => (rest, ""),
+            };
+            let has_value = values.get(name).is_some_and(|v| !v.trim().is_empty());
+            if has_value == tag.starts_with('#') {
+                output.push_str(&render_template(inner, values));
+            }
+            rest = remainder;
+        } else if !tag.starts_with('/') {
+            output.push_str(values.get(tag).map(String::as_str).unwrap_or(""));
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn template_values(
+    state: &State,
+    user: &Principal,
+    question: &str,
+    user_context: &str,
+    memories: &[MemoryNode],
+    extras: &[(&str, String)],
+) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+    
+    if let Some(kg) = state.personal_knowledge_graphs.get(user) {
+        let profile = &kg.user_profile;
+        values.insert("name".to_string(), profile.name.clone().unwrap_or_default());
+        values.insert("preferred_name".to_string(), profile.preferred_name.clone().or(profile.name.clone()).unwrap_or_default());
+        values.insert("interests".to_string(), profile.interests.join(", "));
+        values.insert("expertise_areas".to_string(), profile.expertise_areas.join(", "));
+        values.insert("expertise_levels".to_string(), expertise_levels_for(profile, question));
+        values.insert("communication_style".to_string(), style_guidance(&profile.communication_style));
+        values.insert("goals".to_string(), profile.goals.iter()
+            .filter(|goal| goal.status == TaskStatus::Active)
+            .map(|goal| format!("- {} ({}% complete)\n", goal.goal, (goal.progress * 100.0) as u32))
+            .collect());
+        if let Some(work) = &profile.work_context {
+            let parts: Vec<String> = [work.job_title.clone(), work.company.clone().map(|c| format!("at {}", c))]
+                .into_iter()
+                .flatten()
+                .collect();
+            values.insert("work_context".to_string(), parts.join(" "));
+        }
+    }
+    
+    let history: Vec<String> = state.conversations.get(user)
+        .map(|messages| messages.iter()
+            .rev()
+            .take(TEMPLATE_HISTORY_MESSAGES)
+            .rev()
+            .map(|m| format!("- {}: {}\n", m.role, truncate_chars(&m.content, 200)))
+            .collect())
+        .unwrap_or_default();
+    values.insert("history".to_string(), history.concat());
+    
+    values.insert("memories".to_string(), memories.iter()
+        .map(|memory| match document_title(state, user, &memory.id) {
+            Some(title) => format!("- {} ({}) [source: {}]\n", memory.content, memory.node_type.to_string(), title),
+            None => format!("- {} ({})\n", memory.content, memory.node_type.to_string()),
+        })
+        .collect());
+    values.insert("user_context".to_string(), user_context.to_string());
+    values.insert("question".to_string(), question.to_string());
+    values.insert("topic".to_string(), extract_topic_from_prompt(question));
+    
+    for (key, value) in extras {
+        values.insert(key.to_string(), value.clone());
+    }
+    values
+}
+
+fn render_prompt(
+    user: Principal,
+    kind: PromptTemplateKind,
+    question: &str,
+    user_context: &str,
+    memories: &[MemoryNode],
+    extras: &[(&str, String)],
+) -> String {
+    STATE.with(|state| {
+        let state = state.borrow();
+        let persona = persona_for_user(&state.prompt_templates, &user);
+        let body = active_template_body(&state.prompt_templates, &persona, &kind);
+        let values = template_values(&state, &user, question, user_context, memories, extras);
+        render_template(&body, &values)
+    })
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}...", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn save_conversation_with_learning(
+    user: Principal,
+    user_message: String,
+    ai_response: String,
+    context_thread_id: Option<String>,
+    response_strategy: ResponseStrategy,
+    referenced_memories: Vec<String>,
+    attachment_ids: Vec<String>,
+    user_sentiment: Sentiment,
+) -> Vec<String> {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        
+        // Extract facts from user message
+        let extracted_facts = extract_facts_from_message(&user_message);
+        let mut stored_ids = Vec::new();
+        
+        // Learn from extracted facts
+        if state.personal_knowledge_graphs.contains_key(&user) {
+            for fact in &extracted_facts {
+                if fact.should_remember {
+                    let node_type = match fact.fact_type {
+                        FactType::PersonalInfo => MemoryNodeType::Fact,
+                        FactType::Preference => MemoryNodeType::Preference,
+                        FactType::Goal => MemoryNodeType::Goal,
+                        FactType::Relationship => MemoryNodeType::Relationship,
+                        FactType::Experience => MemoryNodeType::Experience,
+                        FactType::Knowledge => MemoryNodeType::Knowledge,
+                    };
+                    let id = allocate_memory_id(&mut state, user);
+                    let mut memory_node = MemoryNode::new(id, fact.fact.clone(), node_type, fact.confidence);
+                    memory_node.related_conversations.extend(context_thread_id.clone());
+                    let confident = fact.confidence >= FACT_CONFIRMATION_CONFIDENCE;
+                    let stored = store_memory_node(&mut state, user, memory_node, confident);
+                    
+                    // Unconfirmed contradictions wait for the user's answer before reaching the profile
+                    let pending = stored.as_ref().is_some_and(|node| {
+                        state.personal_knowledge_graphs[&user].pending_confirmations.iter().any(|p| p.proposed_node == node.id)
+                    });
+                    if !pending {
+                        if let Some(kg) = state.personal_knowledge_graphs.get_mut(&user) {
+                            apply_fact_to_profile(&mut kg.user_profile, fact, stored.as_ref().map(|node| node.id.as_str()), ic_cdk::api::time());
+                        }
+                    }
+                    if let Some(stored) = stored {
+                        if !stored_ids.contains(&stored.id) {
+                            stored_ids.push(stored.id);
+                        }
+                    }
+                }
+            }
+        }
+        
+        // Entities mentioned in the message join the registry and get linked to the memories stored from it
+        let entities = extract_entities(&user_message, ic_cdk::api::time());
+        let thread_id = context_thread_id.clone().unwrap_or_else(|| DEFAULT_THREAD_ID.to_string());
+        record_entities(&mut state, user, &entities, &stored_ids, &thread_id);
+        if let Some(kg) = state.personal_knowledge_graphs.get_mut(&user) {
+            update_conversation_context(kg, &thread_id, &user_message, &entities, &referenced_memories, &user_sentiment);
+            record_extracted_task(kg, &thread_id, &user_message);
+            record_people_mentions(kg, &user_message, &stored_ids);
+        }
+        
+        // Memories the response drew on are reinforced so they resist decay
+        reinforce_memories(&mut state, user, &referenced_memories);
+        
+        // Update knowledge graph
+        let mut learned_preferences = Vec::new();
+        if let Some(kg) = state.personal_knowledge_graphs.get_mut(&user) {
+            let now = ic_cdk::api::time();
+            record_interaction_patterns(kg, &user_message, stored_ids.len(), now);
+            update_domain_expertise(&mut kg.user_profile, &user_message);
+            learned_preferences = learn_communication_style(&mut kg.user_profile, &user_message);
+            kg.last_updated = now;
+        }
+        
+        // Save conversation
+        let enhanced_message = EnhancedChatMessage {
+            role: "assistant".to_string(),
+            content: ai_response,
+            timestamp: ic_cdk::api::time(),
+            provider: "gemini".to_string(),
+            context_thread_id,
+            extracted_facts,
+            referenced_memories,
+            learned_preferences,
+            user_sentiment: Some(user_sentiment),
+            response_strategy: Some(response_strategy),
+            cycles_cost: Some(0),
+            content_stored_on_chain: Some(false),
+            ii_verified: Some(true),
+            attachment_ids,
+        };
+        
+        let conversation = state.conversations.entry(user).or_insert_with(Vec::new);
+        conversation.push(enhanced_message);
+        
+        // Update metrics
+        state.canister_metrics.total_queries += 1;
+        state.canister_metrics.learning_events += 1;
+        
+        stored_ids
+    })
+}
+
+fn extract_facts_from_message(message: &str) -> Vec<ExtractedFact> {
+    let mut facts = Vec::new();
+    let message_lower = message.to_lowercase();
+    
+    // Simple fact extraction based on patterns
+    if message_lower.contains("my name is") {
+        if let Some(name) = extract_name_from_message(message) {
+            facts.push(ExtractedFact {
+                fact: format!("User's name is {}", name),
+                confidence: 0.9,
+                fact_type: FactType::PersonalInfo,
+                should_remember: true,
+            });
+        }
+    }
+    
+    if message_lower.contains("i work") || message_lower.contains("i'm a") {
+        facts.push(ExtractedFact {
+            fact: message.to_string(),
+            confidence: 0.7,
+            fact_type: FactType::PersonalInfo,
+            should_remember: true,
+        });
+    }
+    
+    if message_lower.contains("i like") || message_lower.contains("i prefer") {
+        facts.push(ExtractedFact {
+            fact: message.to_string(),
+            confidence: 0.8,
+            fact_type: FactType::Preference,
+            should_remember: true,
+        });
+    } else if INTEREST_PHRASES.iter().any(|phrase| message_lower.contains(phrase)) {
+        facts.push(ExtractedFact {
+            fact: message.to_string(),
+            confidence: 0.75,
+            fact_type: FactType::Preference,
+            should_remember: true,
+        });
+    }
+    
+    if PREFERRED_NAME_PHRASES.iter().any(|phrase| message_lower.contains(phrase))
+        || message_lower.contains("birthday is")
+        || message_lower.contains("anniversary is")
+    {
+        facts.push(ExtractedFact {
+            fact: message.to_string(),
+            confidence: 0.8,
+            fact_type: FactType::PersonalInfo,
+            should_remember: true,
+        });
+    }
+    
+    // Plans with a date ("I have a dentist appointment on March 3rd") become important dates
+    if let Some(object) = phrase_object(message, &EVENT_PHRASES) {
+        if parse_due_date(&object, ic_cdk::api::time()).is_some_and(|(_, start)| start > 0) {
+            facts.push(ExtractedFact {
+                fact: message.to_string(),
+                confidence: 0.8,
+                fact_type: FactType::PersonalInfo,
+                should_remember: true,
+            });
+        }
+    }
+    
+    if RELATIONSHIP_WORDS.iter().any(|word| message_lower.contains(&format!("my {}", word))) {
+        facts.push(ExtractedFact {
+            fact: message.to_string(),
+            confidence: 0.75,
+            fact_type: FactType::Relationship,
+            should_remember: true,
+        });
+    }
+    
+    if SKILL_PHRASES.iter().chain(EXPERTISE_PHRASES.iter()).any(|phrase| message_lower.contains(phrase)) {
+        facts.push(ExtractedFact {
+            fact: message.to_string(),
+            confidence: 0.75,
+            fact_type: FactType::Knowledge,
+            should_remember: true,
+        });
+    }
+    
+    if message_lower.contains("my goal") || message_lower.contains("i want to") || GOAL_PHRASES.iter().any(|phrase| message_lower.contains(phrase)) {
+        facts.push(ExtractedFact {
+            fact: message.to_string(),
+            confidence: 0.8,
+            fact_type: FactType::Goal,
+            should_remember: true,
+        });
+    }
+    
+    // Hedged statements are remembered with less confidence
+    if HEDGE_PHRASES.iter().any(|hedge| message_lower.contains(hedge)) {
+        for fact in &mut facts {
+            fact.confidence = (fact.confidence - 0.3).max(0.1);
+        }
+    }
+    
+    facts
+}
+
+const HEDGE_PHRASES: [&str; 6] = ["i think", "maybe", "probably", "might", "not sure", "i guess"];
+
+fn extract_name_from_message(message: &str) -> Option<String> {
+    // Simple name extraction - in production, this would be more sophisticated
+    if let Some((_, end)) = find_lowercase(message, "my name is") {
+        let after_phrase = &message[end..];
+        let name = after_phrase.split_whitespace().next()?;
+        Some(name.trim_end_matches(&['.', ',', '!', '?'][..]).to_string())
+    } else {
+        None
+    }
+}
+
+// Profile learning: extracted facts fill UserProfile fields, with provenance per field.
+// Fields the user set explicitly are never overwritten.
+
+const PROFILE_MIN_CONFIDENCE: f32 = 0.5;
+const MAX_PROFILE_LIST_ITEMS: usize = 20;
+
+const PREFERRED_NAME_PHRASES: [&str; 3] = ["call me ", "i go by ", "my nickname is "];
+const INTEREST_PHRASES: [&str; 5] = ["i love ", "i enjoy ", "i'm interested in ", "i am interested in ", "i'm into "];
+const SKILL_PHRASES: [&str; 4] = ["i'm good at ", "i know how to ", "i'm skilled in ", "i'm experienced with "];
+const EXPERTISE_PHRASES: [&str; 3] = ["i'm an expert in ", "i specialize in ", "i'm an expert on "];
+const JOB_TITLE_PHRASES: [&str; 4] = ["i work as a ", "i work as an ", "my job title is ", "my role is "];
+const EVENT_PHRASES: [&str; 6] = ["i have an ", "i have a ", "i've got an ", "i've got a ", "we have an ", "we have a "];
+const RELATIONSHIP_WORDS: [&str; 18] = [
+    "wife", "husband", "partner", "girlfriend", "boyfriend", "sister", "brother", "mom", "mother",
+    "dad", "father", "son", "daughter", "friend", "boss", "manager", "colleague", "coworker",
+];
+
+enum ProfileFact {
+    Name(String),
+    PreferredName(String),
+    Company(String),
+    JobTitle(String),
+    Skill(String),
+    Expertise(String),
+    Interest(String),
+    Relationship { name: String, relationship_type: String },
+    ImportantDate { event: String, date: i64 },
+    Goal { goal: String, target_date: Option<u64> },
+}
+
+// Text after the first matching phrase up to the end of its clause, keeping the original capitalization
+// Byte range in `text` of the first case-insensitive match of a lowercase phrase. Matching happens on
+// `text` itself, so the range stays on character boundaries even where lowercasing changes lengths.
+fn find_lowercase(text: &str, phrase: &str) -> Option<(usize, usize)> {
+    text.char_indices().find_map(|(start, _)| {
+        let mut wanted = phrase.chars().peekable();
+        let mut end = start;
+        for c in text[start..].chars() {
+            if wanted.peek().is_none() {
+                break;
+            }
+            if !c.to_lowercase().all(|lower| wanted.next() == Some(lower)) {
+                return None;
+            }
+            end += c.len_utf8();
+        }
+        wanted.peek().is_none().then_some((start, end))
+    })
+}
+
+fn phrase_object(text: &str, phrases: &[&str]) -> Option<String> {
+    let normalized = text.replace('’', "'");
+    phrases.iter()
+        .filter_map(|phrase| find_lowercase(&normalized, phrase).map(|(_, end)| &normalized[end..]))
+        .map(|rest| {
+            let clause = rest.split(['.', ',', '!', '?', ';']).next().unwrap_or("");
+            clause.split(" and ").next().unwrap_or("").split(" but ").next().unwrap_or("").trim().to_string()
+        })
+        .find(|object| !object.is_empty() && object.chars().count() <= 80)
+}
+
+fn first_word(text: &str) -> Option<String> {
+    let word = text.split_whitespace().next()?.trim_matches(|c: char| !c.is_alphanumeric() && c != '-' && c != '\'');
+    if word.is_empty() { None } else { Some(word.to_string()) }
+}
+
+fn is_capitalized(word: &str) -> bool {
+    word.chars().next().is_some_and(char::is_uppercase)
+}
+
+// "Anna's birthday is on June 4th" -> ("Anna's birthday", date)
+fn extract_possessive_event(text: &str, event: &str, now: u64) -> Option<(String, i64)> {
+    let normalized = text.replace('’', "'");
+    let marker = format!("'s {} is ", event);
+    let (pos, end) = find_lowercase(&normalized, &marker)?;
+    let owner = normalized[..pos].split_whitespace().last()?.trim_matches(|c: char| !c.is_alphanumeric());
+    if !is_capitalized(owner) {
+        return None;
+    }
+    let rest = &normalized[end..];
+    let date = parse_date(rest.trim_start_matches("on "), now)?;
+    Some((format!("{}'s {}", owner, event), date))
+}
+
+// "my sister Anna", "my sister is called Anna", "Anna is my sister"
+fn extract_relationship(text: &str) -> Option<(String, String)> {
+    let normalized = text.replace('’', "'");
+    let words: Vec<&str> = normalized.split_whitespace().collect();
+    let clean = |word: &str| word.trim_matches(|c: char| !c.is_alphanumeric()).to_string();
+    
+    for (i, word) in words.iter().enumerate() {
+        let relationship = clean(word).to_lowercase();
+        if !RELATIONSHIP_WORDS.contains(&relationship.as_str()) || i == 0 {
+            continue;
+        }
+        if clean(words[i - 1]).to_lowercase() == "my" {
+            let mut next = i + 1;
+            while next < words.len() && ["is", "called", "named", "-"].contains(&clean(words[next]).to_lowercase().as_str()) {
+                next += 1;
+            }
+            if let Some(name) = words.get(next).map(|w| clean(w)).filter(|w| is_capitalized(w)) {
+                return Some((name, relationship));
+            }
+            if i >= 3 && clean(words[i - 2]).to_lowercase() == "is" && is_capitalized(&clean(words[i - 3])) {
+                return Some((clean(words[i - 3]), relationship));
+            }
+        }
+    }
+    None
+}
+
+fn extract_profile_facts(text: &str, now: u64) -> Vec<ProfileFact> {
+    let mut facts = Vec::new();
+    
+    if let Some(name) = phrase_object(text, &["my name is "]).as_deref().and_then(first_word) {
+        facts.push(ProfileFact::Name(name));
+    }
+    if let Some(name) = phrase_object(text, &PREFERRED_NAME_PHRASES).as_deref().and_then(first_word) {
+        facts.push(ProfileFact::PreferredName(name));
+    }
+    if let Some(company) = phrase_object(text, &["i work at ", "i work for ", "i'm working at "]) {
+        facts.push(ProfileFact::Company(company));
+    }
+    if let Some(title) = phrase_object(text, &JOB_TITLE_PHRASES) {
+        facts.push(ProfileFact::JobTitle(title));
+    }
+    if let Some(skill) = phrase_object(text, &SKILL_PHRASES) {
+        facts.push(ProfileFact::Skill(skill));
+    }
+    if let Some(area) = phrase_object(text, &EXPERTISE_PHRASES) {
+        facts.push(ProfileFact::Expertise(area));
+    }
+    if let Some(interest) = phrase_object(text, &INTEREST_PHRASES) {
+        facts.push(ProfileFact::Interest(interest));
+    }
+    if let Some((name, relationship_type)) = extract_relationship(text) {
+        facts.push(ProfileFact::Relationship { name, relationship_type });
+    }
+    let lower = text.to_lowercase().replace('’', "'");
+    for event in ["birthday", "anniversary"] {
+        // Not phrase_object: its clause would end at the comma in "March 5, 1962"
+        let rest = [format!("my {} is on ", event), format!("my {} is ", event)].iter()
+            .find_map(|phrase| lower.find(phrase.as_str()).map(|pos| &lower[pos + phrase.len()..]));
+        if let Some(date) = rest.and_then(|rest| parse_date(rest, now)) {
+            facts.push(ProfileFact::ImportantDate { event: event.to_string(), date });
+        }
+        if let Some((event, date)) = extract_possessive_event(text, event, now) {
+            facts.push(ProfileFact::ImportantDate { event, date });
+        }
+    }
+    if let Some(object) = phrase_object(text, &EVENT_PHRASES) {
+        // "a dentist appointment on March 3rd" -> the event and its date
+        if let Some((date, start)) = parse_due_date(&object, now).filter(|(_, start)| *start > 0) {
+            let event = object[..start].trim().to_string();
+            facts.push(ProfileFact::ImportantDate { event, date: date as i64 });
+        }
+    }
+    if let Some(object) = phrase_object(text, &GOAL_PHRASES) {
+        // "launch the app by March 5th" -> the goal and its target date
+        let (goal, target_date) = match object.split_once(" by ") {
+            Some((goal, date)) => (goal.trim().to_string(), parse_date(date, now).and_then(|date| u64::try_from(date).ok())),
+            None => (object, None),
+        };
+        facts.push(ProfileFact::Goal { goal, target_date });
+    }
+    facts
+}
+
+fn can_update_profile_field(profile: &UserProfile, field: &str, confidence: f32) -> bool {
+    confidence >= PROFILE_MIN_CONFIDENCE
+        && profile.field_provenance.get(field).is_none_or(|provenance| provenance.source != ProfileSource::Explicit)
+}
+
+fn record_provenance(profile: &mut UserProfile, field: &str, source: ProfileSource, confidence: f32, memory_id: Option<&str>, now: u64) {
+    profile.field_provenance.insert(field.to_string(), FieldProvenance {
+        source,
+        confidence,
+        memory_id: memory_id.map(str::to_string),
+        updated_at: now,
+    });
+}
+
+fn push_unique(items: &mut Vec<String>, item: String) -> bool {
+    if items.len() >= MAX_PROFILE_LIST_ITEMS || items.iter().any(|existing| existing.eq_ignore_ascii_case(&item)) {
+        return false;
+    }
+    items.push(item);
+    true
+}
+
+// Maps one extracted fact onto the profile; returns true when any field changed
+fn apply_fact_to_profile(profile: &mut UserProfile, fact: &ExtractedFact, memory_id: Option<&str>, now: u64) -> bool {
+    let mut changed = false;
+    for profile_fact in extract_profile_facts(&fact.fact, ic_cdk::api::time()) {
+        let field = match &profile_fact {
+            ProfileFact::Name(_) => "name",
+            ProfileFact::PreferredName(_) => "preferred_name",
+            ProfileFact::Company(_) => "work_context.company",
+            ProfileFact::JobTitle(_) => "work_context.job_title",
+            ProfileFact::Skill(_) => "work_context.skills",
+            ProfileFact::Expertise(_) => "expertise_areas",
+            ProfileFact::Interest(_) => "interests",
+            ProfileFact::Relationship { .. } => "relationships",
+            ProfileFact::ImportantDate { .. } => "important_dates",
+            ProfileFact::Goal { .. } => "goals",
+        };
+        if !can_update_profile_field(profile, field, fact.confidence) {
+            continue;
+        }
+        
+        let updated = match profile_fact {
+            ProfileFact::Name(name) => profile.name.replace(name.clone()) != Some(name),
+            ProfileFact::PreferredName(name) => profile.preferred_name.replace(name.clone()) != Some(name),
+            ProfileFact::Company(company) => {
+                let work = profile.work_context.get_or_insert_with(WorkContext::default);
+                work.company.replace(company.clone()) != Some(company)
+            }
+            ProfileFact::JobTitle(title) => {
+                let work = profile.work_context.get_or_insert_with(WorkContext::default);
+                work.job_title.replace(title.clone()) != Some(title)
+            }
+            ProfileFact::Skill(skill) => push_unique(&mut profile.work_context.get_or_insert_with(WorkContext::default).skills, skill),
+            ProfileFact::Expertise(area) => push_unique(&mut profile.expertise_areas, area),
+            ProfileFact::Interest(interest) => push_unique(&mut profile.interests, interest),
+            ProfileFact::Relationship { name, relationship_type } => {
+                let full = profile.relationships.len() >= MAX_PEOPLE;
+                match profile.relationships.iter().position(|r| r.name.eq_ignore_ascii_case(&name)) {
+                    Some(i) => {
+                        let person = &mut profile.relationships[i];
+                        person.relationship_type = relationship_type;
+                        person.context = fact.fact.clone();
+                        person.importance = person.importance.max(fact.confidence);
+                        link_person_memory(person, memory_id);
+                    }
+                    None if !full => {
+                        let input = PersonInput { name, relationship_type, context: Some(fact.fact.clone()), importance: Some(fact.confidence) };
+                        let mut person = build_person(profile, input, now);
+                        link_person_memory(&mut person, memory_id);
+                        profile.relationships.push(person);
+                    }
+                    None => continue,
+                }
+                true
+            }
+            ProfileFact::ImportantDate { event, date } => {
+                let full = profile.important_dates.len() >= MAX_IMPORTANT_DATES;
+                match profile.important_dates.iter().position(|e| e.event.eq_ignore_ascii_case(&event)) {
+                    Some(i) if profile.important_dates[i].date == date => continue,
+                    Some(i) => {
+                        let existing = &mut profile.important_dates[i];
+                        existing.date = date;
+                        existing.reminded_until = 0;
+                    }
+                    None if full => continue,
+                    None => {
+                        let input = ImportantDateInput {
+                            event,
+                            date,
+                            category: None,
+                            importance: Some(fact.confidence),
+                            recurrence: None,
+                            lead_days: None,
+                        };
+                        match build_important_event(profile, input) {
+                            Ok(event) => profile.important_dates.push(event),
+                            Err(_) => continue,
+                        }
+                    }
+                }
+                true
+            }
+            ProfileFact::Goal { goal, target_date } => {
+                let known = profile.goals.iter().any(|g| text_similarity(&g.goal, &goal) >= DUPLICATE_TEXT_SIMILARITY);
+                if known || profile.goals.len() >= MAX_GOALS {
+                    continue;
+                }
+                let input = GoalInput { goal, category: None, target_date, importance: Some(fact.confidence), milestones: Vec::new() };
+                match build_goal(profile, input, now) {
+                    Ok(goal) => profile.goals.push(goal),
+                    Err(_) => continue,
+                }
+                true
+            }
+        };
+        if updated {
+            record_provenance(profile, field, ProfileSource::Extracted, fact.confidence, memory_id, now);
+            changed = true;
+        }
+    }
+    changed
+}
+
+// Entities
+
+const MAX_ENTITY_THREADS: usize = 20;
+const MAX_ENTITY_MESSAGES: usize = 50;
+const MAX_THREAD_ENTITIES: usize = 50;
+const DEFAULT_THREAD_ID: &str = "default";
+
+const COMPANY_SUFFIXES: [&str; 10] = ["inc", "corp", "corporation", "ltd", "llc", "co", "gmbh", "labs", "technologies", "group"];
+const LOCATION_CUES: [&str; 7] = ["in", "from", "to", "near", "visiting", "visit", "around"];
+const PERSON_CUES: [&str; 7] = ["with", "met", "told", "asked", "thanks", "thank", "called"];
+const WEEKDAY_NAMES: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+// Lowercase spelling -> display name
+const TECHNOLOGY_TERMS: [(&str, &str); 32] = [
+    ("rust", "Rust"), ("python", "Python"), ("javascript", "JavaScript"), ("typescript", "TypeScript"),
+    ("java", "Java"), ("golang", "Go"), ("c++", "C++"), ("c#", "C#"), ("kotlin", "Kotlin"), ("swift", "Swift"),
+    ("react", "React"), ("vue", "Vue"), ("angular", "Angular"), ("node.js", "Node.js"), ("nodejs", "Node.js"),
+    ("django", "Django"), ("kubernetes", "Kubernetes"), ("docker", "Docker"), ("aws", "AWS"), ("azure", "Azure"),
+    ("gcp", "GCP"), ("postgresql", "PostgreSQL"), ("postgres", "PostgreSQL"), ("mysql", "MySQL"),
+    ("mongodb", "MongoDB"), ("sql", "SQL"), ("graphql", "GraphQL"), ("motoko", "Motoko"), ("icp", "ICP"),
+    ("solidity", "Solidity"), ("tensorflow", "TensorFlow"), ("pytorch", "PyTorch"),
+];
+
+// Registry key: normalized name without company suffixes, so "Acme Corp" and "Acme" share one entry
+fn entity_key(name: &str) -> String {
+    let normalized = normalize_tag(name).unwrap_or_default();
+    let words: Vec<&str> = normalized.split(' ').collect();
+    match words.split_last() {
+        Some((last, rest)) if !rest.is_empty() && COMPANY_SUFFIXES.contains(&last.trim_end_matches('.')) => rest.join(" "),
+        _ => normalized,
+    }
+}
+
+// People, companies, projects, technologies, locations and dates mentioned in a message
+fn extract_entities(message: &str, now: u64) -> Vec<Entity> {
+    let mut entities: Vec<Entity> = Vec::new();
+    let context = truncate_chars(message, 200);
+    let push = |entities: &mut Vec<Entity>, name: String, entity_type: EntityType| {
+        let key = entity_key(&name);
+        if !key.is_empty() && !entities.iter().any(|e| entity_key(&e.name) == key) {
+            entities.push(Entity { name, entity_type, context: context.clone() });
+        }
+    };
+    
+    if let Some((name, _)) = extract_relationship(message) {
+        push(&mut entities, name, EntityType::Person);
+    }
+    
+    let raw_words: Vec<&str> = message.split_whitespace().collect();
+    let clean = |raw: &str| raw.trim_matches(|c: char| !c.is_alphanumeric() && c != '+' && c != '#').to_string();
+    let lower_words: Vec<String> = raw_words.iter().map(|raw| clean(raw).to_lowercase()).collect();
+    
+    // Dates: ISO dates and "March 5" / "5th of March" style phrases
+    for i in 0..raw_words.len() {
+        let starts_date = MONTH_NAMES.iter().any(|month| lower_words[i].len() >= 3 && month.starts_with(lower_words[i].as_str()))
+            || (lower_words[i].starts_with(|c: char| c.is_ascii_digit()) && lower_words.get(i + 1).is_some_and(|next| {
+                MONTH_NAMES.iter().any(|month| next.len() >= 3 && month.starts_with(next.as_str())) || next == "of"
+            }))
+            || lower_words[i].split('-').count() == 3;
+        if !starts_date {
+            continue;
+        }
+        let phrase = raw_words[i..(i + 4).min(raw_words.len())].join(" ");
+        if let Some(date) = parse_date(&phrase, now) {
+            let (year, month, day) = civil_from_days(date.div_euclid(NANOS_PER_DAY as i64));
+            push(&mut entities, format!("{:04}-{:02}-{:02}", year, month, day), EntityType::Date);
+        }
+    }
+    
+    for word in &lower_words {
+        if let Some((_, display)) = TECHNOLOGY_TERMS.iter().find(|(term, _)| term == word) {
+            push(&mut entities, display.to_string(), EntityType::Technology);
+        }
+    }
+    
+    // Capitalized phrases, classified by the words around them
+    let mut i = 0;
+    while i < raw_words.len() {
+        let sentence_start = i == 0 || raw_words[i - 1].ends_with(['.', '!', '?']);
+        let word = clean(raw_words[i]);
+        let skip = !is_capitalized(&word)
+            || word == "I"
+            || STOP_WORDS.contains(&lower_words[i].as_str())
+            || MONTH_NAMES.contains(&lower_words[i].as_str())
+            || WEEKDAY_NAMES.contains(&lower_words[i].as_str());
+        if skip {
+            i += 1;
+            continue;
+        }
+        
+        let start = i;
+        let mut end = i + 1;
+        while end < raw_words.len()
+            && !raw_words[end - 1].ends_with([',', ';', ':', '.', '!', '?'])
+            && is_capitalized(&clean(raw_words[end]))
+            && !STOP_WORDS.contains(&lower_words[end].as_str())
+        {
+            end += 1;
+        }
+        i = end;
+        
+        // A lone capitalized word opening a sentence is usually just capitalization
+        if sentence_start && end - start == 1 {
+            continue;
+        }
+        let name = raw_words[start..end].iter().map(|raw| clean(raw)).collect::<Vec<_>>().join(" ");
+        if TECHNOLOGY_TERMS.iter().any(|(term, _)| *term == name.to_lowercase()) {
+            continue;
+        }
+        
+        let previous = start.checked_sub(1).map(|p| lower_words[p].as_str()).unwrap_or("");
+        let before_previous = start.checked_sub(3).map(|p| &lower_words[p..start]).unwrap_or(&lower_words[..start]);
+        let next = lower_words.get(end).map(String::as_str).unwrap_or("");
+        let last = lower_words[end - 1].as_str();
+        
+        let works_there = ["at", "for", "joined"].contains(&previous)
+            && before_previous.iter().any(|w| w.starts_with("work") || w == "joined" || w == "job" || w == "intern");
+        let entity_type = if (COMPANY_SUFFIXES.contains(&last) && end - start > 1) || works_there {
+            EntityType::Company
+        } else if previous == "project" || next == "project" || lower_words[start] == "project" || last == "project" {
+            EntityType::Project
+        } else if LOCATION_CUES.contains(&previous) {
+            EntityType::Location
+        } else if PERSON_CUES.contains(&previous) || next == "said" || next == "says" {
+            EntityType::Person
+        } else {
+            EntityType::Other
+        };
+        push(&mut entities, name, entity_type);
+    }
+    entities
+}
+
+// Registry entry an extracted name refers to: the same key, a known alias, a first name of a known
+// person, or the acronym of a known multi-word name
+fn resolve_entity(kg: &PersonalKnowledgeGraph, entity: &Entity) -> Option<String> {
+    let key = entity_key(&entity.name);
+    if kg.entities.contains_key(&key) {
+        return Some(key);
+    }
+    kg.entities.iter()
+        .find(|(existing_key, record)| {
+            record.aliases.iter().any(|alias| entity_key(alias) == key)
+                || (record.entity_type == entity.entity_type && entity.entity_type == EntityType::Person && {
+                    let (shorter, longer) = if key.len() < existing_key.len() { (&key, *existing_key) } else { (*existing_key, &key) };
+                    longer.starts_with(&format!("{} ", shorter))
+                })
+                || (entity.name.len() >= 2 && entity.name.len() <= 5 && entity.name.chars().all(|c| c.is_ascii_uppercase()) && {
+                    let initials: String = existing_key.split(' ').filter_map(|w| w.chars().next()).collect();
+                    existing_key.contains(' ') && initials == key
+                })
+        })
+        .map(|(existing_key, _)| existing_key.clone())
+}
+
+// Adds the message's entities to the registry (creating entity nodes as needed) and links the
+// memories stored from the same message to them
+fn record_entities(state: &mut State, user: Principal, entities: &[Entity], memory_ids: &[String], thread_id: &str) {
+    let now = ic_cdk::api::time();
+    for entity in entities {
+        let existing = match state.personal_knowledge_graphs.get(&user) {
+            Some(kg) => resolve_entity(kg, entity),
+            None => return,
+        };
+        let key = match existing {
+            Some(key) => key,
+            None => {
+                let node_id = allocate_memory_id(state, user);
+                let mut node = MemoryNode::new(node_id.clone(), entity.name.clone(), MemoryNodeType::Entity, 0.5);
+                node.tags = vec![format!("{:?}", entity.entity_type).to_lowercase()];
+                insert_memory_node(state, user, node);
+                let key = entity_key(&entity.name);
+                if let Some(kg) = state.personal_knowledge_graphs.get_mut(&user) {
+                    kg.entities.insert(key.clone(), EntityRecord {
+                        name: entity.name.clone(),
+                        entity_type: entity.entity_type.clone(),
+                        aliases: Vec::new(),
+                        node_id,
+                        mention_count: 0,
+                        first_seen: now,
+                        last_seen: now,
+                        last_context: String::new(),
+                        thread_ids: Vec::new(),
+                        message_timestamps: Vec::new(),
+                    });
+                }
+                key
+            }
+        };
+        
+        let record = match state.personal_knowledge_graphs.get_mut(&user).and_then(|kg| kg.entities.get_mut(&key)) {
+            Some(record) => record,
+            None => continue,
+        };
+        if !record.name.eq_ignore_ascii_case(&entity.name) && !record.aliases.iter().any(|a| a.eq_ignore_ascii_case(&entity.name)) {
+            record.aliases.push(entity.name.clone());
+        }
+        record.mention_count = record.mention_count.saturating_add(1);
+        record.last_seen = now;
+        record.last_context = entity.context.clone();
+        if !record.thread_ids.iter().any(|t| t == thread_id) && record.thread_ids.len() < MAX_ENTITY_THREADS {
+            record.thread_ids.push(thread_id.to_string());
+        }
+        if record.message_timestamps.last() != Some(&now) {
+            record.message_timestamps.push(now);
+            let excess = record.message_timestamps.len().saturating_sub(MAX_ENTITY_MESSAGES);
+            record.message_timestamps.drain(..excess);
+        }
+        
+        let entity_node = record.node_id.clone();
+        for memory_id in memory_ids {
+            add_knowledge_edge(state, user, KnowledgeEdge {
+                from_node: memory_id.clone(),
+                to_node: entity_node.clone(),
+                relationship_type: RelationshipType::Mentions,
+                strength: 1.0,
+                created_at: now,
+            });
+        }
+    }
+}
+
+// The thread's ConversationContext, created with a topic taken from `message` if it is new
+fn thread_context<'a>(kg: &'a mut PersonalKnowledgeGraph, thread_id: &str, message: &str, now: u64) -> &'a mut ConversationContext {
+    kg.context_threads.entry(thread_id.to_string()).or_insert_with(|| ConversationContext {
+        thread_id: thread_id.to_string(),
+        topic: extract_topic_from_prompt(message),
+        related_memories: Vec::new(),
+        user_sentiment: Sentiment::Neutral,
+        ongoing_tasks: Vec::new(),
+        mentioned_entities: Vec::new(),
+        last_message_timestamp: now,
+    })
+}
+
+// Keeps the thread's ConversationContext current: topic, memories it used, entities it mentioned
+// and the user's latest sentiment
+fn update_conversation_context(
+    kg: &mut PersonalKnowledgeGraph,
+    thread_id: &str,
+    message: &str,
+    entities: &[Entity],
+    related_memories: &[String],
+    sentiment: &Sentiment,
+) {
+    let now = ic_cdk::api::time();
+    let context = thread_context(kg, thread_id, message, now);
+    for memory_id in related_memories {
+        if !context.related_memories.contains(memory_id) {
+            context.related_memories.push(memory_id.clone());
+        }
+    }
+    for entity in entities {
+        context.mentioned_entities.retain(|e| !e.name.eq_ignore_ascii_case(&entity.name));
+        context.mentioned_entities.push(entity.clone());
+    }
+    let excess = context.mentioned_entities.len().saturating_sub(MAX_THREAD_ENTITIES);
+    context.mentioned_entities.drain(..excess);
+    context.user_sentiment = sentiment.clone();
+    context.last_message_timestamp = now;
+}
+
+#[ic_cdk::query]
+fn list_entities(user: Principal, entity_type: Option<EntityType>) -> Vec<EntityRecord> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Vec::new();
+    }
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let mut entities: Vec<EntityRecord> = state.personal_knowledge_graphs.get(&user)
+            .map(|kg| kg.entities.values()
+                .filter(|record| entity_type.as_ref().is_none_or(|t| &record.entity_type == t))
+                .cloned()
+                .collect())
+            .unwrap_or_default();
+        entities.sort_by(|a, b| b.mention_count.cmp(&a.mention_count).then(a.name.cmp(&b.name)));
+        entities
+    })
+}
+
+// An entity (looked up by name or alias) with the memories that mention it and the messages it came up in
+#[ic_cdk::query]
+fn get_entity(user: Principal, name: String) -> Result<EntityDetail, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let kg = state.personal_knowledge_graphs.get(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let lookup = Entity { name: name.clone(), entity_type: EntityType::Other, context: String::new() };
+        let entity = resolve_entity(kg, &lookup)
+            .and_then(|key| kg.entities.get(&key))
+            .ok_or_else(|| format!("Entity '{}' not found", name))?
+            .clone();
+        
+        let memories = memory_index_for(&state, user).adjacency.get(&entity.node_id).into_iter().flatten()
+            .filter(|a| !a.outgoing && a.relationship_type == RelationshipType::Mentions)
+            .filter_map(|a| kg.memory_nodes.get(&a.neighbor).cloned())
+            .collect();
+        let conversations = state.conversations.get(&user)
+            .map(|messages| messages.iter()
+                .filter(|message| entity.message_timestamps.contains(&message.timestamp))
+                .cloned()
+                .collect())
+            .unwrap_or_default();
+        Ok(EntityDetail { entity, memories, conversations })
+    })
+}
+
+// Sentiment
+
+const SENTIMENT_MODEL_THRESHOLD: f32 = 0.6; // below this lexicon confidence the model is asked, when enabled
+
+const POSITIVE_WORDS: [&str; 16] = [
+    "good", "great", "thanks", "thank", "love", "nice", "happy", "glad",
+    "helpful", "perfect", "appreciate", "works", "worked", "solved", "pleased", "enjoyed",
+];
+const NEGATIVE_WORDS: [&str; 16] = [
+    "bad", "sad", "wrong", "hate", "terrible", "awful", "worried", "upset",
+    "tired", "unhappy", "disappointed", "stressed", "anxious", "worse", "worst", "lonely",
+];
+const FRUSTRATION_PHRASES: [&str; 18] = [
+    "doesn't work", "does not work", "not working", "still broken", "still not", "annoying",
+    "frustrated", "frustrating", "ugh", "waste of time", "useless", "fed up", "sick of",
+    "give up", "makes no sense", "why won't", "already told you", "for the last time",
+];
+const EXCITEMENT_PHRASES: [&str; 10] = [
+    "can't wait", "so excited", "excited", "amazing", "awesome", "fantastic", "incredible", "thrilled", "wow", "yay",
+];
+const CURIOSITY_PHRASES: [&str; 9] = [
+    "wonder", "wondering", "curious", "how does", "how do", "why does", "what if", "tell me about", "explain",
+];
+const NEGATORS: [&str; 12] = [
+    "not", "no", "never", "don't", "doesn't", "didn't", "isn't", "wasn't", "aren't", "can't", "won't", "hardly",
+];
+
+// Lexicon classification of a user turn; the confidence grows with the number of cues that agree.
+// Frustration wins over everything else, and a negated sentiment word ("not happy") flips polarity.
+fn classify_sentiment(text: &str) -> (Sentiment, f32) {
+    let lower = text.to_lowercase().replace('\u{2019}', "'");
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|w| !w.is_empty())
+        .collect();
+    let joined = format!(" {} ", words.join(" "));
+    let count = |phrases: &[&str]| phrases.iter().filter(|p| joined.contains(&format!(" {} ", p))).count();
+    
+    let frustration = count(&FRUSTRATION_PHRASES);
+    let mut excitement = count(&EXCITEMENT_PHRASES);
+    let curiosity = count(&CURIOSITY_PHRASES) + usize::from(text.trim_end().ends_with('?'));
+    let (mut positive, mut negative) = (0, 0);
+    for (i, word) in words.iter().enumerate() {
+        let negated = words[i.saturating_sub(2)..i].iter().any(|w| NEGATORS.contains(w));
+        if POSITIVE_WORDS.contains(word) {
+            if negated { negative += 1 } else { positive += 1 }
+        } else if NEGATIVE_WORDS.contains(word) && !negated {
+            negative += 1;
+        }
+    }
+    if text.contains("!!") && positive + excitement > 0 {
+        excitement += 1;
+    }
+    
+    let confidence = |cues: usize| (0.5 + 0.15 * cues as f32).min(0.95);
+    if frustration > 0 {
+        (Sentiment::Frustrated, confidence(frustration + negative))
+    } else if excitement > 0 && excitement + positive > negative {
+        (Sentiment::Excited, confidence(excitement + positive))
+    } else if positive > negative {
+        (Sentiment::Positive, confidence(positive - negative))
+    } else if negative > positive {
+        (Sentiment::Negative, confidence(negative - positive))
+    } else if positive > 0 {
+        // As many positive as negative cues: mixed, and worth a second opinion
+        (Sentiment::Neutral, 0.3)
+    } else if curiosity > 0 {
+        (Sentiment::Curious, confidence(curiosity))
+    } else {
+        // No cues at all is the common case and plainly neutral; only mixed cues go to the model
+        (Sentiment::Neutral, 0.7)
+    }
+}
+
+fn parse_sentiment(text: &str) -> Option<Sentiment> {
+    match text.trim().trim_matches(|c: char| !c.is_alphabetic()).to_lowercase().as_str() {
+        "positive" => Some(Sentiment::Positive),
+        "neutral" => Some(Sentiment::Neutral),
+        "negative" => Some(Sentiment::Negative),
+        "excited" => Some(Sentiment::Excited),
+        "frustrated" => Some(Sentiment::Frustrated),
+        "curious" => Some(Sentiment::Curious),
+        _ => None,
+    }
+}
+
+// Lexicon first; the model only sees turns the lexicon is unsure about, and only when enabled
+async fn analyze_sentiment(text: &str) -> Sentiment {
+    let (sentiment, confidence) = classify_sentiment(text);
+    let (enabled, api_key) = STATE.with(|state| {
+        let state = state.borrow();
+        (state.model_sentiment_enabled, state.api_key.clone())
+    });
+    if confidence >= SENTIMENT_MODEL_THRESHOLD || !enabled || api_key.is_empty() {
+        return sentiment;
+    }
+    
+    let prompt = format!(
+        "Classify the sentiment of this message as exactly one of: Positive, Neutral, Negative, Excited, Frustrated, Curious. \
+         Answer with that single word and nothing else.\n\nMessage: {}",
+        truncate_chars(text, 500),
+    );
+    match call_gemini_api(prompt, api_key).await {
+        Ok(reply) => parse_sentiment(&reply).unwrap_or(sentiment),
+        Err(e) => {
+            ic_cdk::println!("Model sentiment failed: {}", e);
+            sentiment
+        }
+    }
+}
+
+// Extra instruction for the contextual response template
+fn tone_guidance(sentiment: &Sentiment) -> &'static str {
+    match sentiment {
+        Sentiment::Frustrated => {
+            "The user sounds frustrated. Be concise and supportive: acknowledge the problem in one sentence, \
+             skip small talk and lead with the most direct fix or next step."
+        }
+        Sentiment::Negative => "The user seems to be having a hard time. Be warm and supportive, and keep the reply focused.",
+        Sentiment::Excited => "The user is excited. Share their enthusiasm while staying helpful.",
+        Sentiment::Curious => "The user is curious. Explain the reasoning behind your answer and suggest something to explore next.",
+        Sentiment::Positive | Sentiment::Neutral => "",
+    }
+}
+
+#[ic_cdk::update]
+fn set_model_sentiment(enabled: bool) -> Result<String, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only a controller can change model sentiment analysis".to_string());
+    }
+    STATE.with(|state| state.borrow_mut().model_sentiment_enabled = enabled);
+    Ok(format!("Model-assisted sentiment analysis {}", if enabled { "enabled" } else { "disabled" }))
+}
+
+// Sentiment trends
+
+const DEFAULT_TREND_DAYS: u64 = 30;
+const SENTIMENT_SHIFT_THRESHOLD: f32 = 0.5; // change in average score between neighbouring buckets
+const MIN_SHIFT_MESSAGES: u32 = 3; // per bucket, so one bad message is not a shift
+const CHECK_IN_WINDOW_DAYS: u64 = 14;
+const CHECK_IN_COOLDOWN_NANOS: u64 = 3 * NANOS_PER_DAY;
+
+fn sentiment_score(sentiment: &Sentiment) -> f32 {
+    match sentiment {
+        Sentiment::Positive | Sentiment::Excited => 1.0,
+        Sentiment::Curious => 0.3,
+        Sentiment::Neutral => 0.0,
+        Sentiment::Negative | Sentiment::Frustrated => -1.0,
+    }
+}
+
+impl SentimentCounts {
+    fn add(&mut self, sentiment: &Sentiment) {
+        match sentiment {
+            Sentiment::Positive => self.positive += 1,
+            Sentiment::Neutral => self.neutral += 1,
+            Sentiment::Negative => self.negative += 1,
+            Sentiment::Excited => self.excited += 1,
+            Sentiment::Frustrated => self.frustrated += 1,
+            Sentiment::Curious => self.curious += 1,
+        }
+    }
+    
+    fn entries(&self) -> [(Sentiment, u32); 6] {
+        [
+            (Sentiment::Positive, self.positive),
+            (Sentiment::Neutral, self.neutral),
+            (Sentiment::Negative, self.negative),
+            (Sentiment::Excited, self.excited),
+            (Sentiment::Frustrated, self.frustrated),
+            (Sentiment::Curious, self.curious),
+        ]
+    }
+    
+    fn total(&self) -> u32 {
+        self.entries().iter().map(|(_, count)| count).sum()
+    }
+    
+    fn average_score(&self) -> f32 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        self.entries().iter().map(|(sentiment, count)| sentiment_score(sentiment) * *count as f32).sum::<f32>() / total as f32
+    }
+    
+    // Most frequent sentiment; ties go to the earlier variant
+    fn dominant(&self) -> Sentiment {
+        let mut best = (Sentiment::Neutral, 0);
+        for (sentiment, count) in self.entries() {
+            if count > best.1 {
+                best = (sentiment, count);
+            }
+        }
+        best.0
+    }
+}
+
+fn bucket_start(timestamp: u64, bucket: &TrendBucket) -> u64 {
+    let days = timestamp / NANOS_PER_DAY;
+    match bucket {
+        TrendBucket::Day => days * NANOS_PER_DAY,
+        // 1970-01-01 was a Thursday, three days after a Monday
+        TrendBucket::Week => days.saturating_sub((days + 3) % 7) * NANOS_PER_DAY,
+    }
+}
+
+fn shift_suggestion(change: f32, dominant: &Sentiment) -> String {
+    let text = if change > 0.0 {
+        "Things seem to be going better lately. Is there anything good happening you'd like me to remember?"
+    } else if matches!(dominant, Sentiment::Frustrated) {
+        "A few things seem to have been frustrating lately. Is there something I can help untangle?"
+    } else {
+        "You've seemed a bit down recently. How are you doing?"
+    };
+    text.to_string()
+}
+
+// Buckets the sentiment recorded on each turn by time and by thread, and flags neighbouring
+// buckets whose average score moved by at least SENTIMENT_SHIFT_THRESHOLD
+fn sentiment_trends(
+    messages: &[EnhancedChatMessage],
+    threads: &HashMap<String, ConversationContext>,
+    range: &TimeRange,
+    bucket: &TrendBucket,
+) -> SentimentTrends {
+    let mut by_bucket: BTreeMap<u64, SentimentCounts> = BTreeMap::new();
+    let mut by_thread: HashMap<String, (SentimentCounts, u64)> = HashMap::new();
+    for message in messages.iter().filter(|m| m.timestamp >= range.start && m.timestamp <= range.end) {
+        let sentiment = match &message.user_sentiment {
+            Some(sentiment) => sentiment,
+            None => continue,
+        };
+        by_bucket.entry(bucket_start(message.timestamp, bucket)).or_default().add(sentiment);
+        let thread_id = message.context_thread_id.clone().unwrap_or_else(|| DEFAULT_THREAD_ID.to_string());
+        let (counts, last_message_at) = by_thread.entry(thread_id).or_default();
+        counts.add(sentiment);
+        *last_message_at = (*last_message_at).max(message.timestamp);
+    }
+    
+    let buckets: Vec<SentimentBucket> = by_bucket.into_iter()
+        .map(|(start, counts)| SentimentBucket {
+            start,
+            average_score: counts.average_score(),
+            dominant: counts.dominant(),
+            counts,
+        })
+        .collect();
+    
+    let shifts = buckets.windows(2)
+        .filter(|pair| pair.iter().all(|b| b.counts.total() >= MIN_SHIFT_MESSAGES))
+        .filter_map(|pair| {
+            let change = pair[1].average_score - pair[0].average_score;
+            (change.abs() >= SENTIMENT_SHIFT_THRESHOLD).then(|| SentimentShift {
+                bucket_start: pair[1].start,
+                previous_score: pair[0].average_score,
+                score: pair[1].average_score,
+                change,
+                suggestion: shift_suggestion(change, &pair[1].dominant),
+            })
+        })
+        .collect();
+    
+    let mut threads: Vec<ThreadSentiment> = by_thread.into_iter()
+        .map(|(thread_id, (counts, last_message_at))| ThreadSentiment {
+            topic: threads.get(&thread_id).map(|c| c.topic.clone()).unwrap_or_default(),
+            thread_id,
+            average_score: counts.average_score(),
+            dominant: counts.dominant(),
+            counts,
+            last_message_at,
+        })
+        .collect();
+    threads.sort_by(|a, b| b.last_message_at.cmp(&a.last_message_at).then(a.thread_id.cmp(&b.thread_id)));
+    
+    SentimentTrends { buckets, threads, shifts }
+}
+
+// A check-in suggestion when the user's mood dropped over the last day or two. Taking it starts
+// a cooldown so the assistant does not keep asking.
+fn take_sentiment_check_in(state: &mut State, user: Principal, now: u64) -> Option<String> {
+    let kg = state.personal_knowledge_graphs.get(&user)?;
+    if kg.last_sentiment_check_in > 0 && now.saturating_sub(kg.last_sentiment_check_in) < CHECK_IN_COOLDOWN_NANOS {
+        return None;
+    }
+    let range = TimeRange { start: now.saturating_sub(CHECK_IN_WINDOW_DAYS * NANOS_PER_DAY), end: now };
+    let trends = sentiment_trends(state.conversations.get(&user)?, &kg.context_threads, &range, &TrendBucket::Day);
+    let shift = trends.shifts.last()
+        .filter(|shift| shift.change < 0.0 && shift.bucket_start >= bucket_start(now, &TrendBucket::Day).saturating_sub(NANOS_PER_DAY))?;
+    let suggestion = shift.suggestion.clone();
+    state.personal_knowledge_graphs.get_mut(&user)?.last_sentiment_check_in = now;
+    Some(suggestion)
+}
+
+// Sentiment over time (default: the last 30 days) and per thread, with notable shifts
+#[ic_cdk::query]
+fn get_sentiment_trends(user: Principal, range: Option<TimeRange>, bucket: TrendBucket) -> Result<SentimentTrends, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    let now = ic_cdk::api::time();
+    let range = range.unwrap_or(TimeRange { start: now.saturating_sub(DEFAULT_TREND_DAYS * NANOS_PER_DAY), end: now });
+    if range.start > range.end {
+        return Err("Range start must not be after its end".to_string());
+    }
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let no_threads = HashMap::new();
+        let threads = state.personal_knowledge_graphs.get(&user).map(|kg| &kg.context_threads).unwrap_or(&no_threads);
+        let messages = state.conversations.get(&user).map(Vec::as_slice).unwrap_or(&[]);
+        Ok(sentiment_trends(messages, threads, &range, &bucket))
+    })
+}
+
+// Learning patterns
+
+const SESSION_GAP_NANOS: u64 = 30 * 60 * 1_000_000_000; // inactivity that ends a session
+const NANOS_PER_HOUR: u64 = 60 * 60 * 1_000_000_000;
+const LEARNING_SPEED_SMOOTHING: f32 = 0.1;
+const MAX_COMMON_TOPICS: usize = 5;
+const MAX_PEAK_HOURS: usize = 3;
+
+// Topic -> words that place a message in it (matched after stemming)
+const TOPIC_KEYWORDS: [(&str, &[&str]); 12] = [
+    ("programming", &["code", "coding", "programming", "bug", "debug", "function", "compiler", "api", "rust", "python", "javascript", "database", "deploy", "git"]),
+    ("work", &["work", "job", "career", "boss", "manager", "meeting", "colleague", "office", "interview", "promotion", "salary"]),
+    ("health", &["health", "doctor", "sick", "sleep", "diet", "medicine", "symptom", "therapy", "stress", "anxiety"]),
+    ("fitness", &["workout", "gym", "exercise", "running", "training", "yoga", "marathon", "fitness"]),
+    ("finance", &["money", "budget", "invest", "investment", "savings", "tax", "loan", "mortgage", "stock"]),
+    ("crypto", &["crypto", "bitcoin", "ckbtc", "blockchain", "icp", "canister", "token", "wallet", "ethereum"]),
+    ("travel", &["travel", "trip", "flight", "hotel", "vacation", "holiday", "visa", "itinerary"]),
+    ("food", &["recipe", "cooking", "dinner", "lunch", "breakfast", "restaurant", "food", "meal", "bake"]),
+    ("learning", &["learn", "study", "course", "exam", "university", "school", "homework", "tutorial"]),
+    ("family", &["family", "wife", "husband", "partner", "kids", "son", "daughter", "mom", "dad", "parents", "friend"]),
+    ("entertainment", &["movie", "film", "series", "book", "novel", "game", "gaming", "music", "song", "album"]),
+    ("productivity", &["planning", "schedule", "todo", "habit", "productivity", "focus", "deadline", "organize"]),
+];
+
+fn classify_topics(message: &str) -> Vec<&'static str> {
+    let words: HashSet<String> = tokenize(message).into_iter().collect();
+    TOPIC_KEYWORDS.iter()
+        .filter(|(_, keywords)| keywords.iter().any(|keyword| words.contains(&stem(keyword))))
+        .map(|(topic, _)| *topic)
+        .collect()
+}
+
+// Kind of question a message asks, or None when it is not a question or request
+fn question_type(message: &str) -> Option<&'static str> {
+    let lower = message.trim().to_lowercase();
+    let starts = |prefixes: &[&str]| prefixes.iter().any(|p| lower.starts_with(p));
+    let has = |phrases: &[&str]| phrases.iter().any(|p| lower.contains(p));
+    
+    if has(&["difference between", " vs ", " versus ", "better than", "compared to"]) {
+        Some("comparison")
+    } else if starts(&["how do", "how to", "how can", "how should"]) {
+        Some("how_to")
+    } else if starts(&["why"]) || has(&["explain"]) {
+        Some("explanation")
+    } else if starts(&["what is", "what are", "what's", "what does", "define"]) {
+        Some("definition")
+    } else if starts(&["should i", "which"]) || has(&["recommend", "suggest", "what's the best", "what is the best"]) {
+        Some("recommendation")
+    } else if starts(&["can you", "could you", "would you", "please", "help me"]) {
+        Some("request")
+    } else if starts(&["who", "when", "where", "what", "how"]) {
+        Some("factual")
+    } else if starts(&["is ", "are ", "do ", "does ", "did ", "can ", "will ", "would ", "could "]) || lower.ends_with('?') {
+        Some("yes_no")
+    } else {
+        None
+    }
+}
+
+// Folds one user turn into the learning history and conversation patterns: topics, question
+// types, hour-of-day activity and sessions (split by SESSION_GAP_NANOS of inactivity)
+fn record_interaction_patterns(kg: &mut PersonalKnowledgeGraph, message: &str, learned: usize, now: u64) {
+    let history = &mut kg.learning_patterns;
+    history.interaction_count += 1;
+    let n = history.interaction_count as f32;
+    
+    for topic in classify_topics(message) {
+        *history.topics_discussed.entry(topic.to_string()).or_insert(0) += 1;
+    }
+    
+    let question = question_type(message);
+    let asked = if question.is_some() { 1.0 } else { 0.0 };
+    history.question_asking_frequency += (asked - history.question_asking_frequency) / n;
+    history.learning_speed = if n <= 1.0 {
+        learned as f32
+    } else {
+        history.learning_speed + LEARNING_SPEED_SMOOTHING * (learned as f32 - history.learning_speed)
+    };
+    
+    let patterns = &mut kg.user_profile.conversation_patterns;
+    if let Some(question) = question {
+        *patterns.question_types.entry(question.to_string()).or_insert(0) += 1;
+    }
+    let mut topics: Vec<(&String, &u32)> = history.topics_discussed.iter().collect();
+    topics.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    patterns.common_topics = topics.into_iter().take(MAX_COMMON_TOPICS).map(|(topic, _)| topic.clone()).collect();
+    
+    patterns.time_patterns.resize(24, 0);
+    patterns.time_patterns[((now / NANOS_PER_HOUR) % 24) as usize] += 1;
+    
+    if patterns.session_count == 0 || now.saturating_sub(patterns.last_active_at) > SESSION_GAP_NANOS {
+        if patterns.session_count > 0 {
+            patterns.completed_session_nanos += patterns.last_active_at - patterns.session_started_at;
+        }
+        patterns.session_count += 1;
+        patterns.session_started_at = now;
+    }
+    patterns.last_active_at = now;
+    let total_nanos = patterns.completed_session_nanos + (now - patterns.session_started_at);
+    patterns.avg_session_length = total_nanos as f32 / patterns.session_count as f32 / 60_000_000_000.0;
+}
+
+fn count_entries(counts: &HashMap<String, u32>) -> Vec<TopicCount> {
+    let mut entries: Vec<TopicCount> = counts.iter()
+        .map(|(topic, count)| TopicCount { topic: topic.clone(), count: *count })
+        .collect();
+    entries.sort_by(|a, b| b.count.cmp(&a.count).then(a.topic.cmp(&b.topic)));
+    entries
+}
+
+// What the canister has learned about how the user interacts
+#[ic_cdk::query]
+fn get_learning_insights(user: Principal) -> Result<LearningInsights, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let kg = state.personal_knowledge_graphs.get(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let history = &kg.learning_patterns;
+        let patterns = &kg.user_profile.conversation_patterns;
+        
+        let mut hourly_activity = patterns.time_patterns.clone();
+        hourly_activity.resize(24, 0);
+        let mut hours: Vec<u32> = (0..24).filter(|&hour| hourly_activity[hour as usize] > 0).collect();
+        hours.sort_by(|a, b| hourly_activity[*b as usize].cmp(&hourly_activity[*a as usize]).then(a.cmp(b)));
+        hours.truncate(MAX_PEAK_HOURS);
+        
+        Ok(LearningInsights {
+            interaction_count: history.interaction_count,
+            top_topics: count_entries(&history.topics_discussed),
+            question_types: count_entries(&patterns.question_types),
+            question_asking_frequency: history.question_asking_frequency,
+            learning_speed: history.learning_speed,
+            hourly_activity,
+            peak_hours: hours,
+            session_count: patterns.session_count,
+            avg_session_minutes: patterns.avg_session_length,
+        })
+    })
+}
+
+// Domain expertise
+
+const EXPERTISE_CONFIDENCE_GAIN: f32 = 0.15; // share of the remaining uncertainty each observation removes
+const MIN_EXPERTISE_WEIGHT: f32 = 0.1; // settled domains still move a little with new evidence
+
+const BEGINNER_CUES: [&str; 11] = [
+    "new to", "beginner", "eli5", "explain like", "simple terms", "don't understand",
+    "confused", "just started", "never used", "first time", "basics",
+];
+const ADVANCED_CUES: [&str; 16] = [
+    "trade-off", "tradeoff", "edge case", "benchmark", "optimiz", "architecture", "implementation",
+    "under the hood", "internals", "latency", "throughput", "complexity", "idiomatic", "best practice",
+    "scalab", "concurrency",
+];
+
+// Evidence of expertise in one domain from a single message, 0 (novice) to 1 (expert): beginner
+// and advanced vocabulary, what kind of question it is and how densely it uses the domain's terms
+fn expertise_signal(message: &str, domain: &str) -> f32 {
+    let lower = message.to_lowercase().replace('\u{2019}', "'");
+    let count = |cues: &[&str]| cues.iter().filter(|cue| lower.contains(*cue)).count() as f32;
+    
+    let mut signal = 0.5 - 0.3 * count(&BEGINNER_CUES) + 0.15 * count(&ADVANCED_CUES);
+    if EXPERTISE_PHRASES.iter().any(|phrase| lower.contains(phrase)) {
+        signal += 0.4;
+    }
+    signal += match question_type(message) {
+        Some("definition") => -0.15,
+        Some("how_to") => -0.05,
+        Some("comparison") => 0.1,
+        None => 0.05,
+        Some(_) => 0.0,
+    };
+    
+    let words: HashSet<String> = tokenize(message).into_iter().collect();
+    let domain_terms = TOPIC_KEYWORDS.iter()
+        .find(|(topic, _)| *topic == domain)
+        .map(|(_, keywords)| keywords.iter().filter(|keyword| words.contains(&stem(keyword))).count())
+        .unwrap_or(0);
+    if domain_terms >= 3 {
+        signal += 0.1;
+    }
+    signal.clamp(0.05, 0.95)
+}
+
+fn technical_level_for(level: f32) -> TechnicalLevel {
+    match level {
+        l if l < 0.3 => TechnicalLevel::Beginner,
+        l if l < 0.55 => TechnicalLevel::Intermediate,
+        l if l < 0.8 => TechnicalLevel::Advanced,
+        _ => TechnicalLevel::Expert,
+    }
+}
+
+// Moves the level of every domain the message touches toward its evidence. The less confident
+// we are about a domain the further it moves; the global technical level follows the
+// confidence-weighted average across domains.
+fn update_domain_expertise(profile: &mut UserProfile, message: &str) {
+    let domains = classify_topics(message);
+    if domains.is_empty() {
+        return;
+    }
+    for domain in domains {
+        let signal = expertise_signal(message, domain);
+        let confidence = profile.domain_confidence.entry(domain.to_string()).or_insert(0.0);
+        let weight = (1.0 - *confidence).max(MIN_EXPERTISE_WEIGHT);
+        *confidence += (1.0 - *confidence) * EXPERTISE_CONFIDENCE_GAIN;
+        let level = profile.knowledge_domains.entry(domain.to_string()).or_insert(signal);
+        *level += (signal - *level) * weight;
+    }
+    
+    let (weighted, total) = profile.knowledge_domains.iter()
+        .map(|(domain, level)| (level, profile.domain_confidence.get(domain).copied().unwrap_or(0.0)))
+        .fold((0.0, 0.0), |(weighted, total), (level, confidence)| (weighted + level * confidence, total + confidence));
+    let locked = profile.communication_style.locked_fields.iter().any(|field| field == "technical_level");
+    if total > 0.0 && !locked {
+        profile.communication_style.technical_level = technical_level_for(weighted / total);
+    }
+}
+
+// Expertise lines for the prompt: the domains the question touches, then the overall level
+fn expertise_levels_for(profile: &UserProfile, question: &str) -> String {
+    if profile.knowledge_domains.is_empty() {
+        return String::new();
+    }
+    let mut lines: String = classify_topics(question).into_iter()
+        .filter_map(|domain| profile.knowledge_domains.get(domain).map(|level| (domain, *level)))
+        .map(|(domain, level)| format!("- {}: {:?}\n", domain, technical_level_for(level)))
+        .collect();
+    lines.push_str(&format!("- overall: {:?}\n", profile.communication_style.technical_level));
+    lines
+}
+
+// Communication style
+
+const STYLE_SMOOTHING: f32 = 0.2;
+const MIN_STYLE_MESSAGES: u32 = 3; // passive inference waits for a few messages
+const EMOJI_THRESHOLD: f32 = 0.3;
+const HUMOR_THRESHOLD: f32 = 0.2;
+const BRIEF_MESSAGE_WORDS: f32 = 6.0;
+const DETAILED_MESSAGE_WORDS: f32 = 60.0;
+const STYLE_FEEDBACK_CONFIDENCE: f32 = 0.9;
+const DETAIL_FEEDBACK_MESSAGES: u32 = 30; // how long asking for shorter or longer answers outranks message length
+
+const STYLE_FIELDS: [&str; 5] = ["formality_level", "detail_preference", "humor_preference", "technical_level", "emoji_usage"];
+
+const FORMAL_CUES: [&str; 10] = [
+    "dear", "regards", "sincerely", "kindly", "could you please", "would you please", "thank you",
+    "i would appreciate", "furthermore", "therefore",
+];
+const CASUAL_CUES: [&str; 16] = [
+    "hey", "yo", "lol", "gonna", "wanna", "gotta", "yeah", "yep", "nope", "cool", "thx", "btw", "u", "dude", "kinda", "ok",
+];
+const HUMOR_CUES: [&str; 8] = ["lol", "haha", "hahaha", "lmao", "rofl", "jk", "kidding", "hehe"];
+const EMOTICONS: [&str; 5] = [":)", ":-)", ";)", ":D", "<3"];
+
+// Explicit feedback: phrase, style field, target. Detail targets step the level down (-1) or up (1);
+// the others pin the evidence. Each must be a whole directive (see style_directives); earlier entries win.
+const STYLE_FEEDBACK: [(&str, &str, f32); 28] = [
+    ("shorter please", "detail_preference", -1.0),
+    ("be shorter", "detail_preference", -1.0),
+    ("too long", "detail_preference", -1.0),
+    ("be brief", "detail_preference", -1.0),
+    ("keep it short", "detail_preference", -1.0),
+    ("tldr", "detail_preference", -1.0),
+    ("just the answer", "detail_preference", -1.0),
+    ("less detail", "detail_preference", -1.0),
+    ("more detail", "detail_preference", 1.0),
+    ("elaborate", "detail_preference", 1.0),
+    ("too short", "detail_preference", 1.0),
+    ("go deeper", "detail_preference", 1.0),
+    ("explain more", "detail_preference", 1.0),
+    ("less formal", "formality_level", -1.0),
+    ("more casual", "formality_level", -1.0),
+    ("be more formal", "formality_level", 1.0),
+    ("more professional", "formality_level", 1.0),
+    ("don't use emoji", "emoji_usage", 0.0),
+    ("no emoji", "emoji_usage", 0.0),
+    ("stop using emoji", "emoji_usage", 0.0),
+    ("fewer emoji", "emoji_usage", 0.0),
+    ("use emoji", "emoji_usage", 1.0),
+    ("more emoji", "emoji_usage", 1.0),
+    ("no jokes", "humor_preference", 0.0),
+    ("stop joking", "humor_preference", 0.0),
+    ("be serious", "humor_preference", 0.0),
+    ("be funny", "humor_preference", 1.0),
+    ("more jokes", "humor_preference", 1.0),
+];
+
+// Politeness and framing around a feedback phrase that still leave it a directive
+const DIRECTIVE_PREFIXES: [&str; 18] = [
+    "please", "ok", "okay", "hmm", "can you", "could you", "would you", "just", "way", "a bit", "a little",
+    "that's", "that is", "that was", "this is", "this was", "it's", "answers are",
+];
+const DIRECTIVE_SUFFIXES: [&str; 5] = ["please", "thanks", "next time", "from now on", "in future"];
+
+// Sentences of the message with the framing above removed. Feedback phrases only count when they make
+// up a whole sentence ("Too long.", "Could you elaborate?"), so "the build took too long" doesn't.
+fn style_directives(lower: &str) -> Vec<String> {
+    lower.replace("tl;dr", "tldr")
+        .split(['.', '!', '?', ';', '\n'])
+        .map(|sentence| {
+            let mut clause = sentence.replace(',', " ").split_whitespace().collect::<Vec<_>>().join(" ");
+            loop {
+                let stripped = DIRECTIVE_PREFIXES.iter()
+                    .find_map(|prefix| clause.strip_prefix(prefix).and_then(|rest| rest.strip_prefix(' ')))
+                    .or_else(|| DIRECTIVE_SUFFIXES.iter().find_map(|suffix| clause.strip_suffix(suffix).and_then(|rest| rest.strip_suffix(' '))));
+                match stripped {
+                    Some(rest) => clause = rest.to_string(),
+                    None => return clause,
+                }
+            }
+        })
+        .filter(|clause| !clause.is_empty())
+        .collect()
+}
+
+fn contains_emoji(text: &str) -> bool {
+    text.chars().any(|c| matches!(c as u32, 0x1F300..=0x1FAFF | 0x2600..=0x27BF))
+        || EMOTICONS.iter().any(|emoticon| text.contains(emoticon))
+}
+
+fn formality_for(score: f32) -> FormalityLevel {
+    match score {
+        s if s > 0.5 => FormalityLevel::VeryFormal,
+        s if s > 0.15 => FormalityLevel::Formal,
+        s if s > -0.5 => FormalityLevel::Casual,
+        _ => FormalityLevel::Verycasual,
+    }
+}
+
+fn step_detail(level: &DetailLevel, direction: f32) -> DetailLevel {
+    const LEVELS: [DetailLevel; 4] = [DetailLevel::Brief, DetailLevel::Moderate, DetailLevel::Detailed, DetailLevel::Comprehensive];
+    let index = LEVELS.iter().position(|l| l == level).unwrap_or(1);
+    let index = if direction < 0.0 { index.saturating_sub(1) } else { (index + 1).min(LEVELS.len() - 1) };
+    LEVELS[index].clone()
+}
+
+// Updates the style evidence from one message and re-derives every unlocked style field.
+// Returns a LearnedPreference for each field the message changed or explicitly asked about.
+fn learn_communication_style(profile: &mut UserProfile, message: &str) -> Vec<LearnedPreference> {
+    let lower = message.to_lowercase().replace('\u{2019}', "'");
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|w| !w.is_empty())
+        .collect();
+    let joined = format!(" {} ", words.join(" "));
+    let count = |cues: &[&str]| cues.iter().filter(|cue| joined.contains(&format!(" {} ", cue))).count() as f32;
+    let locked = profile.communication_style.locked_fields.clone();
+    let is_locked = |field: &str| locked.iter().any(|f| f == field);
+    let before = profile.communication_style.clone();
+    let evidence = &mut profile.style_evidence;
+    let style = &mut profile.communication_style;
+    
+    evidence.messages += 1;
+    let alpha = if evidence.messages == 1 { 1.0 } else { STYLE_SMOOTHING };
+    let (formal, casual) = (count(&FORMAL_CUES), count(&CASUAL_CUES));
+    if formal + casual > 0.0 {
+        evidence.formality += alpha * ((formal - casual) / (formal + casual) - evidence.formality);
+    }
+    let emoji = if contains_emoji(message) { 1.0 } else { 0.0 };
+    evidence.emoji += alpha * (emoji - evidence.emoji);
+    let humor = if count(&HUMOR_CUES) > 0.0 || message.contains('😂') || message.contains('🤣') { 1.0 } else { 0.0 };
+    evidence.humor += alpha * (humor - evidence.humor);
+    evidence.words_per_message += alpha * (words.len() as f32 - evidence.words_per_message);
+    
+    let directives = style_directives(&lower);
+    let mut explicit: Vec<&str> = Vec::new();
+    for (phrase, field, target) in STYLE_FEEDBACK {
+        if !directives.iter().any(|directive| directive == phrase) || explicit.contains(&field) || is_locked(field) {
+            continue;
+        }
+        explicit.push(field);
+        match field {
+            "detail_preference" => {
+                evidence.detail_feedback_at = evidence.messages;
+                style.detail_preference = step_detail(&style.detail_preference, target);
+            }
+            "formality_level" => evidence.formality = target,
+            "emoji_usage" => evidence.emoji = target,
+            _ => evidence.humor = target,
+        }
+    }
+    
+    let settled = evidence.messages >= MIN_STYLE_MESSAGES;
+    let derive = |field: &str| !is_locked(field) && (settled || explicit.contains(&field));
+    if derive("formality_level") {
+        style.formality_level = formality_for(evidence.formality);
+    }
+    if derive("emoji_usage") {
+        style.emoji_usage = evidence.emoji >= EMOJI_THRESHOLD;
+    }
+    if derive("humor_preference") {
+        style.humor_preference = evidence.humor >= HUMOR_THRESHOLD;
+    }
+    let recent_feedback = evidence.detail_feedback_at > 0 && evidence.messages - evidence.detail_feedback_at < DETAIL_FEEDBACK_MESSAGES;
+    if settled && !recent_feedback && !is_locked("detail_preference") {
+        style.detail_preference = match evidence.words_per_message {
+            w if w < BRIEF_MESSAGE_WORDS => DetailLevel::Brief,
+            w if w > DETAILED_MESSAGE_WORDS => DetailLevel::Detailed,
+            _ => DetailLevel::Moderate,
+        };
+    }
+    
+    let inferred_confidence = evidence.messages as f32 / (evidence.messages as f32 + 5.0);
+    let changes = [
+        ("formality_level", before.formality_level != style.formality_level, format!("{:?}", style.formality_level)),
+        ("detail_preference", before.detail_preference != style.detail_preference, format!("{:?}", style.detail_preference)),
+        ("emoji_usage", before.emoji_usage != style.emoji_usage, style.emoji_usage.to_string()),
+        ("humor_preference", before.humor_preference != style.humor_preference, style.humor_preference.to_string()),
+    ];
+    changes.into_iter()
+        .filter(|(field, changed, _)| *changed || explicit.contains(field))
+        .map(|(field, _, preference)| LearnedPreference {
+            category: field.to_string(),
+            preference,
+            confidence: if explicit.contains(&field) { STYLE_FEEDBACK_CONFIDENCE } else { inferred_confidence },
+        })
+        .collect()
+}
+
+// Style lines for the prompt
+fn style_guidance(style: &CommunicationStyle) -> String {
+    let formality = match style.formality_level {
+        FormalityLevel::VeryFormal => "very formal and polite",
+        FormalityLevel::Formal => "formal",
+        FormalityLevel::Casual => "casual and friendly",
+        FormalityLevel::Verycasual => "very casual, like chatting with a friend",
+    };
+    let detail = match style.detail_preference {
+        DetailLevel::Brief => "brief, a few sentences at most",
+        DetailLevel::Moderate => "moderate",
+        DetailLevel::Detailed => "detailed, with explanations and examples",
+        DetailLevel::Comprehensive => "comprehensive and thorough",
+    };
+    format!(
+        "- Tone: {}\n- Detail: {}\n- Emoji: {}\n- Humor: {}\n",
+        formality,
+        detail,
+        if style.emoji_usage { "a few are welcome" } else { "avoid them" },
+        if style.humor_preference { "light humor is welcome" } else { "keep it straightforward" },
+    )
+}
+
+// Locks a style field at its current value (or unlocks it so inference resumes)
+#[ic_cdk::update]
+fn lock_communication_style(user: Principal, field: String, locked: bool) -> Result<CommunicationStyle, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    if !STYLE_FIELDS.contains(&field.as_str()) {
+        return Err(format!("Unknown communication style field '{}'", field));
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let style = &mut kg.user_profile.communication_style;
+        style.locked_fields.retain(|f| f != &field);
+        if locked {
+            style.locked_fields.push(field);
+        }
+        let style = style.clone();
+        kg.last_updated = ic_cdk::api::time();
+        Ok(style)
+    })
+}
+
+// Goals
+
+const MAX_GOALS: usize = 50;
+const MAX_GOAL_CHARS: usize = 200;
+const MAX_MILESTONES: usize = 20;
+const GOAL_MATCH_SIMILARITY: f32 = 0.5;
+const AT_RISK_MARGIN: f32 = 0.2; // progress this far behind the elapsed share of the timeline
+const AT_RISK_WINDOW_NANOS: u64 = 7 * NANOS_PER_DAY; // for goals without a known start
+
+const GOAL_PHRASES: [&str; 6] = [
+    "my goal is to ", "my goal is ", "i'm working toward ", "i'm working towards ", "i plan to ", "i aim to ",
+];
+const GOAL_COMPLETION_PHRASES: [&str; 9] = [
+    "i finished ", "i've finished ", "i have finished ", "i just finished ", "i completed ", "i've completed ",
+    "i'm done with ", "i am done with ", "i shipped ",
+];
+const GOAL_HALFWAY_PHRASES: [&str; 2] = ["i'm halfway through ", "i am halfway through "];
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct GoalInput {
+    goal: String,
+    category: Option<String>, // inferred from the goal's topic when missing
+    target_date: Option<u64>,
+    importance: Option<f32>,
+    milestones: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct GoalUpdate {
+    goal: Option<String>,
+    category: Option<String>,
+    target_date: Option<u64>,
+    progress: Option<f32>, // ignored for goals with milestones, whose progress follows them
+    importance: Option<f32>,
+    status: Option<TaskStatus>,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType, Default)]
+struct GoalsOverview {
+    active: Vec<PersonalGoal>, // soonest target date first
+    overdue: Vec<PersonalGoal>,
+    at_risk: Vec<PersonalGoal>,
+    completed: Vec<PersonalGoal>,
+    inactive: Vec<PersonalGoal>, // paused or cancelled
+}
+
+fn allocate_item_id(profile: &mut UserProfile, prefix: &str) -> String {
+    profile.next_item_seq += 1;
+    format!("{}_{}", prefix, profile.next_item_seq)
+}
+
+fn validate_goal_text(goal: &str) -> Result<String, String> {
+    let goal = goal.trim();
+    if goal.is_empty() || goal.chars().count() > MAX_GOAL_CHARS {
+        return Err(format!("Goal must be between 1 and {} characters", MAX_GOAL_CHARS));
+    }
+    Ok(goal.to_string())
+}
+
+fn push_goal(profile: &mut UserProfile, input: GoalInput, now: u64) -> Result<PersonalGoal, String> {
+    if profile.goals.len() >= MAX_GOALS {
+        return Err(format!("A user can have at most {} goals", MAX_GOALS));
+    }
+    let goal = build_goal(profile, input, now)?;
+    profile.goals.push(goal.clone());
+    Ok(goal)
+}
+
+fn build_goal(profile: &mut UserProfile, input: GoalInput, now: u64) -> Result<PersonalGoal, String> {
+    let goal = validate_goal_text(&input.goal)?;
+    if input.milestones.len() > MAX_MILESTONES {
+        return Err(format!("A goal can have at most {} milestones", MAX_MILESTONES));
+    }
+    Ok(PersonalGoal {
+        id: allocate_item_id(profile, "goal"),
+        category: input.category
+            .map(|category| category.trim().to_lowercase())
+            .filter(|category| !category.is_empty())
+            .unwrap_or_else(|| classify_topics(&goal).first().copied().unwrap_or("personal").to_string()),
+        target_date: input.target_date,
+        progress: 0.0,
+        importance: input.importance.unwrap_or(0.5).clamp(0.0, 1.0),
+        milestones: input.milestones.iter()
+            .map(|description| description.trim())
+            .filter(|description| !description.is_empty())
+            .map(|description| Milestone { description: description.to_string(), completed: false, completed_at: None })
+            .collect(),
+        status: TaskStatus::Active,
+        created_at: now,
+        updated_at: now,
+        goal,
+    })
+}
+
+// Milestones drive progress when a goal has them; reaching 100% completes an active goal
+fn refresh_goal_progress(goal: &mut PersonalGoal, now: u64) {
+    if !goal.milestones.is_empty() {
+        let completed = goal.milestones.iter().filter(|m| m.completed).count();
+        goal.progress = completed as f32 / goal.milestones.len() as f32;
+    }
+    goal.progress = goal.progress.clamp(0.0, 1.0);
+    if goal.progress >= 1.0 && goal.status == TaskStatus::Active {
+        goal.status = TaskStatus::Completed;
+    }
+    goal.updated_at = now;
+}
+
+// The active goal or open milestone a message like "I finished the login page" refers to
+fn detect_goal_progress(profile: &UserProfile, message: &str) -> Option<PendingGoalUpdate> {
+    let (object, halfway) = match phrase_object(message, &GOAL_COMPLETION_PHRASES) {
+        Some(object) => (object, false),
+        None => (phrase_object(message, &GOAL_HALFWAY_PHRASES)?, true),
+    };
+    
+    let mut best: Option<(f32, PendingGoalUpdate)> = None;
+    for goal in profile.goals.iter().filter(|goal| goal.status == TaskStatus::Active) {
+        // Halfway only makes sense for goals whose progress is not driven by milestones
+        let mut candidates = Vec::new();
+        if !halfway || goal.milestones.is_empty() {
+            candidates.push((text_similarity(&object, &goal.goal), None, if halfway { 0.5 } else { 1.0 }));
+        }
+        if !halfway {
+            candidates.extend(goal.milestones.iter().enumerate()
+                .filter(|(_, milestone)| !milestone.completed)
+                .map(|(i, milestone)| (text_similarity(&object, &milestone.description), Some(i as u32), goal.progress)));
+        }
+        for (similarity, milestone, progress) in candidates {
+            let advances = milestone.is_some() || progress > goal.progress;
+            if similarity >= GOAL_MATCH_SIMILARITY && advances && best.as_ref().is_none_or(|(best, _)| similarity > *best) {
+                best = Some((similarity, PendingGoalUpdate {
+                    goal_id: goal.id.clone(),
+                    milestone,
+                    progress,
+                    evidence: truncate_chars(message, 200),
+                    asked_at: 0,
+                }));
+            }
+        }
+    }
+    best.map(|(_, update)| update)
+}
+
+// Asks before applying progress inferred from the message; the yes/no is handled by resolve_pending_confirmation
+fn goal_progress_strategy(user: Principal, message: &str) -> Option<ResponseStrategy> {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)?;
+        let mut update = detect_goal_progress(&kg.user_profile, message)?;
+        let goal = kg.user_profile.goals.iter().find(|goal| goal.id == update.goal_id)?;
+        let question = match update.milestone.and_then(|i| goal.milestones.get(i as usize)) {
+            Some(milestone) => format!(
+                "Nice work! Should I mark \"{}\" as done for your goal \"{}\"? (yes/no)",
+                milestone.description, goal.goal,
+            ),
+            None if update.progress >= 1.0 => format!("Congratulations! Should I mark your goal \"{}\" as complete? (yes/no)", goal.goal),
+            None => format!(
+                "Great progress! Should I set your goal \"{}\" to {}% complete? (yes/no)",
+                goal.goal, (update.progress * 100.0) as u32,
+            ),
+        };
+        update.asked_at = ic_cdk::api::time();
+        kg.pending_goal_update = Some(update);
+        Some(ResponseStrategy::InquiryFirst {
+            question,
+            why_asking: "I only change your goals once you confirm".to_string(),
+        })
+    })
+}
+
+fn apply_goal_update(profile: &mut UserProfile, update: &PendingGoalUpdate, now: u64) {
+    let goal = match profile.goals.iter_mut().find(|goal| goal.id == update.goal_id) {
+        Some(goal) => goal,
+        None => return,
+    };
+    match update.milestone.and_then(|i| goal.milestones.get_mut(i as usize)) {
+        Some(milestone) => {
+            milestone.completed = true;
+            milestone.completed_at = Some(now);
+        }
+        None if update.progress >= 1.0 => {
+            goal.progress = 1.0;
+            for milestone in goal.milestones.iter_mut().filter(|m| !m.completed) {
+                milestone.completed = true;
+                milestone.completed_at = Some(now);
+            }
+        }
+        None => goal.progress = goal.progress.max(update.progress),
+    }
+    refresh_goal_progress(goal, now);
+}
+
+fn goal_overdue(goal: &PersonalGoal, now: u64) -> bool {
+    goal.target_date.is_some_and(|target| target < now) && goal.progress < 1.0
+}
+
+// Behind schedule: progress trails the share of the timeline already used up. Goals without a
+// known start count as at risk when less than half done in their last week.
+fn goal_at_risk(goal: &PersonalGoal, now: u64) -> bool {
+    let target = match goal.target_date {
+        Some(target) if target > now => target,
+        _ => return false,
+    };
+    if goal.created_at > 0 && goal.created_at < target {
+        let elapsed = now.saturating_sub(goal.created_at) as f32 / (target - goal.created_at) as f32;
+        return goal.progress + AT_RISK_MARGIN < elapsed;
+    }
+    target - now < AT_RISK_WINDOW_NANOS && goal.progress < 0.5
+}
+
+// Applies an edit to one goal and returns the result
+fn edit_goal(
+    user: Principal,
+    goal_id: &str,
+    edit: impl FnOnce(&mut PersonalGoal, u64) -> Result<(), String>,
+) -> Result<PersonalGoal, String> {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let now = ic_cdk::api::time();
+        let goal = kg.user_profile.goals.iter_mut()
+            .find(|goal| goal.id == goal_id)
+            .ok_or_else(|| "Goal not found".to_string())?;
+        edit(goal, now)?;
+        goal.updated_at = now;
+        let goal = goal.clone();
+        kg.last_updated = now;
+        Ok(goal)
+    })
+}
+
+#[ic_cdk::update]
+fn create_goal(user: Principal, input: GoalInput) -> Result<PersonalGoal, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let now = ic_cdk::api::time();
+        let goal = push_goal(&mut kg.user_profile, input, now)?;
+        kg.last_updated = now;
+        Ok(goal)
+    })
+}
+
+#[ic_cdk::update]
+fn update_goal(user: Principal, goal_id: String, update: GoalUpdate) -> Result<PersonalGoal, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    edit_goal(user, &goal_id, |goal, now| {
+        if let Some(text) = update.goal {
+            goal.goal = validate_goal_text(&text)?;
+        }
+        if let Some(category) = update.category {
+            goal.category = category.trim().to_lowercase();
+        }
+        if let Some(target_date) = update.target_date {
+            goal.target_date = Some(target_date);
+        }
+        if let Some(importance) = update.importance {
+            goal.importance = importance.clamp(0.0, 1.0);
+        }
+        if let Some(progress) = update.progress {
+            goal.progress = progress;
+        }
+        refresh_goal_progress(goal, now);
+        // An explicit status wins over the one progress implies, so a finished goal can be reopened
+        if let Some(status) = update.status {
+            goal.status = status;
+        }
+        Ok(())
+    })
+}
+
+#[ic_cdk::update]
+fn delete_goal(user: Principal, goal_id: String) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let before = kg.user_profile.goals.len();
+        kg.user_profile.goals.retain(|goal| goal.id != goal_id);
+        if kg.user_profile.goals.len() == before {
+            return Err("Goal not found".to_string());
+        }
+        if kg.pending_goal_update.as_ref().is_some_and(|update| update.goal_id == goal_id) {
+            kg.pending_goal_update = None;
+        }
+        kg.last_updated = ic_cdk::api::time();
+        Ok(format!("Goal {} deleted", goal_id))
+    })
+}
+
+#[ic_cdk::update]
+fn add_goal_milestone(user: Principal, goal_id: String, description: String) -> Result<PersonalGoal, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    edit_goal(user, &goal_id, |goal, now| {
+        let description = validate_goal_text(&description)?;
+        if goal.milestones.len() >= MAX_MILESTONES {
+            return Err(format!("A goal can have at most {} milestones", MAX_MILESTONES));
+        }
+        goal.milestones.push(Milestone { description, completed: false, completed_at: None });
+        refresh_goal_progress(goal, now);
+        Ok(())
+    })
+}
+
+#[ic_cdk::update]
+fn set_milestone_completed(user: Principal, goal_id: String, milestone_index: u32, completed: bool) -> Result<PersonalGoal, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    edit_goal(user, &goal_id, |goal, now| {
+        let milestone = goal.milestones.get_mut(milestone_index as usize)
+            .ok_or_else(|| "Milestone not found".to_string())?;
+        milestone.completed = completed;
+        milestone.completed_at = completed.then_some(now);
+        refresh_goal_progress(goal, now);
+        Ok(())
+    })
+}
+
+// Every goal grouped by where it stands, with overdue and at-risk goals called out
+#[ic_cdk::query]
+fn get_goals_overview(user: Principal) -> Result<GoalsOverview, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let kg = state.personal_knowledge_graphs.get(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let now = ic_cdk::api::time();
+        let mut overview = GoalsOverview::default();
+        for goal in &kg.user_profile.goals {
+            match goal.status {
+                TaskStatus::Active => {
+                    if goal_overdue(goal, now) {
+                        overview.overdue.push(goal.clone());
+                    } else if goal_at_risk(goal, now) {
+                        overview.at_risk.push(goal.clone());
+                    }
+                    overview.active.push(goal.clone());
+                }
+                TaskStatus::Completed => overview.completed.push(goal.clone()),
+                TaskStatus::Paused | TaskStatus::Cancelled => overview.inactive.push(goal.clone()),
+            }
+        }
+        overview.active.sort_by_key(|goal| goal.target_date.unwrap_or(u64::MAX));
+        Ok(overview)
+    })
+}
+
+// Tasks
+
+const MAX_THREAD_TASKS: usize = 100;
+const MAX_TASK_CHARS: usize = 200;
+
+const TASK_PHRASES: [&str; 4] = ["remind me to ", "don't let me forget to ", "add a task to ", "todo: "];
+// Statements of obligation are mostly conversation ("I need to understand closures"), so they only
+// become tasks when they carry a due date
+const OBLIGATION_PHRASES: [&str; 4] = ["i need to ", "i have to ", "i must ", "i've got to "];
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct TaskUpdate {
+    description: Option<String>,
+    due_date: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct ThreadTask {
+    thread_id: String,
+    topic: String,
+    task: Task,
+    overdue: bool,
+}
+
+fn task_overdue(task: &Task, now: u64) -> bool {
+    task.status == TaskStatus::Active && task.due_date.is_some_and(|due| now >= due + NANOS_PER_DAY)
+}
+
+// Allowed status changes; finished and cancelled tasks can only be reopened
+fn task_transition_allowed(from: &TaskStatus, to: &TaskStatus) -> bool {
+    matches!(
+        (from, to),
+        (TaskStatus::Active, TaskStatus::Paused | TaskStatus::Completed | TaskStatus::Cancelled)
+            | (TaskStatus::Paused, TaskStatus::Active | TaskStatus::Completed | TaskStatus::Cancelled)
+            | (TaskStatus::Completed | TaskStatus::Cancelled, TaskStatus::Active)
+    )
+}
+
+// "remind me to send the invoice Friday" -> ("send the invoice", next Friday)
+fn extract_task(message: &str, now: u64) -> Option<(String, Option<u64>)> {
+    let (object, explicit) = match phrase_object(message, &TASK_PHRASES) {
+        Some(object) => (object, true),
+        None => (phrase_object(message, &OBLIGATION_PHRASES)?, false),
+    };
+    match parse_due_date(&object, now) {
+        Some((due, start)) if start > 0 => Some((object[..start].trim().to_string(), Some(due))),
+        Some((due, _)) => Some((object, Some(due))),
+        None => explicit.then_some((object, None)),
+    }
+}
+
+fn validate_task_text(description: &str) -> Result<String, String> {
+    let description = description.trim();
+    if description.is_empty() || description.chars().count() > MAX_TASK_CHARS {
+        return Err(format!("Task description must be between 1 and {} characters", MAX_TASK_CHARS));
+    }
+    Ok(description.to_string())
+}
+
+fn add_thread_task(
+    kg: &mut PersonalKnowledgeGraph,
+    thread_id: &str,
+    description: &str,
+    due_date: Option<u64>,
+    now: u64,
+) -> Result<Task, String> {
+    let description = validate_task_text(description)?;
+    let id = allocate_item_id(&mut kg.user_profile, "task");
+    let context = thread_context(kg, thread_id, &description, now);
+    if context.ongoing_tasks.len() >= MAX_THREAD_TASKS {
+        return Err(format!("A thread can have at most {} tasks", MAX_THREAD_TASKS));
+    }
+    let task = Task {
+        id,
+        description,
+        status: TaskStatus::Active,
+        created_at: now,
+        updated_at: now,
+        due_date,
+        completed_at: None,
+    };
+    context.ongoing_tasks.push(task.clone());
+    Ok(task)
+}
+
+// Adds the task a message asks for, unless the thread already has a matching open one
+fn record_extracted_task(kg: &mut PersonalKnowledgeGraph, thread_id: &str, message: &str) {
+    let now = ic_cdk::api::time();
+    let (description, due_date) = match extract_task(message, now) {
+        Some(task) => task,
+        None => return,
+    };
+    let known = kg.context_threads.get(thread_id).is_some_and(|context| context.ongoing_tasks.iter().any(|task| {
+        task.status == TaskStatus::Active && text_similarity(&task.description, &description) >= DUPLICATE_TEXT_SIMILARITY
+    }));
+    if !known {
+        let _ = add_thread_task(kg, thread_id, &description, due_date, now);
+    }
+}
+
+// Applies an edit to one task, wherever its thread, and returns the result
+fn edit_task(
+    user: Principal,
+    task_id: &str,
+    edit: impl FnOnce(&mut Task, u64) -> Result<(), String>,
+) -> Result<Task, String> {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let now = ic_cdk::api::time();
+        let task = kg.context_threads.values_mut()
+            .flat_map(|context| context.ongoing_tasks.iter_mut())
+            .find(|task| task.id == task_id)
+            .ok_or_else(|| "Task not found".to_string())?;
+        edit(task, now)?;
+        task.updated_at = now;
+        let task = task.clone();
+        kg.last_updated = now;
+        Ok(task)
+    })
+}
+
+// Creates a task in a thread (the default thread when none is given). Without an explicit due date
+// one is read from the description ("call the bank tomorrow").
+#[ic_cdk::update]
+fn create_task(user: Principal, thread_id: Option<String>, description: String, due_date: Option<u64>) -> Result<Task, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let now = ic_cdk::api::time();
+        let (description, due_date) = match (due_date, parse_due_date(&description, now)) {
+            (Some(due), _) => (description, Some(due)),
+            (None, Some((due, start))) if start > 0 => (description[..start].to_string(), Some(due)),
+            (None, parsed) => (description, parsed.map(|(due, _)| due)),
+        };
+        let thread_id = thread_id.unwrap_or_else(|| DEFAULT_THREAD_ID.to_string());
+        let task = add_thread_task(kg, &thread_id, &description, due_date, now)?;
+        kg.last_updated = now;
+        Ok(task)
+    })
+}
+
+#[ic_cdk::update]
+fn update_task(user: Principal, task_id: String, update: TaskUpdate) -> Result<Task, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    edit_task(user, &task_id, |task, _| {
+        if let Some(description) = update.description {
+            task.description = validate_task_text(&description)?;
+        }
+        if let Some(due_date) = update.due_date {
+            task.due_date = Some(due_date);
+        }
+        Ok(())
+    })
+}
+
+#[ic_cdk::update]
+fn set_task_status(user: Principal, task_id: String, status: TaskStatus) -> Result<Task, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    edit_task(user, &task_id, |task, now| {
+        if !task_transition_allowed(&task.status, &status) {
+            return Err("That status change is not allowed for this task".to_string());
+        }
+        task.completed_at = (status == TaskStatus::Completed).then_some(now);
+        task.status = status;
+        Ok(())
+    })
+}
+
+#[ic_cdk::update]
+fn delete_task(user: Principal, task_id: String) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let mut removed = false;
+        for context in kg.context_threads.values_mut() {
+            let before = context.ongoing_tasks.len();
+            context.ongoing_tasks.retain(|task| task.id != task_id);
+            removed |= context.ongoing_tasks.len() < before;
+        }
+        if !removed {
+            return Err("Task not found".to_string());
+        }
+        kg.last_updated = ic_cdk::api::time();
+        Ok(format!("Task {} deleted", task_id))
+    })
+}
+
+// Active and paused tasks from every thread, soonest due first (undated tasks last)
+#[ic_cdk::query]
+fn list_open_tasks(user: Principal) -> Vec<ThreadTask> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Vec::new();
+    }
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let now = ic_cdk::api::time();
+        let mut tasks: Vec<ThreadTask> = state.personal_knowledge_graphs.get(&user)
+            .map(|kg| kg.context_threads.values()
+                .flat_map(|context| context.ongoing_tasks.iter()
+                    .filter(|task| matches!(task.status, TaskStatus::Active | TaskStatus::Paused))
+                    .map(move |task| ThreadTask {
+                        thread_id: context.thread_id.clone(),
+                        topic: context.topic.clone(),
+                        task: task.clone(),
+                        overdue: task_overdue(task, now),
+                    }))
+                .collect())
+            .unwrap_or_default();
+        tasks.sort_by_key(|t| (t.task.due_date.unwrap_or(u64::MAX), t.task.created_at));
+        tasks
+    })
+}
+
+// Important dates and reminders
+
+const MAX_IMPORTANT_DATES: usize = 100;
+const MAX_EVENT_CHARS: usize = 120;
+const MAX_LEAD_DAYS: u32 = 90;
+const MAX_NOTIFICATIONS: usize = 200;
+const REMINDER_INTERVAL_SECS: u64 = 60 * 60;
+const REMINDER_INSTRUCTION_BUDGET: u64 = 2_000_000_000;
+const REMINDER_HOUR: u64 = 9; // local time reminders are delivered at
+const UPCOMING_CONTEXT_DAYS: u32 = 14;
+const NOTIFICATIONS_THREAD_ID: &str = "notifications";
+const MAX_UTC_OFFSET_MINUTES: i32 = 14 * 60;
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct ImportantDateInput {
+    event: String,
+    date: i64,
+    category: Option<String>,
+    importance: Option<f32>,
+    recurrence: Option<EventRecurrence>, // defaults to Yearly for birthdays and anniversaries
+    lead_days: Option<Vec<u32>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct ImportantDateUpdate {
+    event: Option<String>,
+    date: Option<i64>,
+    category: Option<String>,
+    importance: Option<f32>,
+    recurrence: Option<EventRecurrence>,
+    lead_days: Option<Vec<u32>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct UpcomingEvent {
+    event: ImportantEvent,
+    occurs_on: u64,
+    days_until: u32,
+}
+
+fn validate_lead_days(lead_days: &[u32]) -> Result<Vec<u32>, String> {
+    if lead_days.len() > 5 || lead_days.iter().any(|days| *days > MAX_LEAD_DAYS) {
+        return Err(format!("Up to 5 lead times of at most {} days are allowed", MAX_LEAD_DAYS));
+    }
+    let mut lead_days = lead_days.to_vec();
+    lead_days.sort_unstable();
+    lead_days.dedup();
+    Ok(lead_days)
+}
+
+fn push_important_date(profile: &mut UserProfile, input: ImportantDateInput) -> Result<ImportantEvent, String> {
+    if profile.important_dates.len() >= MAX_IMPORTANT_DATES {
+        return Err(format!("At most {} important dates can be stored", MAX_IMPORTANT_DATES));
+    }
+    let event = build_important_event(profile, input)?;
+    profile.important_dates.push(event.clone());
+    Ok(event)
+}
+
+fn build_important_event(profile: &mut UserProfile, input: ImportantDateInput) -> Result<ImportantEvent, String> {
+    let event = input.event.trim().to_string();
+    if event.is_empty() || event.chars().count() > MAX_EVENT_CHARS {
+        return Err(format!("Event name must be between 1 and {} characters", MAX_EVENT_CHARS));
+    }
+    let lead_days = input.lead_days.as_deref().map(validate_lead_days).transpose()?;
+    let lower = event.to_lowercase();
+    let yearly = lower.contains("birthday") || lower.contains("anniversary");
+    Ok(ImportantEvent {
+        id: allocate_item_id(profile, "event"),
+        category: input.category.unwrap_or_else(|| if yearly { "personal" } else { "event" }.to_string()),
+        recurrence: input.recurrence.unwrap_or(if yearly { EventRecurrence::Yearly } else { EventRecurrence::Once }),
+        importance: input.importance.unwrap_or(0.5).clamp(0.0, 1.0),
+        event,
+        date: input.date - input.date.rem_euclid(NANOS_PER_DAY as i64),
+        lead_days,
+        reminded_until: 0,
+    })
+}
+
+// The user's current day number, shifted by their UTC offset
+fn local_day(now: u64, utc_offset_minutes: i32) -> i64 {
+    (now as i64 + utc_offset_minutes as i64 * 60 * 1_000_000_000).div_euclid(NANOS_PER_DAY as i64)
+}
+
+// Day number of a date, stepping back to the month's last day for dates it doesn't have (Feb 29, the 31st)
+fn clamped_day(year: i64, month: u32, day: u32) -> Option<i64> {
+    (0..4).find_map(|back| civil_day(year, month, day.saturating_sub(back)))
+}
+
+// First occurrence on or after `today`, None once a one-off event has passed
+fn next_occurrence(event: &ImportantEvent, today: i64) -> Option<i64> {
+    let first = event.date.div_euclid(NANOS_PER_DAY as i64);
+    if first >= today {
+        return Some(first);
+    }
+    let (_, month, day) = civil_from_days(first);
+    let (year, this_month, _) = civil_from_days(today);
+    let candidates = match event.recurrence {
+        EventRecurrence::Once => return None,
+        EventRecurrence::Yearly => [clamped_day(year, month, day), clamped_day(year + 1, month, day)],
+        EventRecurrence::Monthly => {
+            let (next_year, next_month) = if this_month == 12 { (year + 1, 1) } else { (year, this_month + 1) };
+            [clamped_day(year, this_month, day), clamped_day(next_year, next_month, day)]
+        }
+    };
+    candidates.into_iter().flatten().find(|day| *day >= today)
+}
+
+fn days_until_phrase(days: u32) -> String {
+    match days {
+        0 => "today".to_string(),
+        1 => "tomorrow".to_string(),
+        days => format!("in {} days", days),
+    }
+}
+
+// Events occurring within `days` of the user's today, soonest first
+fn upcoming_events(profile: &UserProfile, now: u64, days: u32) -> Vec<UpcomingEvent> {
+    let today = local_day(now, profile.reminder_settings.utc_offset_minutes);
+    let mut upcoming: Vec<UpcomingEvent> = profile.important_dates.iter()
+        .filter_map(|event| {
+            let day = next_occurrence(event, today)?;
+            let days_until = (day - today) as u32;
+            (days_until <= days).then(|| UpcomingEvent { event: event.clone(), occurs_on: day as u64 * NANOS_PER_DAY, days_until })
+        })
+        .collect();
+    upcoming.sort_by_key(|upcoming| upcoming.occurs_on);
+    upcoming
+}
+
+fn reminder_text(event: &str, days_until: u32, occurs_on: i64) -> String {
+    let (_, month, day) = civil_from_days(occurs_on);
+    let month = MONTH_NAMES[month as usize - 1];
+    let month = month[..1].to_uppercase() + &month[1..];
+    format!("Reminder: {} is {} ({} {}).", event, days_until_phrase(days_until), month, day)
+}
+
+// Events whose latest reminder time has passed since they were last reminded, as (id, event, occurrence
+// day). Marks them reminded, so catching up after a pause yields a single reminder per event.
+fn take_due_reminders(profile: &mut UserProfile, now: u64) -> Vec<(String, String, i64)> {
+    let settings = &profile.reminder_settings;
+    if !settings.enabled {
+        return Vec::new();
+    }
+    let today = local_day(now, settings.utc_offset_minutes);
+    let offset = settings.utc_offset_minutes as i64 * 60 * 1_000_000_000;
+    let mut due = Vec::new();
+    for event in profile.important_dates.iter_mut() {
+        let Some(occurrence) = next_occurrence(event, today) else { continue };
+        let lead_days = event.lead_days.as_ref().unwrap_or(&settings.lead_days);
+        let latest = lead_days.iter()
+            .filter_map(|lead| {
+                let local = (occurrence - *lead as i64) * NANOS_PER_DAY as i64 + (REMINDER_HOUR * NANOS_PER_HOUR) as i64;
+                u64::try_from(local - offset).ok()
+            })
+            .filter(|at| *at <= now && *at > event.reminded_until)
+            .max();
+        if let Some(at) = latest {
+            event.reminded_until = at;
+            due.push((event.id.clone(), event.event.clone(), occurrence));
+        }
+    }
+    due
+}
+
+// Timer job: for every event whose next reminder time has passed, posts one assistant message to
+// the user's notifications inbox. Catching up after a pause sends a single reminder per event.
+// Users are walked from the cursor until the instruction budget runs out; the rest wait a tick.
+fn send_due_reminders() {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let now = ic_cdk::api::time();
+        let mut users: Vec<Principal> = state.personal_knowledge_graphs.keys().copied().collect();
+        users.sort();
+        let start = state.reminder_cursor
+            .map(|cursor| users.iter().position(|user| *user > cursor).unwrap_or(0))
+            .unwrap_or(0);
+        
+        for user in users.iter().cycle().skip(start).take(users.len()) {
+            if ic_cdk::api::instruction_counter() > REMINDER_INSTRUCTION_BUDGET {
+                break;
+            }
+            state.reminder_cursor = Some(*user);
+            let kg = match state.personal_knowledge_graphs.get_mut(user) {
+                Some(kg) => kg,
+                None => continue,
+            };
+            let today = local_day(now, kg.user_profile.reminder_settings.utc_offset_minutes);
+            for (event_id, event, occurrence) in take_due_reminders(&mut kg.user_profile, now) {
+                let id = allocate_item_id(&mut kg.user_profile, "notification");
+                kg.notifications.push(Notification {
+                    id,
+                    event_id,
+                    occurs_on: occurrence as u64 * NANOS_PER_DAY,
+                    message: EnhancedChatMessage {
+                        role: "assistant".to_string(),
+                        content: reminder_text(&event, (occurrence - today) as u32, occurrence),
+                        timestamp: now,
+                        provider: "memorymind".to_string(),
+                        context_thread_id: Some(NOTIFICATIONS_THREAD_ID.to_string()),
+                        extracted_facts: Vec::new(),
+                        referenced_memories: Vec::new(),
+                        learned_preferences: Vec::new(),
+                        user_sentiment: None,
+                        response_strategy: None,
+                        cycles_cost: None,
+                        content_stored_on_chain: Some(true),
+                        ii_verified: None,
+                        attachment_ids: Vec::new(),
+                    },
+                    read: false,
+                });
+            }
+            let overflow = kg.notifications.len().saturating_sub(MAX_NOTIFICATIONS);
+            kg.notifications.drain(..overflow);
+        }
+    });
+}
+
+// Applies an edit to one important date and returns the result
+fn edit_important_date(
+    user: Principal,
+    event_id: &str,
+    edit: impl FnOnce(&mut ImportantEvent) -> Result<(), String>,
+) -> Result<ImportantEvent, String> {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let event = kg.user_profile.important_dates.iter_mut()
+            .find(|event| event.id == event_id)
+            .ok_or_else(|| "Important date not found".to_string())?;
+        edit(event)?;
+        let event = event.clone();
+        kg.last_updated = ic_cdk::api::time();
+        Ok(event)
+    })
+}
+
+#[ic_cdk::update]
+fn add_important_date(user: Principal, input: ImportantDateInput) -> Result<ImportantEvent, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let event = push_important_date(&mut kg.user_profile, input)?;
+        kg.last_updated = ic_cdk::api::time();
+        Ok(event)
+    })
+}
+
+#[ic_cdk::update]
+fn update_important_date(user: Principal, event_id: String, update: ImportantDateUpdate) -> Result<ImportantEvent, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    edit_important_date(user, &event_id, |event| {
+        if let Some(name) = update.event {
+            let name = name.trim().to_string();
+            if name.is_empty() || name.chars().count() > MAX_EVENT_CHARS {
+                return Err(format!("Event name must be between 1 and {} characters", MAX_EVENT_CHARS));
+            }
+            event.event = name;
+        }
+        if let Some(lead_days) = update.lead_days {
+            event.lead_days = Some(validate_lead_days(&lead_days)?);
+        }
+        if let Some(date) = update.date {
+            event.date = date - date.rem_euclid(NANOS_PER_DAY as i64);
+            // A new date gets its reminders again
+            event.reminded_until = 0;
+        }
+        if let Some(recurrence) = update.recurrence {
+            event.recurrence = recurrence;
+        }
+        if let Some(category) = update.category {
+            event.category = category;
+        }
+        if let Some(importance) = update.importance {
+            event.importance = importance.clamp(0.0, 1.0);
+        }
+        Ok(())
+    })
+}
+
+#[ic_cdk::update]
+fn delete_important_date(user: Principal, event_id: String) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let before = kg.user_profile.important_dates.len();
+        kg.user_profile.important_dates.retain(|event| event.id != event_id);
+        if kg.user_profile.important_dates.len() == before {
+            return Err("Important date not found".to_string());
+        }
+        kg.last_updated = ic_cdk::api::time();
+        Ok(format!("Important date {} deleted", event_id))
+    })
+}
+
+// Events in the next `days` days (default 30) in the user's time zone
+#[ic_cdk::query]
+fn list_upcoming_dates(user: Principal, days: Option<u32>) -> Vec<UpcomingEvent> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Vec::new();
+    }
+    
+    STATE.with(|state| {
+        state.borrow().personal_knowledge_graphs.get(&user)
+            .map(|kg| upcoming_events(&kg.user_profile, ic_cdk::api::time(), days.unwrap_or(30).min(366)))
+            .unwrap_or_default()
+    })
+}
+
+#[ic_cdk::update]
+fn set_reminder_settings(user: Principal, settings: ReminderSettings) -> Result<ReminderSettings, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    if settings.utc_offset_minutes.abs() > MAX_UTC_OFFSET_MINUTES {
+        return Err("UTC offset must be within 14 hours".to_string());
+    }
+    let settings = ReminderSettings { lead_days: validate_lead_days(&settings.lead_days)?, ..settings };
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        kg.user_profile.reminder_settings = settings.clone();
+        kg.last_updated = ic_cdk::api::time();
+        Ok(settings)
+    })
+}
+
+// Reminder inbox, newest first
+#[ic_cdk::query]
+fn get_notifications(user: Principal, unread_only: bool) -> Vec<Notification> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Vec::new();
+    }
+    
+    STATE.with(|state| {
+        state.borrow().personal_knowledge_graphs.get(&user)
+            .map(|kg| kg.notifications.iter().rev()
+                .filter(|notification| !unread_only || !notification.read)
+                .cloned()
+                .collect())
+            .unwrap_or_default()
+    })
+}
+
+// Marks the given notifications read, or every notification when the list is empty
+#[ic_cdk::update]
+fn mark_notifications_read(user: Principal, notification_ids: Vec<String>) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let mut marked = 0;
+        for notification in kg.notifications.iter_mut() {
+            if !notification.read && (notification_ids.is_empty() || notification_ids.contains(&notification.id)) {
+                notification.read = true;
+                marked += 1;
+            }
+        }
+        Ok(format!("{} notifications marked read", marked))
+    })
+}
+
+// People
+
+const MAX_PEOPLE: usize = 100;
+const MAX_PERSON_NAME_CHARS: usize = 80;
+const MAX_PERSON_CONTEXT_CHARS: usize = 500;
+const MAX_PERSON_MEMORIES: usize = 50;
+const PERSON_MENTION_BOOST: f32 = 0.02;
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct PersonInput {
+    name: String,
+    relationship_type: String,
+    context: Option<String>,
+    importance: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct PersonUpdate {
+    name: Option<String>,
+    relationship_type: Option<String>,
+    context: Option<String>,
+    importance: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, CandidType)]
+struct PersonDetail {
+    person: PersonalRelationship,
+    entity: Option<EntityRecord>,
+    memories: Vec<MemoryNode>,
+}
+
+fn push_person(profile: &mut UserProfile, input: PersonInput, now: u64) -> Result<PersonalRelationship, String> {
+    validate_person(&input.name, input.context.as_deref())?;
+    if profile.relationships.iter().any(|person| person.name.eq_ignore_ascii_case(input.name.trim())) {
+        return Err(format!("{} is already in your people", input.name.trim()));
+    }
+    if profile.relationships.len() >= MAX_PEOPLE {
+        return Err(format!("At most {} people can be stored", MAX_PEOPLE));
+    }
+    let person = build_person(profile, input, now);
+    profile.relationships.push(person.clone());
+    Ok(person)
+}
+
+fn build_person(profile: &mut UserProfile, input: PersonInput, now: u64) -> PersonalRelationship {
+    PersonalRelationship {
+        id: allocate_item_id(profile, "person"),
+        name: input.name.trim().to_string(),
+        relationship_type: input.relationship_type.trim().to_lowercase(),
+        context: input.context.unwrap_or_default(),
+        importance: input.importance.unwrap_or(0.5).clamp(0.0, 1.0),
+        entity_key: None,
+        memory_ids: Vec::new(),
+        mention_count: 0,
+        first_mentioned: now,
+        last_mentioned: now,
+    }
+}
+
+fn validate_person(name: &str, context: Option<&str>) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_PERSON_NAME_CHARS {
+        return Err(format!("Name must be between 1 and {} characters", MAX_PERSON_NAME_CHARS));
+    }
+    if context.is_some_and(|context| context.chars().count() > MAX_PERSON_CONTEXT_CHARS) {
+        return Err(format!("Context must be at most {} characters", MAX_PERSON_CONTEXT_CHARS));
+    }
+    Ok(())
+}
+
+fn link_person_memory(person: &mut PersonalRelationship, memory_id: Option<&str>) {
+    if let Some(memory_id) = memory_id.filter(|id| !person.memory_ids.iter().any(|existing| existing == id)) {
+        person.memory_ids.push(memory_id.to_string());
+        let excess = person.memory_ids.len().saturating_sub(MAX_PERSON_MEMORIES);
+        person.memory_ids.drain(..excess);
+    }
+}
+
+// Indexes of the people a message refers to, by first name or by "my <relationship>" when only one
+// person has that relationship ("my manager" -> Dana)
+fn mentioned_people(people: &[PersonalRelationship], message: &str) -> Vec<usize> {
+    let words = |text: &str| -> Vec<String> {
+        text.replace('’', "'")
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .map(|word| word.trim_matches('\'').to_string())
+            .filter(|word| !word.is_empty())
+            .collect()
+    };
+    let message_words = words(message);
+    let lower = format!(" {} ", message_words.join(" ").to_lowercase());
+    people.iter().enumerate()
+        .filter(|(_, person)| {
+            // Names must be capitalized in the message, so "I will" or "may be" don't count as Will or May
+            let first_name = words(&person.name).into_iter().next().map(|name| name.to_lowercase());
+            let by_name = first_name.is_some_and(|first| message_words.iter().any(|word| {
+                let word = word.strip_suffix("'s").unwrap_or(word);
+                is_capitalized(word) && word.to_lowercase() == first
+            }));
+            let role = person.relationship_type.to_lowercase();
+            let by_role = !role.is_empty()
+                && lower.contains(&format!(" my {} ", role))
+                && people.iter().filter(|other| other.relationship_type.eq_ignore_ascii_case(&role)).count() == 1;
+            by_name || by_role
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// Counts a mention for everyone the message refers to, links them to the memories stored from it
+// and to their entity registry entry
+fn record_people_mentions(kg: &mut PersonalKnowledgeGraph, message: &str, memory_ids: &[String]) {
+    let now = ic_cdk::api::time();
+    for i in mentioned_people(&kg.user_profile.relationships, message) {
+        let entity = Entity {
+            name: kg.user_profile.relationships[i].name.clone(),
+            entity_type: EntityType::Person,
+            context: String::new(),
+        };
+        let entity_key = resolve_entity(kg, &entity);
+        let person = &mut kg.user_profile.relationships[i];
+        person.mention_count = person.mention_count.saturating_add(1);
+        person.last_mentioned = now;
+        person.importance = (person.importance + PERSON_MENTION_BOOST).min(1.0);
+        if entity_key.is_some() {
+            person.entity_key = entity_key;
+        }
+        for memory_id in memory_ids {
+            link_person_memory(person, Some(memory_id));
+        }
+    }
+}
+
+#[ic_cdk::update]
+fn add_person(user: Principal, input: PersonInput) -> Result<PersonalRelationship, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let now = ic_cdk::api::time();
+        let person = push_person(&mut kg.user_profile, input, now)?;
+        kg.last_updated = now;
+        Ok(person)
+    })
+}
+
+#[ic_cdk::update]
+fn update_person(user: Principal, person_id: String, update: PersonUpdate) -> Result<PersonalRelationship, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let people = &mut kg.user_profile.relationships;
+        let index = people.iter().position(|person| person.id == person_id)
+            .ok_or_else(|| "Person not found".to_string())?;
+        let name = update.name.as_deref().unwrap_or(&people[index].name).trim().to_string();
+        validate_person(&name, update.context.as_deref())?;
+        if people.iter().enumerate().any(|(i, person)| i != index && person.name.eq_ignore_ascii_case(&name)) {
+            return Err(format!("{} is already in your people", name));
+        }
+        
+        let person = &mut people[index];
+        if person.name != name {
+            // The old registry link belonged to the old name
+            person.entity_key = None;
+            person.name = name;
+        }
+        if let Some(relationship_type) = update.relationship_type {
+            person.relationship_type = relationship_type.trim().to_lowercase();
+        }
+        if let Some(context) = update.context {
+            person.context = context;
+        }
+        if let Some(importance) = update.importance {
+            person.importance = importance.clamp(0.0, 1.0);
+        }
+        let person = person.clone();
+        kg.last_updated = ic_cdk::api::time();
+        Ok(person)
+    })
+}
+
+#[ic_cdk::update]
+fn delete_person(user: Principal, person_id: String) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let before = kg.user_profile.relationships.len();
+        kg.user_profile.relationships.retain(|person| person.id != person_id);
+        if kg.user_profile.relationships.len() == before {
+            return Err("Person not found".to_string());
+        }
+        kg.last_updated = ic_cdk::api::time();
+        Ok(format!("Person {} deleted", person_id))
+    })
+}
+
+// Everyone the user has told us about, most important first
+#[ic_cdk::query]
+fn list_people(user: Principal) -> Vec<PersonalRelationship> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Vec::new();
+    }
+    
+    STATE.with(|state| {
+        let mut people = state.borrow().personal_knowledge_graphs.get(&user)
+            .map(|kg| kg.user_profile.relationships.clone())
+            .unwrap_or_default();
+        people.sort_by(|a, b| b.importance.total_cmp(&a.importance).then(b.last_mentioned.cmp(&a.last_mentioned)));
+        people
+    })
+}
+
+// A person with their entity registry entry and the memories that mention them, newest first
+#[ic_cdk::query]
+fn get_person(user: Principal, person_id: String) -> Result<PersonDetail, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let kg = state.personal_knowledge_graphs.get(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        let person = kg.user_profile.relationships.iter()
+            .find(|person| person.id == person_id)
+            .ok_or_else(|| "Person not found".to_string())?;
+        let entity = person.entity_key.as_ref().and_then(|key| kg.entities.get(key)).cloned();
+        
+        let mut memory_ids: Vec<&String> = person.memory_ids.iter().collect();
+        if let Some(entity) = &entity {
+            memory_ids.extend(kg.relationships.iter()
+                .filter(|edge| edge.to_node == entity.node_id && edge.relationship_type == RelationshipType::Mentions)
+                .map(|edge| &edge.from_node));
+        }
+        let mut memories: Vec<MemoryNode> = Vec::new();
+        for id in memory_ids {
+            if let Some(node) = kg.memory_nodes.get(id).filter(|node| !memories.iter().any(|m| m.id == node.id)) {
+                memories.push(node.clone());
+            }
+        }
+        memories.sort_by_key(|node| std::cmp::Reverse(node.created_at));
+        Ok(PersonDetail { person: person.clone(), entity, memories })
+    })
+}
+
+// Dates
+
+const MONTH_NAMES: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june",
+    "july", "august", "september", "october", "november", "december",
+];
+
+// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// (year, month, day) for a day count since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Day number of a calendar date, None for dates that don't exist
+fn civil_day(year: i64, month: u32, day: u32) -> Option<i64> {
+    let max_day = match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if day == 0 || day > max_day {
+        return None;
+    }
+    Some(days_from_civil(year, month, day))
+}
+
+// Signed so that dates before 1970, such as birthdays, are representable
+fn date_to_nanos(year: i64, month: u32, day: u32) -> Option<i64> {
+    civil_day(year, month, day)?.checked_mul(NANOS_PER_DAY as i64)
+}
+
+// Parses "2024-03-05", "March 5th", "Mar 5, 2024" or "5 March 2024" (UTC midnight).
+// Without a year the date falls in the year of `now`.
+fn parse_date(text: &str, now: u64) -> Option<i64> {
+    let text = text.trim().to_lowercase();
+    let current_year = civil_from_days((now / NANOS_PER_DAY) as i64).0;
+    
+    let iso: Vec<&str> = text.split_whitespace().next().unwrap_or("")
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .split('-')
+        .collect();
+    if let [year, month, day] = iso[..] {
+        if let (Ok(year), Ok(month), Ok(day)) = (year.parse(), month.parse(), day.parse()) {
+            return date_to_nanos(year, month, day);
+        }
+    }
+    
+    let words: Vec<String> = text.split(|c: char| c.is_whitespace() || c == ',')
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty() && *word != "of" && *word != "the")
+        .take(3)
+        .map(str::to_string)
+        .collect();
+    let month_of = |word: &str| MONTH_NAMES.iter().position(|month| word.len() >= 3 && month.starts_with(word)).map(|i| i as u32 + 1);
+    let day_of = |word: &str| word.trim_end_matches(|c: char| c.is_alphabetic()).parse::<u32>().ok();
+    let year_of = |word: Option<&String>| word.and_then(|w| w.parse::<i64>().ok()).filter(|year| *year >= 1000);
+    
+    let (month, day) = match (words.first(), words.get(1)) {
+        (Some(first), Some(second)) => match (month_of(first), day_of(first)) {
+            (Some(month), _) => (month, day_of(second)?),
+            (None, Some(day)) => (month_of(second)?, day),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    date_to_nanos(year_of(words.get(2)).unwrap_or(current_year), month, day)
+}
+
+const DUE_DATE_CONNECTORS: [&str; 7] = ["by", "on", "before", "due", "until", "this", "next"];
+
+// Finds a due date in free text ("Friday", "by tomorrow", "in 3 days", "next week", "on March 5th")
+// and returns the start of that day with the byte offset where the expression begins, so callers
+// can cut it from the text
+fn parse_due_date(text: &str, now: u64) -> Option<(u64, usize)> {
+    // Words are lowercased one at a time so their offsets stay valid in `text`
+    let mut words: Vec<(usize, String)> = Vec::new();
+    let mut offset = 0;
+    for word in text.split(' ') {
+        let cleaned = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if !cleaned.is_empty() {
+            words.push((offset, cleaned.to_lowercase()));
+        }
+        offset += word.len() + 1;
+    }
+    let today = now / NANOS_PER_DAY;
+    let word_at = |i: usize| words.get(i).map(|(_, w)| w.as_str()).unwrap_or("");
+    
+    for i in 0..words.len() {
+        let day = match word_at(i) {
+            "today" | "tonight" => Some(today),
+            "tomorrow" => Some(today + 1),
+            // Weeks start on Monday; 1970-01-01 was a Thursday
+            "next" if word_at(i + 1) == "week" => Some(today + 7 - (today + 3) % 7),
+            "next" if word_at(i + 1) == "month" => {
+                let (year, month, _) = civil_from_days(today as i64);
+                let (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                civil_day(year, month, 1).and_then(|day| u64::try_from(day).ok())
+            }
+            "in" => {
+                let count = match word_at(i + 1) {
+                    "a" | "one" => Some(1),
+                    number => number.parse::<u64>().ok().filter(|n| *n <= 365),
+                };
+                match (count, word_at(i + 2).trim_end_matches('s')) {
+                    (Some(n), "day") => Some(today + n),
+                    (Some(n), "week") => Some(today + 7 * n),
+                    _ => None,
+                }
+            }
+            word => match WEEKDAY_NAMES.iter().position(|name| *name == word) {
+                Some(weekday) => {
+                    let ahead = (weekday as u64 + 7 - (today + 3) % 7) % 7;
+                    Some(today + if ahead == 0 { 7 } else { ahead })
+                }
+                None => parse_date(&text[words[i].0..], now).and_then(|nanos| {
+                    let day = nanos.div_euclid(NANOS_PER_DAY as i64);
+                    let (year, month, date) = civil_from_days(day);
+                    // A past date without an explicit year means the next one
+                    let day = if day < today as i64 && !text[words[i].0..].contains(&year.to_string()) {
+                        civil_day(year + 1, month, date).unwrap_or(day)
+                    } else {
+                        day
+                    };
+                    u64::try_from(day).ok()
+                }),
+            },
+        };
+        if let Some(day) = day {
+            let mut start = i;
+            while start > 0 && DUE_DATE_CONNECTORS.contains(&word_at(start - 1)) {
+                start -= 1;
+            }
+            return Some((day * NANOS_PER_DAY, words[start].0));
+        }
+    }
+    None
+}
+
+// Backward compatibility functions
+#[ic_cdk::update]
+async fn prompt(prompt_text: String) -> Result<String, String> {
+    memory_mind_prompt(prompt_text, None, Some(true)).await
+}
+
+// Enhanced ICP functions with MemoryMind integration
+#[ic_cdk::update]
+async fn icp_ai_prompt(
+    prompt_text: String,
+    _provider: Option<String>,
+    _assistant_type: Option<String>,
+    _store_on_chain: Option<bool>,
+) -> Result<String, String> {
+    memory_mind_prompt(prompt_text, None, Some(true)).await
+}
+
+// MemoryMind specific query functions
+#[ic_cdk::query]
+fn get_user_knowledge_graph(user: Principal) -> Option<PersonalKnowledgeGraph> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return None;
+    }
+    
+    STATE.with(|state| {
+        state.borrow().personal_knowledge_graphs.get(&user).cloned()
+    })
+}
+
+#[ic_cdk::query]
+fn get_user_memories(user: Principal, limit: Option<u32>) -> Vec<MemoryNode> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Vec::new();
+    }
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        if let Some(kg) = state.personal_knowledge_graphs.get(&user) {
+            let limit = limit.unwrap_or(50) as usize;
+            kg.memory_nodes.values()
+                .take(limit)
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        }
+    })
+}
+
+// Memory CRUD
+
+const MAX_MEMORY_CONTENT_CHARS: usize = 2_000;
+const MAX_MEMORY_TAGS: usize = 20;
+const MAX_TAG_CHARS: usize = 50;
+
+fn validate_memory_content(content: &str) -> Result<String, String> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err("Memory content cannot be empty".to_string());
+    }
+    if content.chars().count() > MAX_MEMORY_CONTENT_CHARS {
+        return Err(format!("Memory content cannot exceed {} characters", MAX_MEMORY_CONTENT_CHARS));
+    }
+    Ok(content.to_string())
+}
+
+fn validate_memory_type(node_type: &MemoryNodeType) -> Result<(), String> {
+    match node_type {
+        MemoryNodeType::Document => Err("Documents can only be created by uploading them".to_string()),
+        MemoryNodeType::Summary => Err("Summaries are written by memory consolidation".to_string()),
+        MemoryNodeType::Entity => Err("Entities are created from conversation".to_string()),
+        _ => Ok(()),
+    }
+}
+
+fn validate_importance(importance: f32) -> Result<f32, String> {
+    if !importance.is_finite() || !(0.0..=1.0).contains(&importance) {
+        return Err("Importance score must be between 0 and 1".to_string());
+    }
+    Ok(importance)
+}
+
+fn validate_tags(tags: &[String]) -> Result<Vec<String>, String> {
+    if tags.len() > MAX_MEMORY_TAGS {
+        return Err(format!("A memory can have at most {} tags", MAX_MEMORY_TAGS));
+    }
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = normalize_tag(tag)
+            .ok_or_else(|| format!("Tags must be between 1 and {} characters", MAX_TAG_CHARS))?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+#[ic_cdk::update]
+fn add_memory(user: Principal, memory: MemoryInput) -> Result<AddedMemory, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    let content = validate_memory_content(&memory.content)?;
+    validate_memory_type(&memory.node_type)?;
+    let importance = validate_importance(memory.importance_score.unwrap_or(0.7))?;
+    let tags = validate_tags(&memory.tags)?;
+    
+    ensure_user_knowledge_graph(user);
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let id = allocate_memory_id(&mut state, user);
+        let mut node = MemoryNode::new(id.clone(), content, memory.node_type, importance);
+        node.tags = tags;
+        node.pinned = memory.pinned.unwrap_or(false);
+        let node = store_memory_node(&mut state, user, node, true).ok_or_else(|| "User knowledge graph not found".to_string())?;
+        Ok(AddedMemory { merged: node.id != id, node })
+    })
+}
+
+#[ic_cdk::update]
+fn update_memory(user: Principal, node_id: String, update: MemoryUpdate) -> Result<MemoryNode, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    let content = update.content.as_deref().map(validate_memory_content).transpose()?;
+    if let Some(node_type) = &update.node_type {
+        validate_memory_type(node_type)?;
+    }
+    let importance = update.importance_score.map(validate_importance).transpose()?;
+    let tags = update.tags.as_deref().map(validate_tags).transpose()?;
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let mut node = state.personal_knowledge_graphs.get(&user)
+            .and_then(|kg| kg.memory_nodes.get(&node_id))
+            .cloned()
+            .ok_or_else(|| "Memory not found".to_string())?;
+        if matches!(node.node_type, MemoryNodeType::Document | MemoryNodeType::Entity) && update.node_type.is_some() {
+            return Err("The type of a document or entity cannot be changed".to_string());
+        }
+        
+        if let Some(content) = content {
+            if content != node.content {
+                node.content = content;
+                node.embedding = None; // re-embedded on the next turn
+            }
+        }
+        if let Some(node_type) = update.node_type {
+            node.node_type = node_type;
+        }
+        if let Some(importance) = importance {
+            node.importance_score = importance;
+        }
+        if let Some(tags) = tags {
+            node.tags = tags;
+        }
+        
+        insert_memory_node(&mut state, user, node).ok_or_else(|| "Memory not found".to_string())
+    })
+}
+
+#[ic_cdk::update]
+fn delete_memory(user: Principal, node_id: String) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        match state.personal_knowledge_graphs.get(&user).and_then(|kg| kg.memory_nodes.get(&node_id)) {
+            Some(node) if matches!(node.node_type, MemoryNodeType::Document) => {
+                Err("Use delete_document to remove a document and its passages".to_string())
+            }
+            Some(_) => {
+                remove_memory_node(&mut state, user, &node_id);
+                Ok("Memory deleted".to_string())
+            }
+            None => Err("Memory not found".to_string()),
+        }
+    })
+}
+
+// Pinning marks a memory the user wants kept exactly as it is
+#[ic_cdk::update]
+fn pin_memory(user: Principal, node_id: String, pinned: bool) -> Result<MemoryNode, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let node = state.personal_knowledge_graphs.get_mut(&user)
+            .and_then(|kg| kg.memory_nodes.get_mut(&node_id))
+            .ok_or_else(|| "Memory not found".to_string())?;
+        node.pinned = pinned;
+        Ok(node.clone())
+    })
+}
+
+// Relationship inference and manual edges
+
+const MAX_INFERRED_EDGES: usize = 5;
+const MIN_INFERRED_STRENGTH: f32 = 0.2;
+const SEQUENCE_WINDOW_NANOS: u64 = 7 * NANOS_PER_DAY;
+
+// Adds an edge, or strengthens the existing edge of the same type between the same nodes.
+// Like node writes, every edge write goes through here or remove_knowledge_edges to keep adjacency in sync.
+// Returns false for self-loops.
+fn add_knowledge_edge(state: &mut State, user: Principal, edge: KnowledgeEdge) -> bool {
+    if edge.from_node == edge.to_node {
+        return false;
+    }
+    let kg = match state.personal_knowledge_graphs.get_mut(&user) {
+        Some(kg) => kg,
+        None => return false,
+    };
+    let stored = match kg.relationships.iter_mut().find(|existing| {
+        existing.from_node == edge.from_node
+            && existing.to_node == edge.to_node
+            && existing.relationship_type == edge.relationship_type
+    }) {
+        Some(existing) => {
+            existing.strength = existing.strength.max(edge.strength);
+            existing.clone()
+        }
+        None => {
+            kg.relationships.push(edge.clone());
+            edge
+        }
+    };
+    index_edge(ensure_memory_index(state, user), &stored);
+    true
+}
+
+// Removes edges from -> to (of one type, or all types); returns how many were removed
+fn remove_knowledge_edges(
+    state: &mut State,
+    user: Principal,
+    from_node: &str,
+    to_node: &str,
+    relationship_type: Option<&RelationshipType>,
+) -> usize {
+    let matches = |edge: &KnowledgeEdge| {
+        edge.from_node == from_node
+            && edge.to_node == to_node
+            && relationship_type.is_none_or(|t| &edge.relationship_type == t)
+    };
+    let kg = match state.personal_knowledge_graphs.get_mut(&user) {
+        Some(kg) => kg,
+        None => return 0,
+    };
+    let removed: Vec<KnowledgeEdge> = kg.relationships.iter().filter(|edge| matches(edge)).cloned().collect();
+    kg.relationships.retain(|edge| !matches(edge));
+    
+    if let Some(index) = state.memory_indexes.get_mut(&user) {
+        for edge in &removed {
+            unindex_edge(index, edge);
+        }
+    }
+    removed.len()
+}
+
+fn tag_overlap(a: &[String], b: &[String]) -> f32 {
+    let shared = a.iter().filter(|tag| b.contains(tag)).count() as f32;
+    let union = (a.len() + b.len()) as f32 - shared;
+    if union == 0.0 { 0.0 } else { shared / union }
+}
+
+// Links a new node to existing ones: shared tags/entities -> Related, a skill or piece of knowledge
+// sharing a tag with a goal -> UsedFor, and consecutive experiences -> LeadsTo
+fn infer_relationships(kg: &PersonalKnowledgeGraph, node: &MemoryNode) -> Vec<KnowledgeEdge> {
+    // Documents and entities are linked explicitly (PartOf, Mentions) rather than by tags
+    let linked_explicitly = |node: &MemoryNode| matches!(node.node_type, MemoryNodeType::Document | MemoryNodeType::Entity);
+    if linked_explicitly(node) {
+        return Vec::new();
+    }
+    let now = ic_cdk::api::time();
+    let mut edges: Vec<KnowledgeEdge> = Vec::new();
+    
+    let mut related: Vec<(&MemoryNode, f32)> = kg.memory_nodes.values()
+        .filter(|other| !linked_explicitly(other))
+        .map(|other| (other, tag_overlap(&node.tags, &other.tags)))
+        .filter(|(_, overlap)| *overlap > 0.0)
+        .collect();
+    related.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    
+    let supports_goal = |a: &MemoryNode, b: &MemoryNode| {
+        matches!(a.node_type, MemoryNodeType::Knowledge | MemoryNodeType::Fact) && b.node_type == MemoryNodeType::Goal
+    };
+    for (other, overlap) in related.into_iter().take(MAX_INFERRED_EDGES) {
+        let strength = overlap.max(MIN_INFERRED_STRENGTH);
+        let (from, to, relationship_type) = if supports_goal(node, other) {
+            (&node.id, &other.id, RelationshipType::UsedFor)
+        } else if supports_goal(other, node) {
+            (&other.id, &node.id, RelationshipType::UsedFor)
+        } else {
+            (&node.id, &other.id, RelationshipType::Related)
+        };
+        edges.push(KnowledgeEdge {
+            from_node: from.clone(),
+            to_node: to.clone(),
+            relationship_type,
+            strength,
+            created_at: now,
+        });
+    }
+    
+    if node.node_type == MemoryNodeType::Experience {
+        let previous = kg.memory_nodes.values()
+            .filter(|other| other.node_type == MemoryNodeType::Experience && other.created_at <= node.created_at)
+            .max_by_key(|other| other.created_at);
+        if let Some(previous) = previous {
+            let gap = node.created_at.saturating_sub(previous.created_at);
+            if gap < SEQUENCE_WINDOW_NANOS {
+                edges.push(KnowledgeEdge {
+                    from_node: previous.id.clone(),
+                    to_node: node.id.clone(),
+                    relationship_type: RelationshipType::LeadsTo,
+                    strength: (1.0 - gap as f32 / SEQUENCE_WINDOW_NANOS as f32).max(MIN_INFERRED_STRENGTH),
+                    created_at: now,
+                });
+            }
+        }
+    }
+    edges
+}
+
+#[ic_cdk::update]
+fn add_edge(
+    user: Principal,
+    from_node: String,
+    to_node: String,
+    relationship_type: RelationshipType,
+    strength: Option<f32>,
+) -> Result<KnowledgeEdge, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    let strength = validate_importance(strength.unwrap_or(1.0))
+        .map_err(|_| "Edge strength must be between 0 and 1".to_string())?;
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        for id in [&from_node, &to_node] {
+            if !kg.memory_nodes.contains_key(id) {
+                return Err(format!("Memory {} not found", id));
+            }
+        }
+        kg.last_updated = ic_cdk::api::time();
+        
+        let edge = KnowledgeEdge {
+            from_node,
+            to_node,
+            relationship_type,
+            strength,
+            created_at: ic_cdk::api::time(),
+        };
+        if !add_knowledge_edge(&mut state, user, edge.clone()) {
+            return Err("A memory cannot be linked to itself".to_string());
+        }
+        Ok(edge)
+    })
+}
+
+// Removes edges between two nodes; without a relationship type every edge between them is removed
+#[ic_cdk::update]
+fn remove_edge(
+    user: Principal,
+    from_node: String,
+    to_node: String,
+    relationship_type: Option<RelationshipType>,
+) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let removed = remove_knowledge_edges(&mut state, user, &from_node, &to_node, relationship_type.as_ref());
+        if removed == 0 {
+            return Err("Edge not found".to_string());
+        }
+        if let Some(kg) = state.personal_knowledge_graphs.get_mut(&user) {
+            kg.last_updated = ic_cdk::api::time();
+        }
+        Ok(format!("Removed {} edge(s)", removed))
+    })
+}
+
+// Graph traversal over the adjacency index
+
+const MAX_TRAVERSAL_DEPTH: u32 = 4;
+const MAX_SUBGRAPH_NODES: usize = 200;
+const PAGERANK_DAMPING: f32 = 0.85;
+const PAGERANK_ITERATIONS: usize = 20;
+
+impl AdjacentEdge {
+    fn to_edge(&self, node_id: &str) -> KnowledgeEdge {
+        let (from_node, to_node) = if self.outgoing {
+            (node_id.to_string(), self.neighbor.clone())
+        } else {
+            (self.neighbor.clone(), node_id.to_string())
+        };
+        KnowledgeEdge {
+            from_node,
+            to_node,
+            relationship_type: self.relationship_type.clone(),
+            strength: self.strength,
+            created_at: self.created_at,
+        }
+    }
+}
+
+fn index_edge(index: &mut MemoryIndex, edge: &KnowledgeEdge) {
+    for (node, neighbor, outgoing) in [(&edge.from_node, &edge.to_node, true), (&edge.to_node, &edge.from_node, false)] {
+        let adjacent = index.adjacency.entry(node.clone()).or_default();
+        adjacent.retain(|a| !(&a.neighbor == neighbor && a.relationship_type == edge.relationship_type && a.outgoing == outgoing));
+        adjacent.push(AdjacentEdge {
+            neighbor: neighbor.clone(),
+            relationship_type: edge.relationship_type.clone(),
+            strength: edge.strength,
+            outgoing,
+            created_at: edge.created_at,
+        });
+    }
+}
+
+fn unindex_edge(index: &mut MemoryIndex, edge: &KnowledgeEdge) {
+    for (node, neighbor, outgoing) in [(&edge.from_node, &edge.to_node, true), (&edge.to_node, &edge.from_node, false)] {
+        if let Some(adjacent) = index.adjacency.get_mut(node) {
+            adjacent.retain(|a| !(&a.neighbor == neighbor && a.relationship_type == edge.relationship_type && a.outgoing == outgoing));
+            if adjacent.is_empty() {
+                index.adjacency.remove(node);
+            }
+        }
+    }
+}
+
+fn unindex_node_edges(index: &mut MemoryIndex, node_id: &str) {
+    if let Some(adjacent) = index.adjacency.remove(node_id) {
+        for edge in adjacent {
+            if let Some(neighbor_edges) = index.adjacency.get_mut(&edge.neighbor) {
+                neighbor_edges.retain(|a| a.neighbor != node_id);
+                if neighbor_edges.is_empty() {
+                    index.adjacency.remove(&edge.neighbor);
+                }
+            }
+        }
+    }
+}
+
+// Runs a read-only closure against the caller's graph and its index
+fn with_graph_index<R>(user: Principal, f: impl FnOnce(&PersonalKnowledgeGraph, &MemoryIndex) -> R) -> Option<R> {
+    STATE.with(|state| {
+        let state = state.borrow();
+        let kg = state.personal_knowledge_graphs.get(&user)?;
+        Some(f(kg, &memory_index_for(&state, user)))
+    })
+}
+
+// Breadth-first expansion from the seeds, following edges in either direction.
+// Returns each reached node with its depth and the edge it was reached through.
+fn breadth_first(
+    index: &MemoryIndex,
+    seeds: &[String],
+    max_depth: u32,
+    max_nodes: usize,
+    relationship_types: Option<&[RelationshipType]>,
+) -> Vec<(String, u32, Option<KnowledgeEdge>)> {
+    let mut visited: HashSet<String> = seeds.iter().cloned().collect();
+    let mut queue: VecDeque<(String, u32)> = seeds.iter().map(|id| (id.clone(), 0)).collect();
+    let mut reached: Vec<(String, u32, Option<KnowledgeEdge>)> = seeds.iter().map(|id| (id.clone(), 0, None)).collect();
+    
+    while let Some((node_id, depth)) = queue.pop_front() {
+        if depth >= max_depth {
+            continue;
+        }
+        for adjacent in index.adjacency.get(&node_id).into_iter().flatten() {
+            if relationship_types.is_some_and(|types| !types.contains(&adjacent.relationship_type)) {
+                continue;
+            }
+            if reached.len() >= max_nodes {
+                return reached;
+            }
+            if visited.insert(adjacent.neighbor.clone()) {
+                reached.push((adjacent.neighbor.clone(), depth + 1, Some(adjacent.to_edge(&node_id))));
+                queue.push_back((adjacent.neighbor.clone(), depth + 1));
+            }
+        }
+    }
+    reached
+}
+
+#[ic_cdk::query]
+fn get_neighbors(
+    node_id: String,
+    relationship_types: Option<Vec<RelationshipType>>,
+    depth: Option<u32>,
+) -> Result<Vec<GraphNeighbor>, String> {
+    let depth = depth.unwrap_or(1).clamp(1, MAX_TRAVERSAL_DEPTH);
+    
+    with_graph_index(ic_cdk::caller(), |kg, index| {
+        if !kg.memory_nodes.contains_key(&node_id) {
+            return Err("Memory not found".to_string());
+        }
+        Ok(breadth_first(index, &[node_id], depth, MAX_SUBGRAPH_NODES + 1, relationship_types.as_deref())
+            .into_iter()
+            .filter_map(|(id, depth, edge)| Some(GraphNeighbor {
+                node: kg.memory_nodes.get(&id)?.clone(),
+                depth,
+                edge: edge?,
+            }))
+            .collect())
+    })
+    .unwrap_or_else(|| Err("User knowledge graph not found".to_string()))
+}
+
+// Fewest-hops path between two memories, ignoring edge direction
+#[ic_cdk::query]
+fn shortest_path(from_node: String, to_node: String) -> Result<Subgraph, String> {
+    with_graph_index(ic_cdk::caller(), |kg, index| {
+        for id in [&from_node, &to_node] {
+            if !kg.memory_nodes.contains_key(id) {
+                return Err(format!("Memory {} not found", id));
+            }
+        }
+        
+        let mut parents: HashMap<String, (String, KnowledgeEdge)> = HashMap::new();
+        let mut visited: HashSet<String> = HashSet::from([from_node.clone()]);
+        let mut queue: VecDeque<String> = VecDeque::from([from_node.clone()]);
+        while let Some(node_id) = queue.pop_front() {
+            if node_id == to_node {
+                break;
+            }
+            for adjacent in index.adjacency.get(&node_id).into_iter().flatten() {
+                if visited.insert(adjacent.neighbor.clone()) {
+                    parents.insert(adjacent.neighbor.clone(), (node_id.clone(), adjacent.to_edge(&node_id)));
+                    queue.push_back(adjacent.neighbor.clone());
+                }
+            }
+        }
+        if !visited.contains(&to_node) {
+            return Err("No path between these memories".to_string());
+        }
+        
+        let mut node_ids = vec![to_node.clone()];
+        let mut edges = Vec::new();
+        let mut current = to_node.clone();
+        while let Some((parent, edge)) = parents.get(&current) {
+            edges.push(edge.clone());
+            node_ids.push(parent.clone());
+            current = parent.clone();
+        }
+        node_ids.reverse();
+        edges.reverse();
+        
+        Ok(Subgraph {
+            nodes: node_ids.iter().filter_map(|id| kg.memory_nodes.get(id).cloned()).collect(),
+            edges,
+        })
+    })
+    .unwrap_or_else(|| Err("User knowledge graph not found".to_string()))
+}
+
+// Nodes within k hops of the seeds plus every edge among them
+#[ic_cdk::query]
+fn get_subgraph(seed_ids: Vec<String>, k_hops: u32, limit: Option<u32>) -> Result<Subgraph, String> {
+    let limit = (limit.unwrap_or(50) as usize).min(MAX_SUBGRAPH_NODES);
+    let k_hops = k_hops.min(MAX_TRAVERSAL_DEPTH);
+    
+    with_graph_index(ic_cdk::caller(), |kg, index| {
+        let seeds: Vec<String> = seed_ids.into_iter().filter(|id| kg.memory_nodes.contains_key(id)).collect();
+        if seeds.is_empty() {
+            return Err("None of the seed memories were found".to_string());
+        }
+        
+        let node_ids: HashSet<String> = breadth_first(index, &seeds, k_hops, limit, None)
+            .into_iter()
+            .map(|(id, _, _)| id)
+            .collect();
+        let edges = node_ids.iter()
+            .flat_map(|id| index.adjacency.get(id).into_iter().flatten()
+                .filter(|a| a.outgoing && node_ids.contains(&a.neighbor))
+                .map(move |a| a.to_edge(id)))
+            .collect();
+        
+        Ok(Subgraph {
+            nodes: node_ids.iter().filter_map(|id| kg.memory_nodes.get(id).cloned()).collect(),
+            edges,
+        })
+    })
+    .unwrap_or_else(|| Err("User knowledge graph not found".to_string()))
+}
+
+// Ranks memories by PageRank over the edge graph, with degree as a tie-breaker
+#[ic_cdk::query]
+fn get_central_memories(limit: Option<u32>) -> Vec<NodeCentrality> {
+    let limit = limit.unwrap_or(10).min(100) as usize;
+    
+    with_graph_index(ic_cdk::caller(), |kg, index| {
+        let node_ids: Vec<&String> = kg.memory_nodes.keys().collect();
+        let count = node_ids.len();
+        if count == 0 {
+            return Vec::new();
+        }
+        
+        let base = 1.0 / count as f32;
+        let mut rank: HashMap<&str, f32> = node_ids.iter().map(|id| (id.as_str(), base)).collect();
+        for _ in 0..PAGERANK_ITERATIONS {
+            let mut next: HashMap<&str, f32> = node_ids.iter().map(|id| (id.as_str(), (1.0 - PAGERANK_DAMPING) * base)).collect();
+            let mut dangling = 0.0;
+            for id in &node_ids {
+                let outgoing: Vec<&AdjacentEdge> = index.adjacency.get(*id).into_iter().flatten()
+                    .filter(|a| a.outgoing && kg.memory_nodes.contains_key(&a.neighbor))
+                    .collect();
+                let share = rank[id.as_str()];
+                if outgoing.is_empty() {
+                    dangling += share;
+                    continue;
+                }
+                let total_strength: f32 = outgoing.iter().map(|a| a.strength.max(f32::EPSILON)).sum();
+                for a in outgoing {
+                    if let Some(value) = next.get_mut(a.neighbor.as_str()) {
+                        *value += PAGERANK_DAMPING * share * a.strength.max(f32::EPSILON) / total_strength;
+                    }
+                }
+            }
+            for value in next.values_mut() {
+                *value += PAGERANK_DAMPING * dangling * base;
+            }
+            rank = next;
+        }
+        
+        let mut ranked: Vec<NodeCentrality> = node_ids.iter()
+            .map(|id| {
+                let adjacent = index.adjacency.get(*id).map(Vec::as_slice).unwrap_or(&[]);
+                NodeCentrality {
+                    node: kg.memory_nodes[*id].clone(),
+                    degree: adjacent.len() as u32,
+                    weighted_degree: adjacent.iter().map(|a| a.strength).sum(),
+                    pagerank: rank[id.as_str()],
+                }
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.pagerank.partial_cmp(&a.pagerank)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.degree.cmp(&a.degree)));
+        ranked.truncate(limit);
+        ranked
+    })
+    .unwrap_or_default()
+}
+
+// Tagging
+
+const MAX_AUTO_TAGS: usize = 5;
+const MAX_MODEL_TAGGED_NODES: usize = 10;
+
+// Words that say little about what a memory is about
+const GENERIC_WORDS: [&str; 32] = [
+    "also", "always", "because", "been", "being", "different", "doing", "every", "going", "have",
+    "just", "know", "like", "lot", "make", "many", "more", "most", "much", "name", "need", "prefer",
+    "really", "should", "some", "still", "thing", "things", "usually", "user", "very", "want",
+];
+
+// Lowercase, single-spaced, keeping characters that matter in names like "c++", "c#" or "node.js"
+fn normalize_tag(tag: &str) -> Option<String> {
+    let cleaned: String = tag.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || "+#.-".contains(c) { c } else { ' ' })
+        .collect();
+    let tag = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let tag = tag.trim_matches(|c| c == '.' || c == '-').to_string();
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_CHARS {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+fn tag_stem(tag: &str) -> String {
+    tag.split_whitespace().map(stem).collect::<Vec<_>>().join(" ")
+}
+
+// Maps tags onto existing vocabulary spellings ("projects" -> "project") and drops duplicates
+fn canonicalize_tags(vocabulary: &HashMap<String, u32>, tags: &[String]) -> Vec<String> {
+    let mut canonical: Vec<String> = Vec::new();
+    for tag in tags.iter().filter_map(|tag| normalize_tag(tag)) {
+        let tag = if vocabulary.contains_key(&tag) {
+            tag
+        } else {
+            let target = tag_stem(&tag);
+            vocabulary.keys()
+                .find(|existing| tag_stem(existing) == target)
+                .cloned()
+                .unwrap_or(tag)
+        };
+        if !canonical.contains(&tag) {
+            canonical.push(tag);
+        }
+    }
+    canonical
+}
+
+// Re-adding a tag the memory already has (in any spelling the vocabulary maps together) is a no-op;
+// the limit applies to the distinct tags left after that.
+fn merge_tags(vocabulary: &HashMap<String, u32>, existing: &[String], added: Vec<String>) -> Result<Vec<String>, String> {
+    let mut merged = existing.to_vec();
+    merged.extend(added);
+    let merged = canonicalize_tags(vocabulary, &merged);
+    if merged.len() > MAX_MEMORY_TAGS {
+        return Err(format!("A memory can have at most {} tags", MAX_MEMORY_TAGS));
+    }
+    Ok(merged)
+}
+
+fn update_tag_vocabulary(vocabulary: &mut HashMap<String, u32>, removed: &[String], added: &[String]) {
+    for tag in removed {
+        if let Some(count) = vocabulary.get_mut(tag) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                vocabulary.remove(tag);
+            }
+        }
+    }
+    for tag in added {
+        *vocabulary.entry(tag.clone()).or_insert(0) += 1;
+    }
+}
+
+// Keyword/entity tagging: capitalized phrases inside a sentence (people, companies, products)
+// followed by the most frequent content words
+fn generate_tags(content: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    let push_tag = |tags: &mut Vec<String>, tag: &str| {
+        if let Some(tag) = normalize_tag(tag) {
+            if !tags.contains(&tag) && tags.len() < MAX_AUTO_TAGS {
+                tags.push(tag);
+            }
+        }
+    };
+    
+    let mut phrase: Vec<&str> = Vec::new();
+    let mut sentence_start = true;
+    for raw in content.split_whitespace() {
+        let word = raw.trim_matches(|c: char| !c.is_alphanumeric() && c != '+' && c != '#');
+        let capitalized = word.chars().next().is_some_and(char::is_uppercase);
+        let is_stop_word = STOP_WORDS.contains(&word.to_lowercase().as_str());
+        
+        if capitalized && !sentence_start && !is_stop_word {
+            phrase.push(word);
+        } else if !phrase.is_empty() {
+            push_tag(&mut tags, &phrase.join(" "));
+            phrase.clear();
+        }
+        // Punctuation ends both the phrase and possibly the sentence
+        if raw.ends_with(|c: char| ",;:.!?".contains(c)) && !phrase.is_empty() {
+            push_tag(&mut tags, &phrase.join(" "));
+            phrase.clear();
+        }
+        sentence_start = raw.ends_with(|c: char| ".!?".contains(c));
+    }
+    if !phrase.is_empty() {
+        push_tag(&mut tags, &phrase.join(" "));
+    }
+    
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let lower = content.to_lowercase();
+    for word in lower.split(|c: char| !c.is_alphanumeric() && c != '+' && c != '#') {
+        if word.chars().count() >= 4 && !STOP_WORDS.contains(&word) && !GENERIC_WORDS.contains(&word) {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+    }
+    let mut keywords: Vec<(&str, usize)> = counts.into_iter().collect();
+    keywords.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.len().cmp(&a.0.len())).then(a.0.cmp(b.0)));
+    for (word, _) in keywords {
+        // Skip words already covered by an entity tag
+        if !tags.iter().any(|tag| tag.split(' ').any(|part| part == word)) {
+            push_tag(&mut tags, word);
+        }
+    }
+    tags
+}
+
+// Optional second pass: asks the model for topic tags on freshly stored memories
+async fn suggest_tags_with_model(user: Principal, node_ids: &[String]) {
+    let (enabled, api_key, nodes) = STATE.with(|state| {
+        let state = state.borrow();
+        let nodes: Vec<(String, String)> = state.personal_knowledge_graphs.get(&user)
+            .map(|kg| node_ids.iter()
+                .filter_map(|id| kg.memory_nodes.get(id))
+                .take(MAX_MODEL_TAGGED_NODES)
+                .map(|node| (node.id.clone(), node.content.clone()))
+                .collect())
+            .unwrap_or_default();
+        (state.model_tagging_enabled, state.api_key.clone(), nodes)
+    });
+    if !enabled || api_key.is_empty() || nodes.is_empty() {
+        return;
+    }
+    
+    let mut prompt = String::from(
+        "Suggest up to 3 short topic tags for each numbered note. \
+         Answer with one line per note in the form \"<number>: tag, tag\" and nothing else.\n\n",
+    );
+    for (i, (_, content)) in nodes.iter().enumerate() {
+        prompt.push_str(&format!("{}: {}\n", i + 1, truncate_chars(content, 300)));
+    }
+    
+    let reply = match call_gemini_api(prompt, api_key).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            ic_cdk::println!("Model tagging failed: {}", e);
+            return;
+        }
+    };
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        for line in reply.lines() {
+            let (number, tags) = match line.split_once(':') {
+                Some(parts) => parts,
+                None => continue,
+            };
+            let node_id = match number.trim().parse::<usize>().ok().and_then(|n| nodes.get(n.wrapping_sub(1))) {
+                Some((id, _)) => id,
+                None => continue,
+            };
+            let node = state.personal_knowledge_graphs.get(&user).and_then(|kg| kg.memory_nodes.get(node_id)).cloned();
+            if let Some(mut node) = node {
+                node.tags.extend(tags.split(',').take(3).map(|tag| tag.to_string()));
+                insert_memory_node(&mut state, user, node);
+            }
+        }
+    });
+}
+
+#[ic_cdk::update]
+fn set_model_tagging(enabled: bool) -> Result<String, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only a controller can change model tagging".to_string());
+    }
+    STATE.with(|state| state.borrow_mut().model_tagging_enabled = enabled);
+    Ok(format!("Model-assisted tagging {}", if enabled { "enabled" } else { "disabled" }))
+}
+
+#[ic_cdk::update]
+fn add_memory_tags(user: Principal, node_id: String, tags: Vec<String>) -> Result<MemoryNode, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    let tags = validate_tags(&tags)?;
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get(&user).ok_or_else(|| "Memory not found".to_string())?;
+        let mut node = kg.memory_nodes.get(&node_id).cloned().ok_or_else(|| "Memory not found".to_string())?;
+        node.tags = merge_tags(&kg.tag_vocabulary, &node.tags, tags)?;
+        insert_memory_node(&mut state, user, node).ok_or_else(|| "Memory not found".to_string())
+    })
+}
+
+#[ic_cdk::update]
+fn remove_memory_tags(user: Principal, node_id: String, tags: Vec<String>) -> Result<MemoryNode, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    let tags: Vec<String> = tags.iter().filter_map(|tag| normalize_tag(tag)).collect();
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let mut node = state.personal_knowledge_graphs.get(&user)
+            .and_then(|kg| kg.memory_nodes.get(&node_id))
+            .cloned()
+            .ok_or_else(|| "Memory not found".to_string())?;
+        node.tags.retain(|tag| !tags.contains(tag));
+        insert_memory_node(&mut state, user, node).ok_or_else(|| "Memory not found".to_string())
+    })
+}
+
+#[ic_cdk::query]
+fn list_memories_by_tag(user: Principal, tag: String) -> Vec<MemoryNode> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Vec::new();
+    }
+    let tag = match normalize_tag(&tag) {
+        Some(tag) => tag,
+        None => return Vec::new(),
+    };
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let kg = match state.personal_knowledge_graphs.get(&user) {
+            Some(kg) => kg,
+            None => return Vec::new(),
+        };
+        let tag = canonicalize_tags(&kg.tag_vocabulary, &[tag]).pop().unwrap_or_default();
+        let mut nodes: Vec<MemoryNode> = kg.memory_nodes.values()
+            .filter(|node| node.tags.contains(&tag))
+            .cloned()
+            .collect();
+        nodes.sort_by_key(|node| std::cmp::Reverse(node.created_at));
+        nodes
+    })
+}
+
+// Tag usage counts for the dashboard's tag cloud, most used first
+#[ic_cdk::query]
+fn get_tag_cloud(user: Principal, limit: Option<u32>) -> Vec<TagCount> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Vec::new();
+    }
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let mut cloud: Vec<TagCount> = state.personal_knowledge_graphs.get(&user)
+            .map(|kg| kg.tag_vocabulary.iter()
+                .map(|(tag, count)| TagCount { tag: tag.clone(), count: *count })
+                .collect())
+            .unwrap_or_default();
+        cloud.sort_by(|a, b| b.count.cmp(&a.count).then(a.tag.cmp(&b.tag)));
+        cloud.truncate(limit.unwrap_or(50) as usize);
+        cloud
+    })
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum TextListPatch {
+    Add(Vec<String>),
+    Remove(Vec<String>),
+    Replace(Vec<String>),
+}
+
+// Goals, dates and people are removed by id
+#[derive(CandidType, Deserialize, Clone)]
+enum GoalListPatch {
+    Add(Vec<GoalInput>),
+    Remove(Vec<String>),
+    Replace(Vec<GoalInput>),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum ImportantDateListPatch {
+    Add(Vec<ImportantDateInput>),
+    Remove(Vec<String>),
+    Replace(Vec<ImportantDateInput>),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+enum PersonListPatch {
+    Add(Vec<PersonInput>),
+    Remove(Vec<String>),
+    Replace(Vec<PersonInput>),
+}
+
+// Style fields set here are locked so inference leaves them alone (see lock_communication_style)
+#[derive(CandidType, Deserialize, Clone)]
+struct CommunicationStylePatch {
+    formality_level: Option<FormalityLevel>,
+    detail_preference: Option<DetailLevel>,
+    humor_preference: Option<bool>,
+    technical_level: Option<TechnicalLevel>,
+    emoji_usage: Option<bool>,
+}
+
+// An empty string clears an optional text field
+#[derive(CandidType, Deserialize, Clone)]
+struct WorkContextPatch {
+    job_title: Option<String>,
+    company: Option<String>,
+    industry: Option<String>,
+    current_projects: Option<TextListPatch>,
+    skills: Option<TextListPatch>,
+}
+
+// Every field is optional; missing fields are left as they are. An empty string clears name and
+// preferred_name. Set fields and replaced lists are marked as given by the user, which stops
+// extraction from overwriting them; added and removed items leave extraction running.
+#[derive(CandidType, Deserialize, Clone)]
+struct UserProfileUpdate {
+    name: Option<String>,
+    preferred_name: Option<String>,
+    communication_style: Option<CommunicationStylePatch>,
+    personality_traits: Option<TextListPatch>,
+    interests: Option<TextListPatch>,
+    expertise_areas: Option<TextListPatch>,
+    work_context: Option<WorkContextPatch>,
+    relationships: Option<PersonListPatch>,
+    important_dates: Option<ImportantDateListPatch>,
+    goals: Option<GoalListPatch>,
+    response_preferences: Option<ResponsePreferences>,
+}
+
+fn optional_text(value: String) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() { None } else { Some(value.to_string()) }
+}
+
+// Returns true when the patch replaced the whole list
+fn apply_text_list_patch(items: &mut Vec<String>, patch: TextListPatch) -> bool {
+    let (added, replaced) = match patch {
+        TextListPatch::Add(added) => (added, false),
+        TextListPatch::Remove(removed) => {
+            items.retain(|item| !removed.iter().any(|r| r.trim().eq_ignore_ascii_case(item)));
+            return false;
+        }
+        TextListPatch::Replace(replacement) => {
+            items.clear();
+            (replacement, true)
+        }
+    };
+    for item in added.into_iter().filter_map(optional_text) {
+        push_unique(items, item);
+    }
+    replaced
+}
+
+// Removes the items with the given ids, failing without changes if any id is unknown
+fn remove_by_id<T>(items: &mut Vec<T>, ids: &[String], id_of: impl Fn(&T) -> &str, kind: &str) -> Result<(), String> {
+    if let Some(missing) = ids.iter().find(|id| !items.iter().any(|item| id_of(item) == id.as_str())) {
+        return Err(format!("{} not found: {}", kind, missing));
+    }
+    items.retain(|item| !ids.iter().any(|id| id_of(item) == id.as_str()));
+    Ok(())
+}
+
+fn apply_profile_update(profile: &mut UserProfile, update: UserProfileUpdate, now: u64) -> Result<(), String> {
+    let explicit = |profile: &mut UserProfile, field: &str| record_provenance(profile, field, ProfileSource::Explicit, 1.0, None, now);
+    
+    if let Some(name) = update.name {
+        profile.name = optional_text(name);
+        explicit(profile, "name");
+    }
+    if let Some(preferred_name) = update.preferred_name {
+        profile.preferred_name = optional_text(preferred_name);
+        explicit(profile, "preferred_name");
+    }
+    if let Some(style) = update.communication_style {
+        let current = &mut profile.communication_style;
+        let mut set = Vec::new();
+        if let Some(formality) = style.formality_level {
+            current.formality_level = formality;
+            set.push("formality_level");
+        }
+        if let Some(detail) = style.detail_preference {
+            current.detail_preference = detail;
+            set.push("detail_preference");
+        }
+        if let Some(humor) = style.humor_preference {
+            current.humor_preference = humor;
+            set.push("humor_preference");
+        }
+        if let Some(level) = style.technical_level {
+            current.technical_level = level;
+            set.push("technical_level");
+        }
+        if let Some(emoji) = style.emoji_usage {
+            current.emoji_usage = emoji;
+            set.push("emoji_usage");
+        }
+        for field in set {
+            if !current.locked_fields.iter().any(|locked| locked == field) {
+                current.locked_fields.push(field.to_string());
+            }
+        }
+    }
+    if update.personality_traits.is_some_and(|patch| apply_text_list_patch(&mut profile.personality_traits, patch)) {
+        explicit(profile, "personality_traits");
+    }
+    if update.interests.is_some_and(|patch| apply_text_list_patch(&mut profile.interests, patch)) {
+        explicit(profile, "interests");
+    }
+    if update.expertise_areas.is_some_and(|patch| apply_text_list_patch(&mut profile.expertise_areas, patch)) {
+        explicit(profile, "expertise_areas");
+    }
+    if let Some(work) = update.work_context {
+        let current = profile.work_context.get_or_insert_with(WorkContext::default);
+        let mut set = Vec::new();
+        if let Some(job_title) = work.job_title {
+            current.job_title = optional_text(job_title);
+            set.push("work_context.job_title");
+        }
+        if let Some(company) = work.company {
+            current.company = optional_text(company);
+            set.push("work_context.company");
+        }
+        if let Some(industry) = work.industry {
+            current.industry = optional_text(industry);
+            set.push("work_context.industry");
+        }
+        if work.current_projects.is_some_and(|patch| apply_text_list_patch(&mut current.current_projects, patch)) {
+            set.push("work_context.current_projects");
+        }
+        if work.skills.is_some_and(|patch| apply_text_list_patch(&mut current.skills, patch)) {
+            set.push("work_context.skills");
+        }
+        for field in set {
+            explicit(profile, field);
+        }
+    }
+    match update.relationships {
+        Some(PersonListPatch::Add(people)) => {
+            for person in people {
+                push_person(profile, person, now)?;
+            }
+        }
+        Some(PersonListPatch::Remove(ids)) => remove_by_id(&mut profile.relationships, &ids, |p| &p.id, "Person")?,
+        Some(PersonListPatch::Replace(people)) => {
+            profile.relationships.clear();
+            for person in people {
+                push_person(profile, person, now)?;
+            }
+            explicit(profile, "relationships");
+        }
+        None => {}
+    }
+    match update.important_dates {
+        Some(ImportantDateListPatch::Add(events)) => {
+            for event in events {
+                push_important_date(profile, event)?;
+            }
+        }
+        Some(ImportantDateListPatch::Remove(ids)) => remove_by_id(&mut profile.important_dates, &ids, |e| &e.id, "Important date")?,
+        Some(ImportantDateListPatch::Replace(events)) => {
+            profile.important_dates.clear();
+            for event in events {
+                push_important_date(profile, event)?;
+            }
+            explicit(profile, "important_dates");
+        }
+        None => {}
+    }
+    match update.goals {
+        Some(GoalListPatch::Add(goals)) => {
+            for goal in goals {
+                push_goal(profile, goal, now)?;
+            }
+        }
+        Some(GoalListPatch::Remove(ids)) => remove_by_id(&mut profile.goals, &ids, |g| &g.id, "Goal")?,
+        Some(GoalListPatch::Replace(goals)) => {
+            profile.goals.clear();
+            for goal in goals {
+                push_goal(profile, goal, now)?;
+            }
+            explicit(profile, "goals");
+        }
+        None => {}
+    }
+    if let Some(response_prefs) = update.response_preferences {
+        profile.response_preferences = response_prefs;
+    }
+    Ok(())
+}
+
+// Patches the profile, creating the user's graph on their first successful call. The patch applies
+// in full or not at all.
+#[ic_cdk::update]
+fn update_user_profile(user: Principal, profile_update: UserProfileUpdate) -> Result<UserProfile, String> {
+    let caller = ic_cdk::caller();
+    if caller != user && !ic_cdk::api::is_controller(&caller) {
+        return Err("Unauthorized".to_string());
+    }
+    
+    let mut profile = STATE.with(|state| {
+        state.borrow().personal_knowledge_graphs.get(&user).map(|kg| kg.user_profile.clone())
+    }).unwrap_or_default();
+    apply_profile_update(&mut profile, profile_update, ic_cdk::api::time())?;
+    
+    ensure_user_knowledge_graph(user);
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let kg = state.personal_knowledge_graphs.get_mut(&user)
+            .ok_or_else(|| "User knowledge graph not found".to_string())?;
+        // Progress waiting for confirmation on a goal the patch removed has nothing left to apply to
+        if kg.pending_goal_update.as_ref().is_some_and(|update| !profile.goals.iter().any(|goal| goal.id == update.goal_id)) {
+            kg.pending_goal_update = None;
+        }
+        kg.user_profile = profile.clone();
+        kg.last_updated = ic_cdk::api::time();
+        Ok(profile)
+    })
+}
+
+// Attachments
+
+// HTTPS outcall requests are capped at 2MB and inline data is base64 encoded
+const MAX_ATTACHMENT_BYTES: u64 = 1_400_000;
+const MAX_USER_ATTACHMENT_BYTES: u64 = 20_000_000;
+const MAX_UPLOAD_CHUNK_BYTES: usize = 1_000_000;
+const UPLOAD_EXPIRY_NANOS: u64 = 60 * 60 * 1_000_000_000;
+const MAX_ATTACHMENT_FACTS: usize = 5;
+
+const SUPPORTED_ATTACHMENT_TYPES: [&str; 9] = [
+    "image/png",
+    "image/jpeg",
+    "image/webp",
+    "image/heic",
+    "image/heif",
+    "application/pdf",
+    "text/plain",
+    "text/markdown",
+    "text/csv",
+];
+
+impl Attachment {
+    fn info(&self) -> AttachmentInfo {
+        AttachmentInfo {
+            id: self.id.clone(),
+            file_name: self.file_name.clone(),
+            mime_type: self.mime_type.clone(),
+            size_bytes: self.data.len() as u64,
+            uploaded_at: self.uploaded_at,
+            facts_extracted: self.facts_extracted,
+        }
+    }
+}
+
+#[ic_cdk::update]
+fn begin_upload(file_name: String, mime_type: String, total_size: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot upload files".to_string());
+    }
+    let mime_type = mime_type.trim().to_lowercase();
+    if !SUPPORTED_ATTACHMENT_TYPES.contains(&mime_type.as_str()) {
+        return Err(format!("Unsupported file type '{}'", mime_type));
+    }
+    if total_size == 0 || total_size > MAX_ATTACHMENT_BYTES {
+        return Err(format!("Files must be between 1 and {} bytes", MAX_ATTACHMENT_BYTES));
+    }
+    let file_name = file_name.trim().to_string();
+    if file_name.is_empty() || file_name.len() > 255 {
+        return Err("File name must be between 1 and 255 characters".to_string());
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let now = ic_cdk::api::time();
+        state.pending_uploads.retain(|_, upload| now.saturating_sub(upload.started_at) < UPLOAD_EXPIRY_NANOS);
+        
+        let stored: u64 = state.attachments.get(&caller)
+            .map(|files| files.values().map(|a| a.data.len() as u64).sum())
+            .unwrap_or(0);
+        let pending: u64 = state.pending_uploads.values()
+            .filter(|upload| upload.owner == caller)
+            .map(|upload| upload.total_size)
+            .sum();
+        if stored + pending + total_size > MAX_USER_ATTACHMENT_BYTES {
+            return Err(format!("Upload would exceed your {} byte storage limit", MAX_USER_ATTACHMENT_BYTES));
+        }
+        
+        state.next_upload_id += 1;
+        let upload_id = format!("upload_{}", state.next_upload_id);
+        state.pending_uploads.insert(upload_id.clone(), PendingUpload {
+            owner: caller,
+            file_name,
+            mime_type,
+            total_size,
+            data: Vec::with_capacity(total_size as usize),
+            next_chunk: 0,
+            started_at: now,
+        });
+        Ok(upload_id)
+    })
+}
+
+// Returns the number of bytes received so far
+#[ic_cdk::update]
+fn upload_chunk(upload_id: String, chunk_index: u32, data: Vec<u8>) -> Result<u64, String> {
+    let caller = ic_cdk::caller();
+    if data.is_empty() || data.len() > MAX_UPLOAD_CHUNK_BYTES {
+        return Err(format!("Chunks must be between 1 and {} bytes", MAX_UPLOAD_CHUNK_BYTES));
+    }
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let upload = state.pending_uploads.get_mut(&upload_id)
+            .filter(|upload| upload.owner == caller)
+            .ok_or_else(|| "Upload not found".to_string())?;
+        
+        if chunk_index != upload.next_chunk {
+            return Err(format!("Expected chunk {}, got {}", upload.next_chunk, chunk_index));
+        }
+        if upload.data.len() as u64 + data.len() as u64 > upload.total_size {
+            return Err("Chunk exceeds the declared file size".to_string());
+        }
+        
+        upload.data.extend_from_slice(&data);
+        upload.next_chunk += 1;
+        Ok(upload.data.len() as u64)
+    })
+}
+
+// Removes a completed upload from the pending set, checking ownership and size
+fn take_completed_upload(state: &mut State, caller: Principal, upload_id: &str) -> Result<PendingUpload, String> {
+    match state.pending_uploads.get(upload_id) {
+        Some(upload) if upload.owner == caller => {
+            if upload.data.len() as u64 != upload.total_size {
+                return Err(format!("Upload incomplete: {} of {} bytes received", upload.data.len(), upload.total_size));
+            }
+        }
+        _ => return Err("Upload not found".to_string()),
+    }
+    Ok(state.pending_uploads.remove(upload_id).expect("upload was just checked"))
+}
+
+#[ic_cdk::update]
+fn finish_attachment_upload(upload_id: String) -> Result<AttachmentInfo, String> {
+    let caller = ic_cdk::caller();
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let upload = take_completed_upload(&mut state, caller, &upload_id)?;
+        
+        let attachment = Attachment {
+            id: format!("attachment_{}", upload_id.trim_start_matches("upload_")),
+            file_name: upload.file_name,
+            mime_type: upload.mime_type,
+            data: upload.data,
+            uploaded_at: ic_cdk::api::time(),
+            facts_extracted: false,
+        };
+        let info = attachment.info();
+        state.canister_metrics.storage_used_bytes += info.size_bytes;
+        state.attachments.entry(caller).or_default().insert(attachment.id.clone(), attachment);
+        Ok(info)
+    })
+}
+
+#[ic_cdk::query]
+fn list_attachments() -> Vec<AttachmentInfo> {
+    let caller = ic_cdk::caller();
+    STATE.with(|state| {
+        let state = state.borrow();
+        let mut files: Vec<AttachmentInfo> = state.attachments.get(&caller)
+            .map(|files| files.values().map(Attachment::info).collect())
+            .unwrap_or_default();
+        files.sort_by_key(|file| file.uploaded_at);
+        files
+    })
+}
+
+#[ic_cdk::update]
+fn delete_attachment(attachment_id: String) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let removed = state.attachments.get_mut(&caller)
+            .and_then(|files| files.remove(&attachment_id))
+            .ok_or_else(|| "Attachment not found".to_string())?;
+        state.canister_metrics.storage_used_bytes = state.canister_metrics.storage_used_bytes
+            .saturating_sub(removed.data.len() as u64);
+        Ok(format!("Deleted {}", removed.file_name))
+    })
+}
+
+fn load_attachments(user: Principal, attachment_ids: &[String]) -> Result<Vec<Attachment>, String> {
+    STATE.with(|state| {
+        let state = state.borrow();
+        let files = state.attachments.get(&user);
+        let attachments: Vec<Attachment> = attachment_ids.iter()
+            .map(|id| files.and_then(|files| files.get(id)).cloned()
+                .ok_or_else(|| format!("Attachment {} not found", id)))
+            .collect::<Result<_, _>>()?;
+        
+        let total: u64 = attachments.iter().map(|a| a.data.len() as u64).sum();
+        if total > MAX_ATTACHMENT_BYTES {
+            return Err(format!("Attachments in one prompt cannot exceed {} bytes", MAX_ATTACHMENT_BYTES));
+        }
+        Ok(attachments)
+    })
+}
+
+// Asks the model what an attachment says about the user and stores the answers as Knowledge memories
+async fn extract_facts_from_attachments(user: Principal, attachments: &[Attachment]) {
+    let api_key = STATE.with(|state| state.borrow().api_key.clone());
+    if api_key.is_empty() {
+        return;
+    }
+    
+    for attachment in attachments.iter().filter(|a| !a.facts_extracted) {
+        let instruction = format!(
+            "List up to {} durable facts about the user, their work or their plans that this file ({}) reveals. \
+             Write one fact per line starting with \"- \". Reply with NONE if nothing is worth remembering.",
+            MAX_ATTACHMENT_FACTS, attachment.file_name
+        );
+        let reply = match call_gemini_api_with_parts(vec![Part::text(instruction), Part::inline(attachment)], api_key.clone()).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                ic_cdk::println!("Fact extraction failed for {}: {}", attachment.id, e);
+                continue;
+            }
+        };
+        
+        let facts: Vec<String> = reply.lines()
+            .filter_map(|line| line.trim().strip_prefix("- "))
+            .map(|fact| fact.trim().to_string())
+            .filter(|fact| !fact.is_empty())
+            .take(MAX_ATTACHMENT_FACTS)
+            .collect();
+        
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            for fact in facts {
+                let id = allocate_memory_id(&mut state, user);
+                let content = format!("From {}: {}", attachment.file_name, fact);
+                store_memory_node(&mut state, user, MemoryNode::new(id, content, MemoryNodeType::Knowledge, 0.6), true);
+            }
+            if let Some(stored) = state.attachments.get_mut(&user).and_then(|files| files.get_mut(&attachment.id)) {
+                stored.facts_extracted = true;
+            }
+        });
+    }
+}
+
+// Document ingestion
+
+const DOCUMENT_TYPES: [&str; 2] = ["text/plain", "text/markdown"];
+const PASSAGE_TARGET_CHARS: usize = 800;
+const MAX_DOCUMENT_PASSAGES: usize = 200;
+const DOCUMENT_EMBEDDING_BATCHES: usize = 4;
+
+// Turns a completed text upload into a Document node with one Knowledge node per passage
+#[ic_cdk::update]
+async fn finish_document_upload(upload_id: String, title: Option<String>) -> Result<DocumentInfo, String> {
+    let caller = ic_cdk::caller();
+    ensure_user_knowledge_graph(caller);
+    
+    let info = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        match state.pending_uploads.get(&upload_id) {
+            Some(upload) if upload.owner == caller && !DOCUMENT_TYPES.contains(&upload.mime_type.as_str()) => {
+                return Err("Only plain text and Markdown documents can be ingested".to_string());
+            }
+            _ => {}
+        }
+        let upload = take_completed_upload(&mut state, caller, &upload_id)?;
+        // A rejected document stays uploaded so the caller can retry it as an attachment instead
+        let passages = match document_passages(&upload.data) {
+            Ok(passages) => passages,
+            Err(e) => {
+                state.pending_uploads.insert(upload_id, upload);
+                return Err(e);
+            }
+        };
+        
+        let title = title.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).unwrap_or(upload.file_name);
+        let now = ic_cdk::api::time();
+        let document_id = allocate_memory_id(&mut state, caller);
+        insert_memory_node(&mut state, caller, MemoryNode::new(document_id.clone(), title.clone(), MemoryNodeType::Document, 0.5));
+        
+        for passage in &passages {
+            let passage_id = allocate_memory_id(&mut state, caller);
+            insert_memory_node(&mut state, caller, MemoryNode::new(passage_id.clone(), passage.clone(), MemoryNodeType::Knowledge, 0.5));
+            add_knowledge_edge(&mut state, caller, KnowledgeEdge {
+                from_node: passage_id,
+                to_node: document_id.clone(),
+                relationship_type: RelationshipType::PartOf,
+                strength: 1.0,
+                created_at: now,
+            });
+        }
+        
+        Ok(DocumentInfo {
+            id: document_id,
+            title,
+            passage_count: passages.len() as u32,
+            created_at: now,
+        })
+    })?;
+    
+    // Embed the first batches now; whatever is left is picked up by the embedding timer
+    for _ in 0..DOCUMENT_EMBEDDING_BATCHES {
+        if embed_pending_memories(caller).await == 0 {
+            break;
+        }
+    }
+    
+    Ok(info)
+}
+
+#[ic_cdk::query]
+fn list_documents() -> Vec<DocumentInfo> {
+    let caller = ic_cdk::caller();
+    STATE.with(|state| {
+        let state = state.borrow();
+        let kg = match state.personal_knowledge_graphs.get(&caller) {
+            Some(kg) => kg,
+            None => return Vec::new(),
+        };
+        
+        let mut documents: Vec<DocumentInfo> = kg.memory_nodes.values()
+            .filter(|node| matches!(node.node_type, MemoryNodeType::Document))
+            .map(|node| DocumentInfo {
+                id: node.id.clone(),
+                title: node.content.clone(),
+                passage_count: document_passage_ids(kg, &node.id).len() as u32,
+                created_at: node.created_at,
+            })
+            .collect();
+        documents.sort_by_key(|doc| doc.created_at);
+        documents
+    })
+}
+
+#[ic_cdk::update]
+fn delete_document(document_id: String) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let passage_ids = match state.personal_knowledge_graphs.get(&caller).and_then(|kg| kg.memory_nodes.get(&document_id)) {
+            Some(node) if matches!(node.node_type, MemoryNodeType::Document) => {
+                document_passage_ids(&state.personal_knowledge_graphs[&caller], &document_id)
+            }
+            _ => return Err("Document not found".to_string()),
+        };
+        
+        for id in passage_ids.iter().chain(std::iter::once(&document_id)) {
+            remove_memory_node(&mut state, caller, id);
+        }
+        Ok(format!("Deleted document with {} passages", passage_ids.len()))
+    })
+}
+
+fn document_passage_ids(kg: &PersonalKnowledgeGraph, document_id: &str) -> Vec<String> {
+    kg.relationships.iter()
+        .filter(|edge| edge.to_node == document_id && matches!(edge.relationship_type, RelationshipType::PartOf))
+        .map(|edge| edge.from_node.clone())
+        .collect()
+}
+
+// Title of the document a passage belongs to, if any
+fn document_title(state: &State, user: &Principal, node_id: &str) -> Option<String> {
+    let kg = state.personal_knowledge_graphs.get(user)?;
+    kg.relationships.iter()
+        .filter(|edge| edge.from_node == node_id && matches!(edge.relationship_type, RelationshipType::PartOf))
+        .filter_map(|edge| kg.memory_nodes.get(&edge.to_node))
+        .find(|node| matches!(node.node_type, MemoryNodeType::Document))
+        .map(|node| node.content.clone())
+}
+
+fn document_titles_for(user: Principal, memories: &[MemoryNode]) -> Vec<String> {
+    STATE.with(|state| {
+        let state = state.borrow();
+        let mut titles: Vec<String> = Vec::new();
+        for memory in memories {
+            if let Some(title) = document_title(&state, &user, &memory.id) {
+                if !titles.contains(&title) {
+                    titles.push(title);
+                }
+            }
+        }
+        titles
+    })
+}
+
+fn document_passages(data: &[u8]) -> Result<Vec<String>, String> {
+    let text = std::str::from_utf8(data).map_err(|_| "Document is not valid UTF-8".to_string())?;
+    let passages = split_into_passages(text);
+    if passages.is_empty() {
+        return Err("Document has no text".to_string());
+    }
+    if passages.len() > MAX_DOCUMENT_PASSAGES {
+        return Err(format!("Document is too long ({} passages, max {})", passages.len(), MAX_DOCUMENT_PASSAGES));
+    }
+    Ok(passages)
+}
+
+// Splits text on blank lines into passages of roughly PASSAGE_TARGET_CHARS, prefixing each passage
+// with the closest Markdown heading so it still makes sense on its own
+fn split_into_passages(text: &str) -> Vec<String> {
+    let mut passages = Vec::new();
+    let mut heading = String::new();
+    let mut current = String::new();
+    
+    let flush = |passages: &mut Vec<String>, heading: &str, current: &mut String| {
+        if !current.trim().is_empty() {
+            if heading.is_empty() {
+                passages.push(current.trim().to_string());
+            } else {
+                passages.push(format!("{}: {}", heading, current.trim()));
+            }
+        }
+        current.clear();
+    };
+    
+    for block in text.replace("\r\n", "\n").split("\n\n") {
+        let mut block = block.trim();
+        if block.is_empty() {
+            continue;
+        }
+        
+        if block.starts_with('#') {
+            flush(&mut passages, &heading, &mut current);
+            let (line, rest) = block.split_once('\n').unwrap_or((block, ""));
+            heading = line.trim_start_matches('#').trim().to_string();
+            block = rest.trim();
+            if block.is_empty() {
+                continue;
+            }
+        }
+        
+        // Oversized paragraphs are cut on word boundaries
+        let mut pieces = Vec::new();
+        let mut piece = String::new();
+        for word in block.split_whitespace() {
+            if !piece.is_empty() && piece.chars().count() + word.chars().count() + 1 > PASSAGE_TARGET_CHARS {
+                pieces.push(std::mem::take(&mut piece));
+            }
+            if !piece.is_empty() {
+                piece.push(' ');
+            }
+            piece.push_str(word);
+        }
+        if !piece.is_empty() {
+            pieces.push(piece);
+        }
+        // Keep the original line breaks when the paragraph fits in one piece
+        if pieces.len() == 1 {
+            pieces[0] = block.to_string();
+        }
+        
+        for piece in pieces {
+            if !current.is_empty() && current.chars().count() + piece.chars().count() > PASSAGE_TARGET_CHARS {
+                flush(&mut passages, &heading, &mut current);
+            }
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(&piece);
+        }
+    }
+    flush(&mut passages, &heading, &mut current);
+    passages
+}
+
+// Prompt template management (controllers only)
+#[ic_cdk::update]
+fn set_prompt_template(
+    persona: String,
+    kind: PromptTemplateKind,
+    body: String,
+    note: Option<String>,
+    activate: bool,
+) -> Result<u32, String> {
+    let caller = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only a controller can manage prompt templates".to_string());
+    }
+    let persona = normalize_persona(&persona)?;
+    validate_template(&body)?;
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let store = &mut state.prompt_templates;
+        let version = store.templates.iter()
+            .filter(|t| t.persona == persona && t.kind == kind)
+            .map(|t| t.version)
+            .max()
+            .unwrap_or(0) + 1;
+        
+        if activate {
+            store.active_versions.insert(template_key(&persona, &kind), version);
+        }
+        store.templates.push(PromptTemplate {
+            persona,
+            kind,
+            version,
+            body,
+            note,
+            created_at: ic_cdk::api::time(),
+            created_by: caller,
+        });
+        Ok(version)
+    })
 }
 
-// Enhanced ICP functions with MemoryMind integration
 #[ic_cdk::update]
-async fn icp_ai_prompt(
-    prompt_text: String,
-    _provider: Option<String>,
-    _assistant_type: Option<String>,
-    _store_on_chain: Option<bool>,
-) -> Result<String, String> {
-    memory_mind_prompt(prompt_text, None, Some(true)).await
-}
-
-// MemoryMind specific query functions
-#[ic_cdk::query]
-fn get_user_knowledge_graph(user: Principal) -> Option<PersonalKnowledgeGraph> {
-    let caller = ic_cdk::caller();
-    if caller != user && !ic_cdk::api::is_controller(&caller) {
-        return None;
+fn activate_prompt_template(persona: String, kind: PromptTemplateKind, version: Option<u32>) -> Result<String, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only a controller can manage prompt templates".to_string());
     }
+    let persona = normalize_persona(&persona)?;
     
     STATE.with(|state| {
-        state.borrow().personal_knowledge_graphs.get(&user).cloned()
+        let mut state = state.borrow_mut();
+        let store = &mut state.prompt_templates;
+        let key = template_key(&persona, &kind);
+        match version {
+            // No version means falling back to the default persona / built-in text
+            None => {
+                store.active_versions.remove(&key);
+                Ok(format!("{:?} template for '{}' reset to default", kind, persona))
+            }
+            Some(version) => {
+                if !store.templates.iter().any(|t| t.persona == persona && t.kind == kind && t.version == version) {
+                    return Err(format!("Version {} not found for {}", version, key));
+                }
+                store.active_versions.insert(key, version);
+                Ok(format!("{:?} template for '{}' now at version {}", kind, persona, version))
+            }
+        }
     })
 }
 
 #[ic_cdk::query]
-fn get_user_memories(user: Principal, limit: Option<u32>) -> Vec<MemoryNode> {
-    let caller = ic_cdk::caller();
-    if caller != user && !ic_cdk::api::is_controller(&caller) {
+fn list_prompt_templates(persona: Option<String>) -> Vec<PromptTemplate> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
         return Vec::new();
     }
     
     STATE.with(|state| {
         let state = state.borrow();
-        if let Some(kg) = state.personal_knowledge_graphs.get(&user) {
-            let limit = limit.unwrap_or(50) as usize;
-            kg.memory_nodes.values()
-                .take(limit)
-                .cloned()
-                .collect()
-        } else {
-            Vec::new()
+        state.prompt_templates.templates.iter()
+            .filter(|t| persona.is_none() || persona.as_ref() == Some(&t.persona))
+            .cloned()
+            .collect()
+    })
+}
+
+#[ic_cdk::update]
+fn set_default_persona(persona: String) -> Result<String, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only a controller can manage prompt templates".to_string());
+    }
+    let persona = normalize_persona(&persona)?;
+    
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if !persona_exists(&state.prompt_templates, &persona) {
+            return Err(format!("Persona '{}' has no templates", persona));
         }
+        state.prompt_templates.default_persona = Some(persona.clone());
+        Ok(format!("Default persona set to '{}'", persona))
     })
 }
 
+// Lets a user pick one of the personas defined by the controllers; None restores the default
 #[ic_cdk::update]
-fn update_user_profile(user: Principal, profile_update: UserProfileUpdate) -> Result<String, String> {
+fn select_persona(persona: Option<String>) -> Result<String, String> {
     let caller = ic_cdk::caller();
-    if caller != user && !ic_cdk::api::is_controller(&caller) {
-        return Err("Unauthorized".to_string());
-    }
     
     STATE.with(|state| {
         let mut state = state.borrow_mut();
-        if let Some(kg) = state.personal_knowledge_graphs.get_mut(&user) {
-            if let Some(name) = profile_update.name {
-                kg.user_profile.name = Some(name);
-            }
-            if let Some(interests) = profile_update.interests {
-                kg.user_profile.interests = interests;
+        match persona {
+            Some(persona) => {
+                let persona = normalize_persona(&persona)?;
+                if !persona_exists(&state.prompt_templates, &persona) {
+                    return Err(format!("Persona '{}' does not exist", persona));
+                }
+                state.prompt_templates.user_personas.insert(caller, persona.clone());
+                Ok(format!("Persona set to '{}'", persona))
             }
-            if let Some(response_prefs) = profile_update.response_preferences {
-                kg.user_profile.response_preferences = response_prefs;
+            None => {
+                state.prompt_templates.user_personas.remove(&caller);
+                Ok("Persona reset to default".to_string())
             }
-            kg.last_updated = ic_cdk::api::time();
-            Ok("Profile updated successfully".to_string())
-        } else {
-            Err("User knowledge graph not found".to_string())
         }
     })
 }
 
-#[derive(CandidType, Deserialize, Clone)]
-struct UserProfileUpdate {
-    name: Option<String>,
-    interests: Option<Vec<String>>,
-    goals: Option<Vec<PersonalGoal>>,
-    response_preferences: Option<ResponsePreferences>,
+// Dry run: renders a template (or an unsaved draft) against a real user's profile without calling the model
+#[ic_cdk::query]
+fn preview_prompt_template(request: PromptPreviewRequest) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only a controller can preview prompt templates".to_string());
+    }
+    if let Some(body) = &request.body {
+        validate_template(body)?;
+    }
+    
+    let user = request.user.unwrap_or(caller);
+    let (user_context, memories) = get_user_context_and_memories(user, &request.question, None, None);
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let persona = match &request.persona {
+            Some(persona) => normalize_persona(persona)?,
+            None => persona_for_user(&state.prompt_templates, &user),
+        };
+        let body = request.body.clone()
+            .unwrap_or_else(|| active_template_body(&state.prompt_templates, &persona, &request.kind));
+        
+        // Sample strategy fields so reply templates render with representative text
+        let extras = [
+            ("inquiry", format!("Could you provide a bit more context about {}?", extract_topic_from_prompt(&request.question))),
+            ("why_asking", "This helps me understand your specific situation".to_string()),
+            ("known_info", "(known information)".to_string()),
+            ("clarification", "(clarification needed)".to_string()),
+            ("suggestion", "(suggestion)".to_string()),
+        ];
+        let values = template_values(&state, &user, &request.question, &user_context, &memories, &extras);
+        Ok(render_template(&body, &values))
+    })
+}
+
+// Ranked full-text search over the caller's memories; works without any outcalls
+#[ic_cdk::query]
+fn search_memories(query: String, limit: Option<u32>) -> Vec<ScoredMemory> {
+    let caller = ic_cdk::caller();
+    let limit = limit.unwrap_or(10).min(100) as usize;
+    
+    STATE.with(|state| {
+        let state = state.borrow();
+        let kg = match state.personal_knowledge_graphs.get(&caller) {
+            Some(kg) => kg,
+            None => return Vec::new(),
+        };
+        let hits = bm25_search(&memory_index_for(&state, caller), &query);
+        
+        hits.into_iter()
+            .filter_map(|(id, score)| kg.memory_nodes.get(&id).map(|node| ScoredMemory { node: node.clone(), score }))
+            .take(limit)
+            .collect()
+    })
 }
 
 // Function to retrieve user conversations
@@ -993,6 +7746,28 @@ fn calculate_days_since_first_interaction(user: &Principal, state: &State) -> u6
     }
 }
 
+impl MemoryNode {
+    fn new(id: String, content: String, node_type: MemoryNodeType, importance_score: f32) -> Self {
+        let now = ic_cdk::api::time();
+        MemoryNode {
+            id,
+            content,
+            node_type,
+            importance_score,
+            created_at: now,
+            last_accessed: now,
+            access_count: 1,
+            tags: Vec::new(),
+            related_conversations: Vec::new(),
+            embedding: None,
+            pinned: false,
+            fact_key: None,
+            superseded_by: None,
+            version: 1,
+        }
+    }
+}
+
 // Utility functions for MemoryNodeType display
 impl MemoryNodeType {
     fn to_string(&self) -> &'static str {
@@ -1004,21 +7779,26 @@ impl MemoryNodeType {
             MemoryNodeType::Experience => "Experience",
             MemoryNodeType::Knowledge => "Knowledge",
             MemoryNodeType::Context => "Context",
+            MemoryNodeType::Document => "Document",
+            MemoryNodeType::Summary => "Summary",
+            MemoryNodeType::Entity => "Entity",
         }
     }
 }
 
 // Keep existing utility functions
 async fn call_gemini_api(prompt: String, api_key: String) -> Result<String, String> {
+    call_gemini_api_with_parts(vec![Part::text(prompt)], api_key).await
+}
+
+async fn call_gemini_api_with_parts(parts: Vec<Part>, api_key: String) -> Result<String, String> {
     let url = format!(
         "https://generativelanguage.googleapis.com/v1beta/models/gemini-1.5-flash-latest:generateContent?key={}",
         api_key
     );
 
     let request_body = GeminiRequest {
-        contents: vec![Content {
-            parts: vec![Part { text: prompt }],
-        }],
+        contents: vec![Content { parts }],
     };
 
     let request_body_bytes = serde_json::to_vec(&request_body)
@@ -1062,6 +7842,62 @@ async fn call_gemini_api(prompt: String, api_key: String) -> Result<String, Stri
     }
 }
 
+async fn call_gemini_embeddings(texts: Vec<String>, api_key: String) -> Result<Vec<Vec<f32>>, String> {
+    let url = format!(
+        "https://generativelanguage.googleapis.com/v1beta/{}:batchEmbedContents?key={}",
+        EMBEDDING_MODEL, api_key
+    );
+    let expected = texts.len();
+    
+    let request_body = BatchEmbedContentsRequest {
+        requests: texts.into_iter()
+            .map(|text| EmbedContentRequest {
+                model: EMBEDDING_MODEL.to_string(),
+                content: Content { parts: vec![Part::text(text)] },
+            })
+            .collect(),
+    };
+    
+    let request_body_bytes = serde_json::to_vec(&request_body)
+        .map_err(|e| format!("Serialization error: {}", e))?;
+    
+    let request = CanisterHttpRequestArgument {
+        url,
+        method: HttpMethod::POST,
+        body: Some(request_body_bytes),
+        // Each 768-dimension embedding is roughly 16KB of JSON
+        max_response_bytes: Some(expected as u64 * 20_000 + 1_024),
+        transform: None,
+        headers: vec![
+            HttpHeader {
+                name: "Content-Type".to_string(),
+                value: "application/json".to_string(),
+            },
+        ],
+    };
+    
+    match http_request(request, 20_000_000_000).await {
+        Ok((response,)) => {
+            if response.status >= 200u32 && response.status < 300u32 {
+                let response_body: BatchEmbedContentsResponse = serde_json::from_slice(&response.body)
+                    .map_err(|e| format!("Failed to parse embedding response: {}", e))?;
+                
+                if response_body.embeddings.len() != expected {
+                    return Err(format!("Expected {} embeddings, got {}", expected, response_body.embeddings.len()));
+                }
+                Ok(response_body.embeddings.into_iter().map(|e| e.values).collect())
+            } else {
+                Err(format!(
+                    "Embedding call failed with status {}: {}",
+                    response.status,
+                    String::from_utf8_lossy(&response.body)
+                ))
+            }
+        }
+        Err((code, msg)) => Err(format!("HTTP request failed: {:?} {}", code, msg)),
+    }
+}
+
 // Keep existing functions for backward compatibility
 #[ic_cdk::query]
 fn get_available_providers() -> Vec<String> {
@@ -1075,4 +7911,686 @@ fn get_canister_metrics() -> CanisterMetrics {
     STATE.with(|state| state.borrow().canister_metrics.clone())
 }
 
-ic_cdk::export_candid!();
\ No newline at end of file
+ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn fact_keys_need_first_person_statements() {
+        assert_eq!(fact_key_for("My name is Dana"), Some(("name".to_string(), "dana".to_string())));
+        assert_eq!(fact_key_for("I work as a nurse"), Some(("occupation".to_string(), "nurse".to_string())));
+        assert_eq!(fact_key_for("His name is Rex"), None);
+        assert_eq!(fact_key_for("I'm a bit tired today"), None);
+    }
+    
+    const DAY: i64 = NANOS_PER_DAY as i64;
+    
+    #[test]
+    fn civil_dates_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        for days in [-40000, -1, 0, 59, 11016, 20000] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+    
+    #[test]
+    fn leap_days_are_validated() {
+        assert!(civil_day(2024, 2, 29).is_some());
+        assert!(civil_day(2000, 2, 29).is_some());
+        assert!(civil_day(1900, 2, 29).is_none());
+        assert!(civil_day(2023, 2, 29).is_none());
+        assert!(civil_day(2023, 4, 31).is_none());
+        assert!(civil_day(2023, 13, 1).is_none());
+    }
+    
+    #[test]
+    fn dates_before_1970_parse() {
+        let now = date_to_nanos(2025, 6, 1).unwrap() as u64;
+        let birthday = parse_date("March 5, 1962", now).unwrap();
+        assert!(birthday < 0);
+        assert_eq!(civil_from_days(birthday.div_euclid(DAY)), (1962, 3, 5));
+        assert_eq!(parse_date("1962-03-05", now), Some(birthday));
+        assert_eq!(parse_date("5th of March 1962", now), Some(birthday));
+        assert_eq!(parse_date("March 5th", now), date_to_nanos(2025, 3, 5));
+        assert_eq!(parse_date("February 30", now), None);
+    }
+    
+    #[test]
+    fn profile_names_need_first_person() {
+        assert!(matches!(&extract_profile_facts("My name is Dana.", 0)[..], [ProfileFact::Name(name)] if name == "Dana"));
+        assert!(extract_profile_facts("His name is Rex", 0).is_empty());
+    }
+    
+    #[test]
+    fn phrase_matches_stay_on_character_boundaries() {
+        // U+023A grows and the Kelvin sign shrinks when lowercased, so total lengths can still agree
+        let text = "\u{023A} my name is \u{023A}\u{212A}";
+        assert!(matches!(&extract_profile_facts(text, 0)[..], [ProfileFact::Name(name)] if name == "\u{023A}\u{212A}"));
+        assert_eq!(extract_name_from_message(text).as_deref(), Some("\u{023A}\u{212A}"));
+        assert_eq!(find_lowercase("I \u{212A}now", "know"), Some((2, 8)));
+        assert_eq!(phrase_object("\u{212A}\u{212A} call me Kay", &PREFERRED_NAME_PHRASES).as_deref(), Some("Kay"));
+    }
+    
+    #[test]
+    fn adding_tags_past_the_limit_is_rejected() {
+        let vocabulary: HashMap<String, u32> = [("project".to_string(), 3)].into_iter().collect();
+        let existing: Vec<String> = (0..15).map(|i| format!("tag{}", i)).collect();
+        let added: Vec<String> = (15..21).map(|i| format!("tag{}", i)).collect();
+        assert!(merge_tags(&vocabulary, &existing, added.clone()).is_err());
+        assert_eq!(merge_tags(&vocabulary, &existing, added[..5].to_vec()).unwrap().len(), MAX_MEMORY_TAGS);
+        
+        // Spellings the vocabulary folds together do not count twice
+        let mut full: Vec<String> = (0..19).map(|i| format!("tag{}", i)).collect();
+        full.push("project".to_string());
+        let merged = merge_tags(&vocabulary, &full, vec!["Projects".to_string(), "tag3".to_string()]).unwrap();
+        assert_eq!(merged, full);
+    }
+    
+    #[test]
+    fn possessive_events_stay_on_character_boundaries() {
+        let (event, _) = extract_possessive_event("\u{212A}\u{212A}'s birthday is June 4", "birthday", wednesday()).unwrap();
+        assert_eq!(event, "\u{212A}\u{212A}'s birthday");
+        assert!(extract_possessive_event("\u{023A}\u{023A} mom's birthday is June 4", "birthday", wednesday()).is_none());
+    }
+    
+    #[test]
+    fn plain_messages_skip_the_sentiment_model() {
+        let (sentiment, confidence) = classify_sentiment("Please summarize the meeting notes");
+        assert!(matches!(sentiment, Sentiment::Neutral) && confidence >= SENTIMENT_MODEL_THRESHOLD);
+        let (sentiment, confidence) = classify_sentiment("The food was good but the service was bad");
+        assert!(matches!(sentiment, Sentiment::Neutral) && confidence < SENTIMENT_MODEL_THRESHOLD);
+    }
+    
+    #[test]
+    fn style_feedback_needs_a_directive() {
+        let detail = |message: &str| {
+            let mut profile = UserProfile::default();
+            learn_communication_style(&mut profile, message);
+            profile.style_evidence.detail_feedback_at > 0
+        };
+        assert!(detail("Too long."));
+        assert!(detail("That was way too long, thanks"));
+        assert!(detail("Could you elaborate?"));
+        assert!(detail("OK. Please be brief from now on"));
+        assert!(!detail("The build took too long"));
+        assert!(!detail("We have an elaborate setup at work"));
+    }
+    
+    #[test]
+    fn detail_feedback_wears_off() {
+        let mut profile = UserProfile::default();
+        learn_communication_style(&mut profile, "Please be brief");
+        let long_message = "word ".repeat(80);
+        for _ in 0..DETAIL_FEEDBACK_MESSAGES {
+            learn_communication_style(&mut profile, &long_message);
+        }
+        assert_eq!(profile.communication_style.detail_preference, DetailLevel::Detailed);
+    }
+    
+    // Wednesday, June 4th 2025, mid-morning UTC
+    fn wednesday() -> u64 {
+        date_to_nanos(2025, 6, 4).unwrap() as u64 + 10 * 60 * 60 * 1_000_000_000
+    }
+    
+    fn due_day(text: &str) -> Option<(i64, u32, u32)> {
+        parse_due_date(text, wednesday()).map(|(due, _)| civil_from_days((due / NANOS_PER_DAY) as i64))
+    }
+    
+    #[test]
+    fn relative_due_dates() {
+        assert_eq!(due_day("call mom today"), Some((2025, 6, 4)));
+        assert_eq!(due_day("by tomorrow"), Some((2025, 6, 5)));
+        assert_eq!(due_day("on Friday"), Some((2025, 6, 6)));
+        assert_eq!(due_day("Wednesday"), Some((2025, 6, 11)));
+        assert_eq!(due_day("in 3 days"), Some((2025, 6, 7)));
+        assert_eq!(due_day("in 2 weeks"), Some((2025, 6, 18)));
+        assert_eq!(due_day("next week"), Some((2025, 6, 9)));
+        assert_eq!(due_day("next month"), Some((2025, 7, 1)));
+        assert_eq!(due_day("sometime soon"), None);
+    }
+    
+    #[test]
+    fn calendar_due_dates() {
+        assert_eq!(due_day("on March 5th"), Some((2026, 3, 5)));
+        assert_eq!(due_day("on March 5th 2025"), Some((2025, 3, 5)));
+        assert_eq!(due_day("by 2025-12-31"), Some((2025, 12, 31)));
+        assert_eq!(due_day("on February 29"), None);
+    }
+    
+    #[test]
+    fn due_date_offsets_index_the_original_text() {
+        let text = "İstanbul trip on Friday";
+        let (_, offset) = parse_due_date(text, wednesday()).unwrap();
+        assert_eq!(&text[..offset], "İstanbul trip ");
+    }
+    
+    #[test]
+    fn tasks_come_from_reminders_or_dated_obligations() {
+        let now = wednesday();
+        let (task, due) = extract_task("Remind me to send the invoice by Friday", now).unwrap();
+        assert_eq!(task, "send the invoice");
+        assert_eq!(due.map(|due| civil_from_days((due / NANOS_PER_DAY) as i64)), Some((2025, 6, 6)));
+        assert!(matches!(extract_task("remind me to water the plants", now), Some((task, None)) if task == "water the plants"));
+        assert!(matches!(extract_task("I need to renew my passport next week", now), Some((task, Some(_))) if task == "renew my passport"));
+        assert!(extract_task("I need to understand closures better", now).is_none());
+        assert!(extract_task("I have to say, this is great", now).is_none());
+    }
+    
+    #[test]
+    fn task_status_transitions() {
+        use TaskStatus::*;
+        assert!(task_transition_allowed(&Active, &Completed));
+        assert!(task_transition_allowed(&Paused, &Active));
+        assert!(task_transition_allowed(&Completed, &Active));
+        assert!(task_transition_allowed(&Cancelled, &Active));
+        assert!(!task_transition_allowed(&Completed, &Paused));
+        assert!(!task_transition_allowed(&Cancelled, &Completed));
+        assert!(!task_transition_allowed(&Active, &Active));
+        assert!(TaskStatus::default() == Active);
+    }
+    
+    fn event(date: (i64, u32, u32), recurrence: EventRecurrence) -> ImportantEvent {
+        ImportantEvent {
+            event: "Anna's birthday".to_string(),
+            date: date_to_nanos(date.0, date.1, date.2).unwrap(),
+            importance: 0.5,
+            category: "personal".to_string(),
+            id: "event_1".to_string(),
+            recurrence,
+            lead_days: None,
+            reminded_until: 0,
+        }
+    }
+    
+    fn next_day(event: &ImportantEvent, today: (i64, u32, u32)) -> Option<(i64, u32, u32)> {
+        next_occurrence(event, civil_day(today.0, today.1, today.2).unwrap()).map(civil_from_days)
+    }
+    
+    #[test]
+    fn next_occurrences() {
+        let today = (2025, 6, 4);
+        assert_eq!(next_day(&event((1962, 3, 5), EventRecurrence::Yearly), today), Some((2026, 3, 5)));
+        assert_eq!(next_day(&event((1990, 6, 4), EventRecurrence::Yearly), today), Some((2025, 6, 4)));
+        assert_eq!(next_day(&event((1960, 2, 29), EventRecurrence::Yearly), today), Some((2026, 2, 28)));
+        assert_eq!(next_day(&event((1960, 2, 29), EventRecurrence::Yearly), (2027, 6, 1)), Some((2028, 2, 29)));
+        assert_eq!(next_day(&event((2025, 1, 31), EventRecurrence::Monthly), today), Some((2025, 6, 30)));
+        assert_eq!(next_day(&event((2025, 1, 31), EventRecurrence::Monthly), (2025, 12, 31)), Some((2025, 12, 31)));
+        assert_eq!(next_day(&event((2025, 5, 1), EventRecurrence::Once), today), None);
+        assert_eq!(next_day(&event((2025, 7, 1), EventRecurrence::Once), today), Some((2025, 7, 1)));
+    }
+    
+    #[test]
+    fn reminders_are_sent_once_per_lead_time() {
+        let mut profile = UserProfile::default();
+        profile.important_dates.push(event((1962, 6, 11), EventRecurrence::Yearly));
+        let at = |day: u32, hour: u64| date_to_nanos(2025, 6, day).unwrap() as u64 + hour * NANOS_PER_HOUR;
+        
+        assert!(take_due_reminders(&mut profile, at(4, 8)).is_empty());
+        let due = take_due_reminders(&mut profile, at(4, 10));
+        assert_eq!(due, vec![("event_1".to_string(), "Anna's birthday".to_string(), civil_day(2025, 6, 11).unwrap())]);
+        assert!(take_due_reminders(&mut profile, at(5, 10)).is_empty());
+        // After a pause only the latest missed reminder goes out
+        assert_eq!(take_due_reminders(&mut profile, at(10, 12)).len(), 1);
+        assert!(take_due_reminders(&mut profile, at(11, 12)).is_empty());
+        
+        profile.reminder_settings.enabled = false;
+        profile.important_dates[0].reminded_until = 0;
+        assert!(take_due_reminders(&mut profile, at(10, 12)).is_empty());
+    }
+    
+    #[test]
+    fn reminders_follow_the_users_time_zone() {
+        let mut profile = UserProfile::default();
+        profile.reminder_settings.utc_offset_minutes = -5 * 60;
+        profile.important_dates.push(event((2025, 6, 11), EventRecurrence::Once));
+        let at = |hour: u64| date_to_nanos(2025, 6, 4).unwrap() as u64 + hour * NANOS_PER_HOUR;
+        assert!(take_due_reminders(&mut profile, at(13)).is_empty());
+        assert_eq!(take_due_reminders(&mut profile, at(14)).len(), 1);
+    }
+    
+    #[test]
+    fn dated_plans_become_important_dates() {
+        let facts = extract_profile_facts("I have a dentist appointment on March 3rd", wednesday());
+        let date = date_to_nanos(2026, 3, 3).unwrap();
+        assert!(matches!(&facts[..], [ProfileFact::ImportantDate { event, date: d }] if event == "dentist appointment" && *d == date));
+        let facts = extract_profile_facts("My birthday is March 5, 1962", wednesday());
+        assert!(matches!(&facts[..], [ProfileFact::ImportantDate { event, date }] if event == "birthday" && *date < 0));
+        assert!(extract_profile_facts("I have a question about Rust", wednesday()).is_empty());
+    }
+    
+    fn person(name: &str, relationship_type: &str) -> PersonalRelationship {
+        PersonalRelationship {
+            name: name.to_string(),
+            relationship_type: relationship_type.to_string(),
+            context: String::new(),
+            importance: 0.5,
+            id: String::new(),
+            entity_key: None,
+            memory_ids: Vec::new(),
+            mention_count: 0,
+            first_mentioned: 0,
+            last_mentioned: 0,
+        }
+    }
+    
+    #[test]
+    fn people_are_matched_by_capitalized_name_or_unique_role() {
+        let people = [person("Will Turner", "friend"), person("May", "sister"), person("Mark", "colleague")];
+        assert!(mentioned_people(&people, "I will call you later, it may rain").is_empty());
+        assert!(mentioned_people(&people, "mark my words").is_empty());
+        assert_eq!(mentioned_people(&people, "Will's new job starts Monday"), vec![0]);
+        assert_eq!(mentioned_people(&people, "Dinner with May and my colleague"), vec![1, 2]);
+    }
+    
+    #[test]
+    fn only_bare_replies_answer_confirmations() {
+        assert_eq!(confirmation_reply("Yes!"), Some(true));
+        assert_eq!(confirmation_reply(" nope. "), Some(false));
+        assert_eq!(confirmation_reply("That’s right"), Some(true));
+        assert_eq!(confirmation_reply("yes, and also tell me about Rust"), None);
+        assert_eq!(confirmation_reply("no idea what you mean"), None);
+    }
+    
+    #[test]
+    fn confirmations_expire() {
+        let asked_at = 1_000 * 1_000_000_000;
+        assert!(confirmation_open(asked_at, asked_at + 60 * 1_000_000_000));
+        assert!(!confirmation_open(asked_at, asked_at + (CONFIRMATION_WINDOW_SECS + 1) * 1_000_000_000));
+    }
+    
+    #[test]
+    fn unanswered_contradictions_keep_the_existing_fact() {
+        let asked_at = 1_000 * 1_000_000_000;
+        let pending = |current: &str, proposed: &str| PendingConfirmation {
+            fact_key: "job".to_string(),
+            current_node: current.to_string(),
+            proposed_node: proposed.to_string(),
+            asked_at,
+        };
+        let questions = vec![pending("old-a", "new-1"), pending("old-b", "new-2"), pending("old-c", "new-2")];
+        
+        let (promoted, dropped) = settle_confirmations(questions.clone(), None, asked_at + 1);
+        assert!(promoted.is_empty());
+        assert_eq!(dropped, vec!["new-1".to_string(), "new-2".to_string()]);
+        
+        let late = asked_at + (CONFIRMATION_WINDOW_SECS + 1) * 1_000_000_000;
+        let (promoted, dropped) = settle_confirmations(questions.clone(), Some(true), late);
+        assert!(promoted.is_empty());
+        assert_eq!(dropped.len(), 2);
+        
+        let (promoted, dropped) = settle_confirmations(questions, Some(true), asked_at + 1);
+        let superseded: Vec<&str> = promoted.iter().map(|pending| pending.current_node.as_str()).collect();
+        assert_eq!(superseded, vec!["old-b", "old-c"]);
+        assert_eq!(dropped, vec!["new-1".to_string()]);
+    }
+    
+    #[test]
+    fn templates_render_sections_by_value() {
+        let values: HashMap<String, String> = [
+            ("name".to_string(), "Ada".to_string()),
+            ("goals".to_string(), "  ".to_string()),
+        ].into_iter().collect();
+        let body = "Hi {{ name }}.{{#goals}} Goals: {{goals}}{{/goals}}{{^goals}} No goals yet.{{/goals}}{{interests}}";
+        assert_eq!(render_template(body, &values), "Hi Ada. No goals yet.");
+        assert_eq!(render_template("Unclosed {{name", &values), "Unclosed {{name");
+    }
+    
+    #[test]
+    fn templates_are_validated() {
+        for kind in [PromptTemplateKind::ContextualResponse, PromptTemplateKind::InquiryFirst, PromptTemplateKind::PartialAnswer, PromptTemplateKind::LearningOpportunity] {
+            assert!(validate_template(builtin_template(&kind)).is_ok());
+        }
+        assert!(validate_template("{{#name}}Hi {{name}}{{/name}}").is_ok());
+        assert!(validate_template("   ").is_err());
+        assert!(validate_template("Hi {{name").is_err());
+        assert!(validate_template("{{password}}").is_err());
+        assert!(validate_template("{{#name}}Hi").is_err());
+        assert!(validate_template("{{#name}}{{#goals}}{{/name}}{{/goals}}").is_err());
+        assert!(validate_template(&"x".repeat(MAX_TEMPLATE_BYTES + 1)).is_err());
+    }
+    
+    fn memory(id: &str, content: &str, node_type: MemoryNodeType, created_at: u64) -> MemoryNode {
+        MemoryNode {
+            id: id.to_string(),
+            content: content.to_string(),
+            node_type,
+            importance_score: 0.5,
+            created_at,
+            last_accessed: created_at,
+            access_count: 1,
+            tags: Vec::new(),
+            related_conversations: Vec::new(),
+            embedding: None,
+            pinned: false,
+            fact_key: None,
+            superseded_by: None,
+            version: 1,
+        }
+    }
+    
+    #[test]
+    fn stemming_folds_inflections() {
+        assert_eq!(stem("likes"), stem("liked"));
+        assert_eq!(stem("liking"), stem("like"));
+        assert_eq!(stem("running"), "run");
+        assert_eq!(stem("stories"), "story");
+        assert_eq!(stem("classes"), "class");
+        assert_eq!(stem("status"), "status");
+        assert_eq!(stem("café"), "café");
+        assert_eq!(tokenize("I'm running to the Zürich office!"), vec!["run", "zürich", "offic"]);
+    }
+    
+    #[test]
+    fn bm25_ranks_rarer_and_denser_matches_first() {
+        let mut index = MemoryIndex::default();
+        for (id, content) in [
+            ("hiking", "I love hiking in the mountains"),
+            ("mountains", "The mountains near home, mountains everywhere"),
+            ("coffee", "I drink coffee every morning in the kitchen"),
+            ("kitchen", "The kitchen needs new lights"),
+        ] {
+            index_node_terms(&mut index, &memory(id, content, MemoryNodeType::Fact, 0));
+        }
+        
+        let ranked: Vec<String> = bm25_search(&index, "hikes in the mountains").into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ranked, vec!["hiking", "mountains"]);
+        assert!(bm25_search(&index, "the and of").is_empty());
+        
+        unindex_node(&mut index, "hiking");
+        let ranked: Vec<String> = bm25_search(&index, "hiking").into_iter().map(|(id, _)| id).collect();
+        assert!(ranked.is_empty());
+        assert_eq!(index.doc_lengths.len(), 3);
+    }
+    
+    #[test]
+    fn documents_split_into_headed_passages() {
+        let text = "Intro line\r\n\r\n# Setup\nInstall it.\n\nThen run it.\n\n## Usage\n\n";
+        assert_eq!(split_into_passages(text), vec!["Intro line", "Setup: Install it.\n\nThen run it."]);
+        
+        let long = "word ".repeat(400);
+        let passages = split_into_passages(&format!("# Notes\n{}", long));
+        assert_eq!(passages.len(), 3);
+        assert!(passages.iter().all(|p| p.starts_with("Notes: ") && p.chars().count() <= PASSAGE_TARGET_CHARS + "Notes: ".len()));
+        
+        assert!(document_passages(b" \n\n ").is_err());
+        assert!(document_passages(&[0xff, 0xfe]).is_err());
+        assert!(document_passages("x\n\n".repeat(MAX_DOCUMENT_PASSAGES * PASSAGE_TARGET_CHARS / 2).as_bytes()).is_err());
+    }
+    
+    #[test]
+    fn generated_tags_prefer_names_then_frequent_keywords() {
+        let tags = generate_tags("Yesterday I met Grace Hopper at the Rust conference. Conference talks about compilers were great.");
+        assert_eq!(tags[..2], ["grace hopper".to_string(), "rust".to_string()]);
+        assert!(tags.contains(&"conference".to_string()));
+        assert!(!tags.iter().any(|tag| tag == "grace" || tag == "hopper" || tag == "yesterday i"));
+        assert!(tags.len() <= MAX_AUTO_TAGS);
+    }
+    
+    #[test]
+    fn tags_canonicalize_onto_the_vocabulary() {
+        let vocabulary: HashMap<String, u32> = [("project".to_string(), 2), ("machine learning".to_string(), 1)].into_iter().collect();
+        let tags = ["Projects", "machine-learning!", "Machine  Learnings", "", "Rust"].map(String::from);
+        assert_eq!(canonicalize_tags(&vocabulary, &tags), vec!["project", "machine-learning", "machine learning", "rust"]);
+        assert_eq!(normalize_tag(" C++ / .NET. "), Some("c++ .net".to_string()));
+        assert_eq!(normalize_tag(&"x".repeat(MAX_TAG_CHARS + 1)), None);
+    }
+    
+    #[test]
+    fn recent_memories_cluster_by_shared_tags() {
+        let now = 100 * NANOS_PER_DAY;
+        let tagged = |id: &str, node_type: MemoryNodeType, age_days: u64, tags: &[&str], importance: f32| {
+            let mut node = memory(id, id, node_type, now - age_days * NANOS_PER_DAY);
+            node.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            node.importance_score = importance;
+            node
+        };
+        let mut kg = PersonalKnowledgeGraph::default();
+        for node in [
+            tagged("a", MemoryNodeType::Experience, 1, &["garden", "tomatoes"], 0.4),
+            tagged("b", MemoryNodeType::Fact, 2, &["garden", "tomatoes"], 0.9),
+            tagged("c", MemoryNodeType::Experience, 3, &["garden", "tomatoes", "rain"], 0.6),
+            tagged("old", MemoryNodeType::Experience, 60, &["garden", "tomatoes"], 0.5),
+            tagged("preference", MemoryNodeType::Preference, 1, &["garden", "tomatoes"], 0.5),
+            tagged("summarized", MemoryNodeType::Fact, 1, &["garden", "tomatoes"], 0.5),
+            tagged("x", MemoryNodeType::Fact, 1, &["chess"], 0.5),
+            tagged("y", MemoryNodeType::Fact, 1, &["chess"], 0.5),
+        ] {
+            kg.memory_nodes.insert(node.id.clone(), node);
+        }
+        let mut index = MemoryIndex::default();
+        index.adjacency.insert("summarized".to_string(), vec![AdjacentEdge {
+            neighbor: "summary".to_string(),
+            relationship_type: RelationshipType::PartOf,
+            strength: 1.0,
+            outgoing: true,
+            created_at: now,
+        }]);
+        
+        // Members come most important first; the chess pair is too small to summarize
+        assert_eq!(cluster_recent_memories(&kg, &index, now), vec![vec!["b", "c", "a"]]);
+    }
+    
+    #[test]
+    fn entities_are_extracted_and_classified() {
+        let entities = extract_entities("I had lunch with Maria Lopez in Lisbon. She works at Acme Corp on Project Atlas, written in Rust, due July 5", wednesday());
+        let found: Vec<(&str, EntityType)> = entities.iter().map(|e| (e.name.as_str(), e.entity_type.clone())).collect();
+        assert_eq!(found, vec![
+            ("2025-07-05", EntityType::Date),
+            ("Rust", EntityType::Technology),
+            ("Maria Lopez", EntityType::Person),
+            ("Lisbon", EntityType::Location),
+            ("Acme Corp", EntityType::Company),
+            ("Project Atlas", EntityType::Project),
+        ]);
+        assert!(extract_entities("Tomorrow is fine. Thanks!", wednesday()).is_empty());
+    }
+    
+    #[test]
+    fn entities_resolve_to_known_names() {
+        assert_eq!(entity_key("Acme Corp."), "acme");
+        assert_eq!(entity_key("Acme"), "acme");
+        assert_eq!(entity_key("Corp"), "corp");
+        
+        let record = |name: &str, entity_type: EntityType, aliases: &[&str]| EntityRecord {
+            name: name.to_string(),
+            entity_type,
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+            node_id: String::new(),
+            mention_count: 1,
+            first_seen: 0,
+            last_seen: 0,
+            last_context: String::new(),
+            thread_ids: Vec::new(),
+            message_timestamps: Vec::new(),
+        };
+        let mut kg = PersonalKnowledgeGraph::default();
+        kg.entities.insert("maria lopez".to_string(), record("Maria Lopez", EntityType::Person, &[]));
+        kg.entities.insert("international business machines".to_string(), record("International Business Machines", EntityType::Company, &[]));
+        kg.entities.insert("acme".to_string(), record("Acme Corp", EntityType::Company, &["Roadrunner Supplies"]));
+        
+        let resolve = |name: &str, entity_type: EntityType| resolve_entity(&kg, &Entity { name: name.to_string(), entity_type, context: String::new() });
+        assert_eq!(resolve("Acme Inc", EntityType::Company).as_deref(), Some("acme"));
+        assert_eq!(resolve("Roadrunner Supplies", EntityType::Other).as_deref(), Some("acme"));
+        assert_eq!(resolve("Maria", EntityType::Person).as_deref(), Some("maria lopez"));
+        assert_eq!(resolve("Maria", EntityType::Location), None);
+        assert_eq!(resolve("IBM", EntityType::Other).as_deref(), Some("international business machines"));
+        assert_eq!(resolve("Ibm", EntityType::Other), None);
+    }
+    
+    fn chat(timestamp: u64, thread_id: &str, sentiment: Sentiment) -> EnhancedChatMessage {
+        EnhancedChatMessage {
+            role: "user".to_string(),
+            content: String::new(),
+            timestamp,
+            provider: "test".to_string(),
+            context_thread_id: Some(thread_id.to_string()),
+            extracted_facts: Vec::new(),
+            referenced_memories: Vec::new(),
+            learned_preferences: Vec::new(),
+            user_sentiment: Some(sentiment),
+            response_strategy: None,
+            cycles_cost: None,
+            content_stored_on_chain: None,
+            ii_verified: None,
+            attachment_ids: Vec::new(),
+        }
+    }
+    
+    #[test]
+    fn trend_buckets_start_at_midnight_and_monday() {
+        let monday = date_to_nanos(2025, 6, 2).unwrap() as u64;
+        assert_eq!(bucket_start(wednesday(), &TrendBucket::Day), monday + 2 * NANOS_PER_DAY);
+        assert_eq!(bucket_start(wednesday(), &TrendBucket::Week), monday);
+        assert_eq!(bucket_start(monday, &TrendBucket::Week), monday);
+        assert_eq!(bucket_start(monday - 1, &TrendBucket::Week), monday - 7 * NANOS_PER_DAY);
+    }
+    
+    #[test]
+    fn sentiment_trends_flag_shifts_between_full_buckets() {
+        let day = bucket_start(wednesday(), &TrendBucket::Day);
+        let mut messages = Vec::new();
+        for hour in 0..3 {
+            messages.push(chat(day - NANOS_PER_DAY + hour * NANOS_PER_HOUR, "work", Sentiment::Positive));
+            messages.push(chat(day + hour * NANOS_PER_HOUR, "work", Sentiment::Frustrated));
+        }
+        messages.push(chat(day + 5 * NANOS_PER_HOUR, "home", Sentiment::Curious));
+        messages.push(chat(day + 6 * NANOS_PER_HOUR, "home", Sentiment::Negative));
+        // One upbeat message is not enough to call a shift
+        messages.push(chat(day + NANOS_PER_DAY, "home", Sentiment::Excited));
+        
+        let range = TimeRange { start: 0, end: u64::MAX };
+        let trends = sentiment_trends(&messages, &HashMap::new(), &range, &TrendBucket::Day);
+        assert_eq!(trends.buckets.len(), 3);
+        assert!(matches!(trends.buckets[1].dominant, Sentiment::Frustrated));
+        assert_eq!(trends.shifts.len(), 1);
+        assert_eq!(trends.shifts[0].bucket_start, day);
+        assert!(trends.shifts[0].change < -1.5);
+        let threads: Vec<&str> = trends.threads.iter().map(|t| t.thread_id.as_str()).collect();
+        assert_eq!(threads, vec!["home", "work"]);
+        
+        let range = TimeRange { start: day, end: day + NANOS_PER_DAY - 1 };
+        assert_eq!(sentiment_trends(&messages, &HashMap::new(), &range, &TrendBucket::Day).buckets.len(), 1);
+    }
+    
+    #[test]
+    fn questions_are_classified_by_type() {
+        assert_eq!(question_type("What's the difference between Rust and Go?"), Some("comparison"));
+        assert_eq!(question_type("How do I bake bread"), Some("how_to"));
+        assert_eq!(question_type("Why is the sky blue?"), Some("explanation"));
+        assert_eq!(question_type("What is a monad?"), Some("definition"));
+        assert_eq!(question_type("Should I learn Python first?"), Some("recommendation"));
+        assert_eq!(question_type("Can you summarize this?"), Some("request"));
+        assert_eq!(question_type("When does the store open?"), Some("factual"));
+        assert_eq!(question_type("Is it raining"), Some("yes_no"));
+        assert_eq!(question_type("I went hiking today."), None);
+    }
+    
+    #[test]
+    fn interaction_patterns_split_sessions_on_inactivity() {
+        let mut kg = PersonalKnowledgeGraph::default();
+        let start = wednesday();
+        let minute = 60_000_000_000;
+        record_interaction_patterns(&mut kg, "How do I bake bread?", 2, start);
+        record_interaction_patterns(&mut kg, "Thanks, that worked.", 0, start + 10 * minute);
+        record_interaction_patterns(&mut kg, "What is sourdough?", 1, start + 10 * minute + SESSION_GAP_NANOS + 1);
+        
+        let patterns = &kg.user_profile.conversation_patterns;
+        assert_eq!(patterns.session_count, 2);
+        assert_eq!(patterns.completed_session_nanos, 10 * minute);
+        assert!((patterns.avg_session_length - 5.0).abs() < 1e-3);
+        assert_eq!(patterns.time_patterns[10], 3);
+        assert_eq!(patterns.question_types.get("how_to"), Some(&1));
+        assert_eq!(patterns.question_types.get("definition"), Some(&1));
+        
+        let history = &kg.learning_patterns;
+        assert_eq!(history.interaction_count, 3);
+        assert!((history.question_asking_frequency - 2.0 / 3.0).abs() < 1e-3);
+    }
+    
+    #[test]
+    fn domain_expertise_follows_the_evidence() {
+        let mut profile = UserProfile::default();
+        update_domain_expertise(&mut profile, "Thanks, see you tomorrow");
+        assert!(profile.knowledge_domains.is_empty());
+        
+        update_domain_expertise(&mut profile, "I'm new to Python, what is a function?");
+        assert!(profile.knowledge_domains["programming"] < 0.3);
+        assert!((profile.domain_confidence["programming"] - EXPERTISE_CONFIDENCE_GAIN).abs() < 1e-6);
+        assert!(matches!(profile.communication_style.technical_level, TechnicalLevel::Beginner));
+        
+        update_domain_expertise(&mut profile, "Profiling Rust compiler internals for latency and throughput");
+        assert!(profile.knowledge_domains["programming"] > 0.55);
+        assert!(matches!(profile.communication_style.technical_level, TechnicalLevel::Advanced | TechnicalLevel::Expert));
+        
+        // A locked level stays put while the per-domain evidence keeps moving
+        profile.communication_style.locked_fields.push("technical_level".to_string());
+        update_domain_expertise(&mut profile, "I don't understand budget basics, what is a loan?");
+        assert!(profile.knowledge_domains["finance"] < 0.3);
+        assert!(matches!(profile.communication_style.technical_level, TechnicalLevel::Advanced | TechnicalLevel::Expert));
+    }
+    
+    fn empty_update() -> UserProfileUpdate {
+        UserProfileUpdate {
+            name: None,
+            preferred_name: None,
+            communication_style: None,
+            personality_traits: None,
+            interests: None,
+            expertise_areas: None,
+            work_context: None,
+            relationships: None,
+            important_dates: None,
+            goals: None,
+            response_preferences: None,
+        }
+    }
+    
+    #[test]
+    fn text_list_patches_add_remove_and_replace() {
+        let mut items = vec!["Rust".to_string()];
+        assert!(!apply_text_list_patch(&mut items, TextListPatch::Add(vec!["rust".into(), " Chess ".into(), "  ".into()])));
+        assert_eq!(items, vec!["Rust", "Chess"]);
+        assert!(!apply_text_list_patch(&mut items, TextListPatch::Remove(vec!["RUST ".into()])));
+        assert_eq!(items, vec!["Chess"]);
+        assert!(apply_text_list_patch(&mut items, TextListPatch::Replace(vec!["Go".into(), "go".into()])));
+        assert_eq!(items, vec!["Go"]);
+    }
+    
+    #[test]
+    fn profile_updates_apply_in_full_or_not_at_all() {
+        let now = wednesday();
+        let mut profile = UserProfile::default();
+        let mut update = empty_update();
+        update.name = Some(" Ada ".to_string());
+        update.interests = Some(TextListPatch::Replace(vec!["Chess".to_string()]));
+        update.goals = Some(GoalListPatch::Add(vec![GoalInput {
+            goal: "Run a marathon".to_string(),
+            category: None,
+            target_date: None,
+            importance: None,
+            milestones: Vec::new(),
+        }]));
+        apply_profile_update(&mut profile, update, now).unwrap();
+        assert_eq!(profile.name.as_deref(), Some("Ada"));
+        assert_eq!(profile.interests, vec!["Chess"]);
+        assert_eq!(profile.goals[0].category, "fitness");
+        // Added goals leave extraction running; the replaced list and the name are pinned as explicit
+        assert!(["name", "interests"].iter().all(|field| profile.field_provenance[*field].updated_at == now));
+        assert!(!profile.field_provenance.contains_key("goals"));
+        
+        // update_user_profile patches a copy, so a failure part way through leaves the profile as it was
+        let mut failing = empty_update();
+        failing.name = Some("Grace".to_string());
+        failing.goals = Some(GoalListPatch::Remove(vec!["goal_missing".to_string()]));
+        let mut patched = profile.clone();
+        assert!(apply_profile_update(&mut patched, failing, now).is_err());
+        assert_eq!(patched.name.as_deref(), Some("Grace"));
+        assert_eq!(profile.name.as_deref(), Some("Ada"));
+        assert_eq!(profile.goals.len(), 1);
+    }
+}
@@ -1,5 +1,6 @@
 import React from 'react';
 import { useState, useEffect } from 'react';
+import { Principal } from '@dfinity/principal';
 import { backend } from 'declarations/backend';
 import '../styles/sidebar.css';
 
@@ -68,15 +69,37 @@ const Sidebar = ({
     setIsUpdatingAutopilot(true);
     try {
       const newAutopilotState = !autopilotEnabled;
+      const principal = Principal.fromText(userPrincipal);
       
-      // Update user profile with autopilot preference
+      // response_preferences replaces the whole record, so start from the stored preferences
+      const [graph] = await backend.get_user_knowledge_graph(principal);
+      const currentPreferences = graph?.user_profile.response_preferences ?? {
+        prefers_examples: false,
+        prefers_step_by_step: false,
+        prefers_quick_answers: false,
+        prefers_detailed_explanations: false,
+        autopilot_enabled: false
+      };
+      
+      // Every field of the patch is optional; [] leaves it unchanged
       const profileUpdate = {
-        response_preferences: {
+        name: [],
+        preferred_name: [],
+        communication_style: [],
+        personality_traits: [],
+        interests: [],
+        expertise_areas: [],
+        work_context: [],
+        relationships: [],
+        important_dates: [],
+        goals: [],
+        response_preferences: [{
+          ...currentPreferences,
           autopilot_enabled: newAutopilotState
-        }
+        }]
       };
       
-      const result = await backend.update_user_profile(userPrincipal, profileUpdate);
+      const result = await backend.update_user_profile(principal, profileUpdate);
       
       if ('Ok' in result) {
         setAutopilotEnabled(newAutopilotState);
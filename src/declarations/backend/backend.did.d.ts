@@ -2,6 +2,15 @@ import type { Principal } from '@dfinity/principal';
 import type { ActorMethod } from '@dfinity/agent';
 import type { IDL } from '@dfinity/candid';
 
+export interface AddedMemory { 'merged' : boolean, 'node' : MemoryNode }
+export interface AttachmentInfo {
+  'id' : string,
+  'size_bytes' : bigint,
+  'mime_type' : string,
+  'file_name' : string,
+  'facts_extracted' : boolean,
+  'uploaded_at' : bigint,
+}
 export interface CanisterMetrics {
   'storage_used_bytes' : bigint,
   'total_queries' : bigint,
@@ -15,9 +24,17 @@ export interface CommunicationStyle {
   'formality_level' : FormalityLevel,
   'technical_level' : TechnicalLevel,
   'emoji_usage' : boolean,
+  'locked_fields' : Array<string>,
   'detail_preference' : DetailLevel,
   'humor_preference' : boolean,
 }
+export interface CommunicationStylePatch {
+  'formality_level' : [] | [FormalityLevel],
+  'technical_level' : [] | [TechnicalLevel],
+  'emoji_usage' : [] | [boolean],
+  'detail_preference' : [] | [DetailLevel],
+  'humor_preference' : [] | [boolean],
+}
 export interface ConversationContext {
   'topic' : string,
   'last_message_timestamp' : bigint,
@@ -28,15 +45,25 @@ export interface ConversationContext {
   'thread_id' : string,
 }
 export interface ConversationPatterns {
+  'session_count' : number,
   'question_types' : Array<[string, number]>,
   'avg_session_length' : number,
+  'completed_session_nanos' : bigint,
   'common_topics' : Array<string>,
+  'session_started_at' : bigint,
   'time_patterns' : Uint32Array | number[],
+  'last_active_at' : bigint,
 }
 export type DetailLevel = { 'Detailed' : null } |
   { 'Comprehensive' : null } |
   { 'Brief' : null } |
   { 'Moderate' : null };
+export interface DocumentInfo {
+  'id' : string,
+  'title' : string,
+  'passage_count' : number,
+  'created_at' : bigint,
+}
 export interface EnhancedChatMessage {
   'ii_verified' : [] | [boolean],
   'content' : string,
@@ -50,6 +77,7 @@ export interface EnhancedChatMessage {
   'timestamp' : bigint,
   'cycles_cost' : [] | [bigint],
   'response_strategy' : [] | [ResponseStrategy],
+  'attachment_ids' : Array<string>,
   'content_stored_on_chain' : [] | [boolean],
 }
 export interface Entity {
@@ -57,6 +85,23 @@ export interface Entity {
   'name' : string,
   'entity_type' : EntityType,
 }
+export interface EntityDetail {
+  'entity' : EntityRecord,
+  'memories' : Array<MemoryNode>,
+  'conversations' : Array<EnhancedChatMessage>,
+}
+export interface EntityRecord {
+  'node_id' : string,
+  'name' : string,
+  'thread_ids' : Array<string>,
+  'mention_count' : number,
+  'aliases' : Array<string>,
+  'first_seen' : bigint,
+  'last_seen' : bigint,
+  'entity_type' : EntityType,
+  'last_context' : string,
+  'message_timestamps' : BigUint64Array | bigint[],
+}
 export type EntityType = { 'Date' : null } |
   { 'Company' : null } |
   { 'Person' : null } |
@@ -64,6 +109,9 @@ export type EntityType = { 'Date' : null } |
   { 'Location' : null } |
   { 'Project' : null } |
   { 'Other' : null };
+export type EventRecurrence = { 'Once' : null } |
+  { 'Monthly' : null } |
+  { 'Yearly' : null };
 export interface ExtractedFact {
   'fact_type' : FactType,
   'fact' : string,
@@ -76,14 +124,73 @@ export type FactType = { 'Goal' : null } |
   { 'Preference' : null } |
   { 'PersonalInfo' : null } |
   { 'Relationship' : null };
+export interface FieldProvenance {
+  'updated_at' : bigint,
+  'source' : ProfileSource,
+  'memory_id' : [] | [string],
+  'confidence' : number,
+}
 export type FormalityLevel = { 'VeryFormal' : null } |
   { 'Formal' : null } |
   { 'Verycasual' : null } |
   { 'Casual' : null };
+export interface GoalInput {
+  'goal' : string,
+  'importance' : [] | [number],
+  'category' : [] | [string],
+  'target_date' : [] | [bigint],
+  'milestones' : Array<string>,
+}
+export type GoalListPatch = { 'Add' : Array<GoalInput> } |
+  { 'Remove' : Array<string> } |
+  { 'Replace' : Array<GoalInput> };
+export interface GoalUpdate {
+  'status' : [] | [TaskStatus],
+  'goal' : [] | [string],
+  'importance' : [] | [number],
+  'progress' : [] | [number],
+  'category' : [] | [string],
+  'target_date' : [] | [bigint],
+}
+export interface GoalsOverview {
+  'active' : Array<PersonalGoal>,
+  'at_risk' : Array<PersonalGoal>,
+  'completed' : Array<PersonalGoal>,
+  'inactive' : Array<PersonalGoal>,
+  'overdue' : Array<PersonalGoal>,
+}
+export interface GraphNeighbor {
+  'edge' : KnowledgeEdge,
+  'node' : MemoryNode,
+  'depth' : number,
+}
+export interface ImportantDateInput {
+  'lead_days' : [] | [Uint32Array | number[]],
+  'date' : bigint,
+  'importance' : [] | [number],
+  'recurrence' : [] | [EventRecurrence],
+  'event' : string,
+  'category' : [] | [string],
+}
+export type ImportantDateListPatch = { 'Add' : Array<ImportantDateInput> } |
+  { 'Remove' : Array<string> } |
+  { 'Replace' : Array<ImportantDateInput> };
+export interface ImportantDateUpdate {
+  'lead_days' : [] | [Uint32Array | number[]],
+  'date' : [] | [bigint],
+  'importance' : [] | [number],
+  'recurrence' : [] | [EventRecurrence],
+  'event' : [] | [string],
+  'category' : [] | [string],
+}
 export interface ImportantEvent {
+  'id' : string,
+  'lead_days' : [] | [Uint32Array | number[]],
   'date' : bigint,
   'importance' : number,
+  'recurrence' : EventRecurrence,
   'event' : string,
+  'reminded_until' : bigint,
   'category' : string,
 }
 export interface KnowledgeEdge {
@@ -106,52 +213,183 @@ export interface LearningHistory {
   'question_asking_frequency' : number,
   'learning_speed' : number,
 }
+export interface LearningInsights {
+  'session_count' : number,
+  'interaction_count' : number,
+  'avg_session_minutes' : number,
+  'question_types' : Array<TopicCount>,
+  'hourly_activity' : Uint32Array | number[],
+  'peak_hours' : Uint32Array | number[],
+  'top_topics' : Array<TopicCount>,
+  'question_asking_frequency' : number,
+  'learning_speed' : number,
+}
+export interface MemoryInput {
+  'node_type' : MemoryNodeType,
+  'content' : string,
+  'tags' : Array<string>,
+  'pinned' : [] | [boolean],
+  'importance_score' : [] | [number],
+}
 export interface MemoryNode {
   'id' : string,
+  'superseded_by' : [] | [string],
   'node_type' : MemoryNodeType,
   'content' : string,
+  'fact_key' : [] | [string],
   'tags' : Array<string>,
   'created_at' : bigint,
   'last_accessed' : bigint,
+  'version' : number,
+  'pinned' : boolean,
   'related_conversations' : Array<string>,
   'importance_score' : number,
+  'embedding' : [] | [Float32Array | number[]],
   'access_count' : number,
 }
 export type MemoryNodeType = { 'Fact' : null } |
   { 'Goal' : null } |
+  { 'Entity' : null } |
+  { 'Summary' : null } |
   { 'Knowledge' : null } |
   { 'Experience' : null } |
   { 'Preference' : null } |
+  { 'Document' : null } |
   { 'Context' : null } |
   { 'Relationship' : null };
+export interface MemoryUpdate {
+  'node_type' : [] | [MemoryNodeType],
+  'content' : [] | [string],
+  'tags' : [] | [Array<string>],
+  'importance_score' : [] | [number],
+}
+export interface Milestone {
+  'completed' : boolean,
+  'description' : string,
+  'completed_at' : [] | [bigint],
+}
+export interface NodeCentrality {
+  'weighted_degree' : number,
+  'node' : MemoryNode,
+  'degree' : number,
+  'pagerank' : number,
+}
+export interface Notification {
+  'id' : string,
+  'read' : boolean,
+  'occurs_on' : bigint,
+  'message' : EnhancedChatMessage,
+  'event_id' : string,
+}
+export interface PendingConfirmation {
+  'fact_key' : string,
+  'proposed_node' : string,
+  'asked_at' : bigint,
+  'current_node' : string,
+}
+export interface PendingGoalUpdate {
+  'goal_id' : string,
+  'progress' : number,
+  'asked_at' : bigint,
+  'evidence' : string,
+  'milestone' : [] | [number],
+}
+export interface PersonDetail {
+  'entity' : [] | [EntityRecord],
+  'person' : PersonalRelationship,
+  'memories' : Array<MemoryNode>,
+}
+export interface PersonInput {
+  'context' : [] | [string],
+  'name' : string,
+  'importance' : [] | [number],
+  'relationship_type' : string,
+}
+export type PersonListPatch = { 'Add' : Array<PersonInput> } |
+  { 'Remove' : Array<string> } |
+  { 'Replace' : Array<PersonInput> };
+export interface PersonUpdate {
+  'context' : [] | [string],
+  'name' : [] | [string],
+  'importance' : [] | [number],
+  'relationship_type' : [] | [string],
+}
 export interface PersonalGoal {
+  'id' : string,
+  'status' : TaskStatus,
+  'updated_at' : bigint,
   'goal' : string,
   'importance' : number,
+  'created_at' : bigint,
   'progress' : number,
   'category' : string,
   'target_date' : [] | [bigint],
+  'milestones' : Array<Milestone>,
 }
 export interface PersonalKnowledgeGraph {
+  'pending_goal_update' : [] | [PendingGoalUpdate],
+  'last_decay_at' : bigint,
   'learning_patterns' : LearningHistory,
+  'notifications' : Array<Notification>,
   'last_updated' : bigint,
+  'last_sentiment_check_in' : bigint,
+  'entities' : Array<[string, EntityRecord]>,
+  'next_node_seq' : bigint,
+  'tag_vocabulary' : Array<[string, number]>,
   'relationships' : Array<KnowledgeEdge>,
   'context_threads' : Array<[string, ConversationContext]>,
   'memory_nodes' : Array<[string, MemoryNode]>,
   'user_profile' : UserProfile,
+  'pending_confirmations' : Array<PendingConfirmation>,
 }
 export interface PersonalRelationship {
+  'id' : string,
   'context' : string,
+  'last_mentioned' : bigint,
   'name' : string,
   'importance' : number,
   'relationship_type' : string,
+  'mention_count' : number,
+  'entity_key' : [] | [string],
+  'first_mentioned' : bigint,
+  'memory_ids' : Array<string>,
 }
+export type ProfileSource = { 'Explicit' : null } |
+  { 'Extracted' : null };
+export interface PromptPreviewRequest {
+  'question' : string,
+  'body' : [] | [string],
+  'kind' : PromptTemplateKind,
+  'user' : [] | [Principal],
+  'persona' : [] | [string],
+}
+export interface PromptTemplate {
+  'body' : string,
+  'kind' : PromptTemplateKind,
+  'note' : [] | [string],
+  'created_at' : bigint,
+  'created_by' : Principal,
+  'version' : number,
+  'persona' : string,
+}
+export type PromptTemplateKind = { 'ContextualResponse' : null } |
+  { 'InquiryFirst' : null } |
+  { 'PartialAnswer' : null } |
+  { 'LearningOpportunity' : null };
 export type RelationshipType = { 'PartOf' : null } |
   { 'Related' : null } |
   { 'UsedFor' : null } |
+  { 'Supersedes' : null } |
   { 'OppositeOf' : null } |
+  { 'Mentions' : null } |
   { 'ExampleOf' : null } |
   { 'LeadsTo' : null } |
   { 'CausedBy' : null };
+export interface ReminderSettings {
+  'lead_days' : Uint32Array | number[],
+  'utc_offset_minutes' : number,
+  'enabled' : boolean,
+}
 export type ResponseLength = { 'Short' : null } |
   { 'Long' : null } |
   { 'Medium' : null } |
@@ -171,16 +409,99 @@ export type ResponseStrategy = {
   } |
   { 'ConfidentAnswer' : { 'sources' : Array<string>, 'confidence' : number } } |
   { 'LearningOpportunity' : { 'suggestion' : string } };
-export type Result = { 'Ok' : UserDashboard } |
+export type Result = { 'Ok' : string } |
+  { 'Err' : string };
+export type Result_1 = { 'Ok' : KnowledgeEdge } |
+  { 'Err' : string };
+export type Result_10 = { 'Ok' : DocumentInfo } |
+  { 'Err' : string };
+export type Result_11 = { 'Ok' : EntityDetail } |
+  { 'Err' : string };
+export type Result_12 = { 'Ok' : Array<MemoryNode> } |
+  { 'Err' : string };
+export type Result_13 = { 'Ok' : GoalsOverview } |
   { 'Err' : string };
-export type Result_1 = { 'Ok' : string } |
+export type Result_14 = { 'Ok' : LearningInsights } |
   { 'Err' : string };
+export type Result_15 = { 'Ok' : Array<GraphNeighbor> } |
+  { 'Err' : string };
+export type Result_16 = { 'Ok' : PersonDetail } |
+  { 'Err' : string };
+export type Result_17 = { 'Ok' : SentimentTrends } |
+  { 'Err' : string };
+export type Result_18 = { 'Ok' : Subgraph } |
+  { 'Err' : string };
+export type Result_19 = { 'Ok' : UserDashboard } |
+  { 'Err' : string };
+export type Result_2 = { 'Ok' : PersonalGoal } |
+  { 'Err' : string };
+export type Result_20 = { 'Ok' : CommunicationStyle } |
+  { 'Err' : string };
+export type Result_21 = { 'Ok' : ReminderSettings } |
+  { 'Err' : string };
+export type Result_22 = { 'Ok' : UserProfile } |
+  { 'Err' : string };
+export type Result_23 = { 'Ok' : bigint } |
+  { 'Err' : string };
+export type Result_3 = { 'Ok' : ImportantEvent } |
+  { 'Err' : string };
+export type Result_4 = { 'Ok' : AddedMemory } |
+  { 'Err' : string };
+export type Result_5 = { 'Ok' : MemoryNode } |
+  { 'Err' : string };
+export type Result_6 = { 'Ok' : PersonalRelationship } |
+  { 'Err' : string };
+export type Result_7 = { 'Ok' : number } |
+  { 'Err' : string };
+export type Result_8 = { 'Ok' : Task } |
+  { 'Err' : string };
+export type Result_9 = { 'Ok' : AttachmentInfo } |
+  { 'Err' : string };
+export interface ScoredMemory { 'node' : MemoryNode, 'score' : number }
 export type Sentiment = { 'Negative' : null } |
   { 'Excited' : null } |
   { 'Curious' : null } |
   { 'Frustrated' : null } |
   { 'Positive' : null } |
   { 'Neutral' : null };
+export interface SentimentBucket {
+  'average_score' : number,
+  'start' : bigint,
+  'dominant' : Sentiment,
+  'counts' : SentimentCounts,
+}
+export interface SentimentCounts {
+  'negative' : number,
+  'frustrated' : number,
+  'excited' : number,
+  'curious' : number,
+  'positive' : number,
+  'neutral' : number,
+}
+export interface SentimentShift {
+  'previous_score' : number,
+  'suggestion' : string,
+  'score' : number,
+  'bucket_start' : bigint,
+  'change' : number,
+}
+export interface SentimentTrends {
+  'shifts' : Array<SentimentShift>,
+  'threads' : Array<ThreadSentiment>,
+  'buckets' : Array<SentimentBucket>,
+}
+export interface StyleEvidence {
+  'formality' : number,
+  'humor' : number,
+  'messages' : number,
+  'words_per_message' : number,
+  'emoji' : number,
+  'detail_feedback_at' : number,
+}
+export interface Subgraph {
+  'edges' : Array<KnowledgeEdge>,
+  'nodes' : Array<MemoryNode>,
+}
 export type SubscriptionTier = {
     'Premium' : { 'cycles_included' : bigint, 'priority_access' : boolean }
   } |
@@ -192,20 +513,54 @@ export type SubscriptionTier = {
     }
   } |
   { 'Basic' : { 'cycles_included' : bigint } };
+export interface TagCount { 'tag' : string, 'count' : number }
 export interface Task {
+  'id' : string,
   'status' : TaskStatus,
+  'updated_at' : bigint,
   'description' : string,
   'created_at' : bigint,
   'due_date' : [] | [bigint],
+  'completed_at' : [] | [bigint],
 }
 export type TaskStatus = { 'Paused' : null } |
   { 'Active' : null } |
   { 'Cancelled' : null } |
   { 'Completed' : null };
+export interface TaskUpdate {
+  'description' : [] | [string],
+  'due_date' : [] | [bigint],
+}
 export type TechnicalLevel = { 'Beginner' : null } |
   { 'Advanced' : null } |
   { 'Intermediate' : null } |
   { 'Expert' : null };
+export type TextListPatch = { 'Add' : Array<string> } |
+  { 'Remove' : Array<string> } |
+  { 'Replace' : Array<string> };
+export interface ThreadSentiment {
+  'average_score' : number,
+  'topic' : string,
+  'last_message_at' : bigint,
+  'dominant' : Sentiment,
+  'counts' : SentimentCounts,
+  'thread_id' : string,
+}
+export interface ThreadTask {
+  'topic' : string,
+  'task' : Task,
+  'overdue' : boolean,
+  'thread_id' : string,
+}
+export interface TimeRange { 'end' : bigint, 'start' : bigint }
+export interface TopicCount { 'topic' : string, 'count' : number }
+export type TrendBucket = { 'Day' : null } |
+  { 'Week' : null };
+export interface UpcomingEvent {
+  'occurs_on' : bigint,
+  'days_until' : number,
+  'event' : ImportantEvent,
+}
 export interface UserDashboard {
   'cycles_balance' : bigint,
   'days_since_first_interaction' : bigint,
@@ -219,11 +574,16 @@ export interface UserDashboard {
   'conversation_count' : bigint,
 }
 export interface UserProfile {
+  'reminder_settings' : ReminderSettings,
   'preferred_name' : [] | [string],
   'personality_traits' : Array<string>,
+  'domain_confidence' : Array<[string, number]>,
+  'style_evidence' : StyleEvidence,
+  'field_provenance' : Array<[string, FieldProvenance]>,
   'interests' : Array<string>,
   'name' : [] | [string],
   'response_preferences' : ResponsePreferences,
+  'next_item_seq' : bigint,
   'work_context' : [] | [WorkContext],
   'knowledge_domains' : Array<[string, number]>,
   'goals' : Array<PersonalGoal>,
@@ -234,10 +594,17 @@ export interface UserProfile {
   'communication_style' : CommunicationStyle,
 }
 export interface UserProfileUpdate {
-  'interests' : [] | [Array<string>],
+  'preferred_name' : [] | [string],
+  'personality_traits' : [] | [TextListPatch],
+  'interests' : [] | [TextListPatch],
   'name' : [] | [string],
   'response_preferences' : [] | [ResponsePreferences],
-  'goals' : [] | [Array<PersonalGoal>],
+  'work_context' : [] | [WorkContextPatch],
+  'goals' : [] | [GoalListPatch],
+  'important_dates' : [] | [ImportantDateListPatch],
+  'expertise_areas' : [] | [TextListPatch],
+  'relationships' : [] | [PersonListPatch],
+  'communication_style' : [] | [CommunicationStylePatch],
 }
 export interface WorkContext {
   'job_title' : [] | [string],
@@ -246,14 +613,70 @@ export interface WorkContext {
   'skills' : Array<string>,
   'industry' : [] | [string],
 }
+export interface WorkContextPatch {
+  'job_title' : [] | [string],
+  'company' : [] | [string],
+  'current_projects' : [] | [TextListPatch],
+  'skills' : [] | [TextListPatch],
+  'industry' : [] | [string],
+}
 export interface _SERVICE {
+  'activate_prompt_template' : ActorMethod<
+    [string, PromptTemplateKind, [] | [number]],
+    Result
+  >,
+  'add_edge' : ActorMethod<
+    [Principal, string, string, RelationshipType, [] | [number]],
+    Result_1
+  >,
+  'add_goal_milestone' : ActorMethod<[Principal, string, string], Result_2>,
+  'add_important_date' : ActorMethod<[Principal, ImportantDateInput], Result_3>,
+  'add_memory' : ActorMethod<[Principal, MemoryInput], Result_4>,
+  'add_memory_tags' : ActorMethod<[Principal, string, Array<string>], Result_5>,
+  'add_person' : ActorMethod<[Principal, PersonInput], Result_6>,
+  'begin_upload' : ActorMethod<[string, string, bigint], Result>,
+  'consolidate_memories' : ActorMethod<[Principal], Result_7>,
+  'create_goal' : ActorMethod<[Principal, GoalInput], Result_2>,
+  'create_task' : ActorMethod<
+    [Principal, [] | [string], string, [] | [bigint]],
+    Result_8
+  >,
+  'delete_attachment' : ActorMethod<[string], Result>,
+  'delete_document' : ActorMethod<[string], Result>,
+  'delete_goal' : ActorMethod<[Principal, string], Result>,
+  'delete_important_date' : ActorMethod<[Principal, string], Result>,
+  'delete_memory' : ActorMethod<[Principal, string], Result>,
+  'delete_person' : ActorMethod<[Principal, string], Result>,
+  'delete_task' : ActorMethod<[Principal, string], Result>,
+  'finish_attachment_upload' : ActorMethod<[string], Result_9>,
+  'finish_document_upload' : ActorMethod<[string, [] | [string]], Result_10>,
   'get_available_providers' : ActorMethod<[], Array<string>>,
   'get_canister_metrics' : ActorMethod<[], CanisterMetrics>,
+  'get_central_memories' : ActorMethod<[[] | [number]], Array<NodeCentrality>>,
+  'get_entity' : ActorMethod<[Principal, string], Result_11>,
+  'get_fact_history' : ActorMethod<[Principal, string], Result_12>,
+  'get_goals_overview' : ActorMethod<[Principal], Result_13>,
+  'get_learning_insights' : ActorMethod<[Principal], Result_14>,
+  'get_neighbors' : ActorMethod<
+    [string, [] | [Array<RelationshipType>], [] | [number]],
+    Result_15
+  >,
+  'get_notifications' : ActorMethod<[Principal, boolean], Array<Notification>>,
+  'get_person' : ActorMethod<[Principal, string], Result_16>,
+  'get_sentiment_trends' : ActorMethod<
+    [Principal, [] | [TimeRange], TrendBucket],
+    Result_17
+  >,
+  'get_subgraph' : ActorMethod<
+    [Array<string>, number, [] | [number]],
+    Result_18
+  >,
+  'get_tag_cloud' : ActorMethod<[Principal, [] | [number]], Array<TagCount>>,
   'get_user_conversations' : ActorMethod<
     [Principal],
     Array<EnhancedChatMessage>
   >,
-  'get_user_dashboard' : ActorMethod<[Principal], Result>,
+  'get_user_dashboard' : ActorMethod<[Principal], Result_19>,
   'get_user_knowledge_graph' : ActorMethod<
     [Principal],
     [] | [PersonalKnowledgeGraph]
@@ -265,15 +688,84 @@ export interface _SERVICE {
   'greet' : ActorMethod<[string], string>,
   'icp_ai_prompt' : ActorMethod<
     [string, [] | [string], [] | [string], [] | [boolean]],
-    Result_1
+    Result
+  >,
+  'list_attachments' : ActorMethod<[], Array<AttachmentInfo>>,
+  'list_documents' : ActorMethod<[], Array<DocumentInfo>>,
+  'list_entities' : ActorMethod<
+    [Principal, [] | [EntityType]],
+    Array<EntityRecord>
+  >,
+  'list_memories_by_tag' : ActorMethod<[Principal, string], Array<MemoryNode>>,
+  'list_open_tasks' : ActorMethod<[Principal], Array<ThreadTask>>,
+  'list_people' : ActorMethod<[Principal], Array<PersonalRelationship>>,
+  'list_prompt_templates' : ActorMethod<[[] | [string]], Array<PromptTemplate>>,
+  'list_upcoming_dates' : ActorMethod<
+    [Principal, [] | [number]],
+    Array<UpcomingEvent>
   >,
+  'lock_communication_style' : ActorMethod<
+    [Principal, string, boolean],
+    Result_20
+  >,
+  'mark_notifications_read' : ActorMethod<[Principal, Array<string>], Result>,
   'memory_mind_prompt' : ActorMethod<
     [string, [] | [string], [] | [boolean]],
-    Result_1
+    Result
+  >,
+  'memory_mind_prompt_with_attachments' : ActorMethod<
+    [string, [] | [string], Array<string>],
+    Result
+  >,
+  'merge_memories' : ActorMethod<[Principal, Array<string>], Result_5>,
+  'pin_memory' : ActorMethod<[Principal, string, boolean], Result_5>,
+  'preview_prompt_template' : ActorMethod<[PromptPreviewRequest], Result>,
+  'prompt' : ActorMethod<[string], Result>,
+  'remove_edge' : ActorMethod<
+    [Principal, string, string, [] | [RelationshipType]],
+    Result
   >,
-  'prompt' : ActorMethod<[string], Result_1>,
+  'remove_memory_tags' : ActorMethod<
+    [Principal, string, Array<string>],
+    Result_5
+  >,
+  'resolve_fact_conflict' : ActorMethod<[Principal, string, string], Result_5>,
+  'search_memories' : ActorMethod<[string, [] | [number]], Array<ScoredMemory>>,
+  'select_persona' : ActorMethod<[[] | [string]], Result>,
   'set_api_key' : ActorMethod<[string], undefined>,
-  'update_user_profile' : ActorMethod<[Principal, UserProfileUpdate], Result_1>,
+  'set_default_persona' : ActorMethod<[string], Result>,
+  'set_milestone_completed' : ActorMethod<
+    [Principal, string, number, boolean],
+    Result_2
+  >,
+  'set_model_sentiment' : ActorMethod<[boolean], Result>,
+  'set_model_tagging' : ActorMethod<[boolean], Result>,
+  'set_prompt_template' : ActorMethod<
+    [string, PromptTemplateKind, string, [] | [string], boolean],
+    Result_7
+  >,
+  'set_reminder_settings' : ActorMethod<
+    [Principal, ReminderSettings],
+    Result_21
+  >,
+  'set_task_status' : ActorMethod<[Principal, string, TaskStatus], Result_8>,
+  'shortest_path' : ActorMethod<[string, string], Result_18>,
+  'update_goal' : ActorMethod<[Principal, string, GoalUpdate], Result_2>,
+  'update_important_date' : ActorMethod<
+    [Principal, string, ImportantDateUpdate],
+    Result_3
+  >,
+  'update_memory' : ActorMethod<[Principal, string, MemoryUpdate], Result_5>,
+  'update_person' : ActorMethod<[Principal, string, PersonUpdate], Result_6>,
+  'update_task' : ActorMethod<[Principal, string, TaskUpdate], Result_8>,
+  'update_user_profile' : ActorMethod<
+    [Principal, UserProfileUpdate],
+    Result_22
+  >,
+  'upload_chunk' : ActorMethod<
+    [string, number, Uint8Array | number[]],
+    Result_23
+  >,
 }
 export declare const idlFactory: IDL.InterfaceFactory;
 export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
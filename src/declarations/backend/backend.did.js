@@ -1,4 +1,172 @@
 export const idlFactory = ({ IDL }) => {
+  const PromptTemplateKind = IDL.Variant({
+    'ContextualResponse' : IDL.Null,
+    'InquiryFirst' : IDL.Null,
+    'PartialAnswer' : IDL.Null,
+    'LearningOpportunity' : IDL.Null,
+  });
+  const Result = IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text });
+  const RelationshipType = IDL.Variant({
+    'PartOf' : IDL.Null,
+    'Related' : IDL.Null,
+    'UsedFor' : IDL.Null,
+    'Supersedes' : IDL.Null,
+    'OppositeOf' : IDL.Null,
+    'Mentions' : IDL.Null,
+    'ExampleOf' : IDL.Null,
+    'LeadsTo' : IDL.Null,
+    'CausedBy' : IDL.Null,
+  });
+  const KnowledgeEdge = IDL.Record({
+    'from_node' : IDL.Text,
+    'to_node' : IDL.Text,
+    'created_at' : IDL.Nat64,
+    'relationship_type' : RelationshipType,
+    'strength' : IDL.Float32,
+  });
+  const Result_1 = IDL.Variant({ 'Ok' : KnowledgeEdge, 'Err' : IDL.Text });
+  const TaskStatus = IDL.Variant({
+    'Paused' : IDL.Null,
+    'Active' : IDL.Null,
+    'Cancelled' : IDL.Null,
+    'Completed' : IDL.Null,
+  });
+  const Milestone = IDL.Record({
+    'completed' : IDL.Bool,
+    'description' : IDL.Text,
+    'completed_at' : IDL.Opt(IDL.Nat64),
+  });
+  const PersonalGoal = IDL.Record({
+    'id' : IDL.Text,
+    'status' : TaskStatus,
+    'updated_at' : IDL.Nat64,
+    'goal' : IDL.Text,
+    'importance' : IDL.Float32,
+    'created_at' : IDL.Nat64,
+    'progress' : IDL.Float32,
+    'category' : IDL.Text,
+    'target_date' : IDL.Opt(IDL.Nat64),
+    'milestones' : IDL.Vec(Milestone),
+  });
+  const Result_2 = IDL.Variant({ 'Ok' : PersonalGoal, 'Err' : IDL.Text });
+  const EventRecurrence = IDL.Variant({
+    'Once' : IDL.Null,
+    'Monthly' : IDL.Null,
+    'Yearly' : IDL.Null,
+  });
+  const ImportantDateInput = IDL.Record({
+    'lead_days' : IDL.Opt(IDL.Vec(IDL.Nat32)),
+    'date' : IDL.Int64,
+    'importance' : IDL.Opt(IDL.Float32),
+    'recurrence' : IDL.Opt(EventRecurrence),
+    'event' : IDL.Text,
+    'category' : IDL.Opt(IDL.Text),
+  });
+  const ImportantEvent = IDL.Record({
+    'id' : IDL.Text,
+    'lead_days' : IDL.Opt(IDL.Vec(IDL.Nat32)),
+    'date' : IDL.Int64,
+    'importance' : IDL.Float32,
+    'recurrence' : EventRecurrence,
+    'event' : IDL.Text,
+    'reminded_until' : IDL.Nat64,
+    'category' : IDL.Text,
+  });
+  const Result_3 = IDL.Variant({ 'Ok' : ImportantEvent, 'Err' : IDL.Text });
+  const MemoryNodeType = IDL.Variant({
+    'Fact' : IDL.Null,
+    'Goal' : IDL.Null,
+    'Entity' : IDL.Null,
+    'Summary' : IDL.Null,
+    'Knowledge' : IDL.Null,
+    'Experience' : IDL.Null,
+    'Preference' : IDL.Null,
+    'Document' : IDL.Null,
+    'Context' : IDL.Null,
+    'Relationship' : IDL.Null,
+  });
+  const MemoryInput = IDL.Record({
+    'node_type' : MemoryNodeType,
+    'content' : IDL.Text,
+    'tags' : IDL.Vec(IDL.Text),
+    'pinned' : IDL.Opt(IDL.Bool),
+    'importance_score' : IDL.Opt(IDL.Float32),
+  });
+  const MemoryNode = IDL.Record({
+    'id' : IDL.Text,
+    'superseded_by' : IDL.Opt(IDL.Text),
+    'node_type' : MemoryNodeType,
+    'content' : IDL.Text,
+    'fact_key' : IDL.Opt(IDL.Text),
+    'tags' : IDL.Vec(IDL.Text),
+    'created_at' : IDL.Nat64,
+    'last_accessed' : IDL.Nat64,
+    'version' : IDL.Nat32,
+    'pinned' : IDL.Bool,
+    'related_conversations' : IDL.Vec(IDL.Text),
+    'importance_score' : IDL.Float32,
+    'embedding' : IDL.Opt(IDL.Vec(IDL.Float32)),
+    'access_count' : IDL.Nat32,
+  });
+  const AddedMemory = IDL.Record({ 'merged' : IDL.Bool, 'node' : MemoryNode });
+  const Result_4 = IDL.Variant({ 'Ok' : AddedMemory, 'Err' : IDL.Text });
+  const Result_5 = IDL.Variant({ 'Ok' : MemoryNode, 'Err' : IDL.Text });
+  const PersonInput = IDL.Record({
+    'context' : IDL.Opt(IDL.Text),
+    'name' : IDL.Text,
+    'importance' : IDL.Opt(IDL.Float32),
+    'relationship_type' : IDL.Text,
+  });
+  const PersonalRelationship = IDL.Record({
+    'id' : IDL.Text,
+    'context' : IDL.Text,
+    'last_mentioned' : IDL.Nat64,
+    'name' : IDL.Text,
+    'importance' : IDL.Float32,
+    'relationship_type' : IDL.Text,
+    'mention_count' : IDL.Nat32,
+    'entity_key' : IDL.Opt(IDL.Text),
+    'first_mentioned' : IDL.Nat64,
+    'memory_ids' : IDL.Vec(IDL.Text),
+  });
+  const Result_6 = IDL.Variant({
+    'Ok' : PersonalRelationship,
+    'Err' : IDL.Text,
+  });
+  const Result_7 = IDL.Variant({ 'Ok' : IDL.Nat32, 'Err' : IDL.Text });
+  const GoalInput = IDL.Record({
+    'goal' : IDL.Text,
+    'importance' : IDL.Opt(IDL.Float32),
+    'category' : IDL.Opt(IDL.Text),
+    'target_date' : IDL.Opt(IDL.Nat64),
+    'milestones' : IDL.Vec(IDL.Text),
+  });
+  const Task = IDL.Record({
+    'id' : IDL.Text,
+    'status' : TaskStatus,
+    'updated_at' : IDL.Nat64,
+    'description' : IDL.Text,
+    'created_at' : IDL.Nat64,
+    'due_date' : IDL.Opt(IDL.Nat64),
+    'completed_at' : IDL.Opt(IDL.Nat64),
+  });
+  const Result_8 = IDL.Variant({ 'Ok' : Task, 'Err' : IDL.Text });
+  const AttachmentInfo = IDL.Record({
+    'id' : IDL.Text,
+    'size_bytes' : IDL.Nat64,
+    'mime_type' : IDL.Text,
+    'file_name' : IDL.Text,
+    'facts_extracted' : IDL.Bool,
+    'uploaded_at' : IDL.Nat64,
+  });
+  const Result_9 = IDL.Variant({ 'Ok' : AttachmentInfo, 'Err' : IDL.Text });
+  const DocumentInfo = IDL.Record({
+    'id' : IDL.Text,
+    'title' : IDL.Text,
+    'passage_count' : IDL.Nat32,
+    'created_at' : IDL.Nat64,
+  });
+  const Result_10 = IDL.Variant({ 'Ok' : DocumentInfo, 'Err' : IDL.Text });
   const CanisterMetrics = IDL.Record({
     'storage_used_bytes' : IDL.Nat64,
     'total_queries' : IDL.Nat64,
@@ -8,6 +176,33 @@ export const idlFactory = ({ IDL }) => {
     'knowledge_nodes_created' : IDL.Nat64,
     'total_cycles_consumed' : IDL.Nat64,
   });
+  const NodeCentrality = IDL.Record({
+    'weighted_degree' : IDL.Float32,
+    'node' : MemoryNode,
+    'degree' : IDL.Nat32,
+    'pagerank' : IDL.Float32,
+  });
+  const EntityType = IDL.Variant({
+    'Date' : IDL.Null,
+    'Company' : IDL.Null,
+    'Person' : IDL.Null,
+    'Technology' : IDL.Null,
+    'Location' : IDL.Null,
+    'Project' : IDL.Null,
+    'Other' : IDL.Null,
+  });
+  const EntityRecord = IDL.Record({
+    'node_id' : IDL.Text,
+    'name' : IDL.Text,
+    'thread_ids' : IDL.Vec(IDL.Text),
+    'mention_count' : IDL.Nat32,
+    'aliases' : IDL.Vec(IDL.Text),
+    'first_seen' : IDL.Nat64,
+    'last_seen' : IDL.Nat64,
+    'entity_type' : EntityType,
+    'last_context' : IDL.Text,
+    'message_timestamps' : IDL.Vec(IDL.Nat64),
+  });
   const Sentiment = IDL.Variant({
     'Negative' : IDL.Null,
     'Excited' : IDL.Null,
@@ -63,8 +258,105 @@ export const idlFactory = ({ IDL }) => {
     'timestamp' : IDL.Nat64,
     'cycles_cost' : IDL.Opt(IDL.Nat64),
     'response_strategy' : IDL.Opt(ResponseStrategy),
+    'attachment_ids' : IDL.Vec(IDL.Text),
     'content_stored_on_chain' : IDL.Opt(IDL.Bool),
   });
+  const EntityDetail = IDL.Record({
+    'entity' : EntityRecord,
+    'memories' : IDL.Vec(MemoryNode),
+    'conversations' : IDL.Vec(EnhancedChatMessage),
+  });
+  const Result_11 = IDL.Variant({ 'Ok' : EntityDetail, 'Err' : IDL.Text });
+  const Result_12 = IDL.Variant({
+    'Ok' : IDL.Vec(MemoryNode),
+    'Err' : IDL.Text,
+  });
+  const GoalsOverview = IDL.Record({
+    'active' : IDL.Vec(PersonalGoal),
+    'at_risk' : IDL.Vec(PersonalGoal),
+    'completed' : IDL.Vec(PersonalGoal),
+    'inactive' : IDL.Vec(PersonalGoal),
+    'overdue' : IDL.Vec(PersonalGoal),
+  });
+  const Result_13 = IDL.Variant({ 'Ok' : GoalsOverview, 'Err' : IDL.Text });
+  const TopicCount = IDL.Record({ 'topic' : IDL.Text, 'count' : IDL.Nat32 });
+  const LearningInsights = IDL.Record({
+    'session_count' : IDL.Nat32,
+    'interaction_count' : IDL.Nat32,
+    'avg_session_minutes' : IDL.Float32,
+    'question_types' : IDL.Vec(TopicCount),
+    'hourly_activity' : IDL.Vec(IDL.Nat32),
+    'peak_hours' : IDL.Vec(IDL.Nat32),
+    'top_topics' : IDL.Vec(TopicCount),
+    'question_asking_frequency' : IDL.Float32,
+    'learning_speed' : IDL.Float32,
+  });
+  const Result_14 = IDL.Variant({ 'Ok' : LearningInsights, 'Err' : IDL.Text });
+  const GraphNeighbor = IDL.Record({
+    'edge' : KnowledgeEdge,
+    'node' : MemoryNode,
+    'depth' : IDL.Nat32,
+  });
+  const Result_15 = IDL.Variant({
+    'Ok' : IDL.Vec(GraphNeighbor),
+    'Err' : IDL.Text,
+  });
+  const Notification = IDL.Record({
+    'id' : IDL.Text,
+    'read' : IDL.Bool,
+    'occurs_on' : IDL.Nat64,
+    'message' : EnhancedChatMessage,
+    'event_id' : IDL.Text,
+  });
+  const PersonDetail = IDL.Record({
+    'entity' : IDL.Opt(EntityRecord),
+    'person' : PersonalRelationship,
+    'memories' : IDL.Vec(MemoryNode),
+  });
+  const Result_16 = IDL.Variant({ 'Ok' : PersonDetail, 'Err' : IDL.Text });
+  const TimeRange = IDL.Record({ 'end' : IDL.Nat64, 'start' : IDL.Nat64 });
+  const TrendBucket = IDL.Variant({ 'Day' : IDL.Null, 'Week' : IDL.Null });
+  const SentimentShift = IDL.Record({
+    'previous_score' : IDL.Float32,
+    'suggestion' : IDL.Text,
+    'score' : IDL.Float32,
+    'bucket_start' : IDL.Nat64,
+    'change' : IDL.Float32,
+  });
+  const SentimentCounts = IDL.Record({
+    'negative' : IDL.Nat32,
+    'frustrated' : IDL.Nat32,
+    'excited' : IDL.Nat32,
+    'curious' : IDL.Nat32,
+    'positive' : IDL.Nat32,
+    'neutral' : IDL.Nat32,
+  });
+  const ThreadSentiment = IDL.Record({
+    'average_score' : IDL.Float32,
+    'topic' : IDL.Text,
+    'last_message_at' : IDL.Nat64,
+    'dominant' : Sentiment,
+    'counts' : SentimentCounts,
+    'thread_id' : IDL.Text,
+  });
+  const SentimentBucket = IDL.Record({
+    'average_score' : IDL.Float32,
+    'start' : IDL.Nat64,
+    'dominant' : Sentiment,
+    'counts' : SentimentCounts,
+  });
+  const SentimentTrends = IDL.Record({
+    'shifts' : IDL.Vec(SentimentShift),
+    'threads' : IDL.Vec(ThreadSentiment),
+    'buckets' : IDL.Vec(SentimentBucket),
+  });
+  const Result_17 = IDL.Variant({ 'Ok' : SentimentTrends, 'Err' : IDL.Text });
+  const Subgraph = IDL.Record({
+    'edges' : IDL.Vec(KnowledgeEdge),
+    'nodes' : IDL.Vec(MemoryNode),
+  });
+  const Result_18 = IDL.Variant({ 'Ok' : Subgraph, 'Err' : IDL.Text });
+  const TagCount = IDL.Record({ 'tag' : IDL.Text, 'count' : IDL.Nat32 });
   const SubscriptionTier = IDL.Variant({
     'Premium' : IDL.Record({
       'cycles_included' : IDL.Nat64,
@@ -89,7 +381,14 @@ export const idlFactory = ({ IDL }) => {
     'token_balance' : IDL.Nat64,
     'conversation_count' : IDL.Nat64,
   });
-  const Result = IDL.Variant({ 'Ok' : UserDashboard, 'Err' : IDL.Text });
+  const Result_19 = IDL.Variant({ 'Ok' : UserDashboard, 'Err' : IDL.Text });
+  const PendingGoalUpdate = IDL.Record({
+    'goal_id' : IDL.Text,
+    'progress' : IDL.Float32,
+    'asked_at' : IDL.Nat64,
+    'evidence' : IDL.Text,
+    'milestone' : IDL.Opt(IDL.Nat32),
+  });
   const ResponseLength = IDL.Variant({
     'Short' : IDL.Null,
     'Long' : IDL.Null,
@@ -104,43 +403,6 @@ export const idlFactory = ({ IDL }) => {
     'question_asking_frequency' : IDL.Float32,
     'learning_speed' : IDL.Float32,
   });
-  const RelationshipType = IDL.Variant({
-    'PartOf' : IDL.Null,
-    'Related' : IDL.Null,
-    'UsedFor' : IDL.Null,
-    'OppositeOf' : IDL.Null,
-    'ExampleOf' : IDL.Null,
-    'LeadsTo' : IDL.Null,
-    'CausedBy' : IDL.Null,
-  });
-  const KnowledgeEdge = IDL.Record({
-    'from_node' : IDL.Text,
-    'to_node' : IDL.Text,
-    'created_at' : IDL.Nat64,
-    'relationship_type' : RelationshipType,
-    'strength' : IDL.Float32,
-  });
-  const TaskStatus = IDL.Variant({
-    'Paused' : IDL.Null,
-    'Active' : IDL.Null,
-    'Cancelled' : IDL.Null,
-    'Completed' : IDL.Null,
-  });
-  const Task = IDL.Record({
-    'status' : TaskStatus,
-    'description' : IDL.Text,
-    'created_at' : IDL.Nat64,
-    'due_date' : IDL.Opt(IDL.Nat64),
-  });
-  const EntityType = IDL.Variant({
-    'Date' : IDL.Null,
-    'Company' : IDL.Null,
-    'Person' : IDL.Null,
-    'Technology' : IDL.Null,
-    'Location' : IDL.Null,
-    'Project' : IDL.Null,
-    'Other' : IDL.Null,
-  });
   const Entity = IDL.Record({
     'context' : IDL.Text,
     'name' : IDL.Text,
@@ -155,25 +417,28 @@ export const idlFactory = ({ IDL }) => {
     'mentioned_entities' : IDL.Vec(Entity),
     'thread_id' : IDL.Text,
   });
-  const MemoryNodeType = IDL.Variant({
-    'Fact' : IDL.Null,
-    'Goal' : IDL.Null,
-    'Knowledge' : IDL.Null,
-    'Experience' : IDL.Null,
-    'Preference' : IDL.Null,
-    'Context' : IDL.Null,
-    'Relationship' : IDL.Null,
-  });
-  const MemoryNode = IDL.Record({
-    'id' : IDL.Text,
-    'node_type' : MemoryNodeType,
-    'content' : IDL.Text,
-    'tags' : IDL.Vec(IDL.Text),
-    'created_at' : IDL.Nat64,
-    'last_accessed' : IDL.Nat64,
-    'related_conversations' : IDL.Vec(IDL.Text),
-    'importance_score' : IDL.Float32,
-    'access_count' : IDL.Nat32,
+  const ReminderSettings = IDL.Record({
+    'lead_days' : IDL.Vec(IDL.Nat32),
+    'utc_offset_minutes' : IDL.Int32,
+    'enabled' : IDL.Bool,
+  });
+  const StyleEvidence = IDL.Record({
+    'formality' : IDL.Float32,
+    'humor' : IDL.Float32,
+    'messages' : IDL.Nat32,
+    'words_per_message' : IDL.Float32,
+    'emoji' : IDL.Float32,
+    'detail_feedback_at' : IDL.Nat32,
+  });
+  const ProfileSource = IDL.Variant({
+    'Explicit' : IDL.Null,
+    'Extracted' : IDL.Null,
+  });
+  const FieldProvenance = IDL.Record({
+    'updated_at' : IDL.Nat64,
+    'source' : ProfileSource,
+    'memory_id' : IDL.Opt(IDL.Text),
+    'confidence' : IDL.Float32,
   });
   const ResponsePreferences = IDL.Record({
     'autopilot_enabled' : IDL.Bool,
@@ -189,30 +454,15 @@ export const idlFactory = ({ IDL }) => {
     'skills' : IDL.Vec(IDL.Text),
     'industry' : IDL.Opt(IDL.Text),
   });
-  const PersonalGoal = IDL.Record({
-    'goal' : IDL.Text,
-    'importance' : IDL.Float32,
-    'progress' : IDL.Float32,
-    'category' : IDL.Text,
-    'target_date' : IDL.Opt(IDL.Nat64),
-  });
-  const ImportantEvent = IDL.Record({
-    'date' : IDL.Nat64,
-    'importance' : IDL.Float32,
-    'event' : IDL.Text,
-    'category' : IDL.Text,
-  });
-  const PersonalRelationship = IDL.Record({
-    'context' : IDL.Text,
-    'name' : IDL.Text,
-    'importance' : IDL.Float32,
-    'relationship_type' : IDL.Text,
-  });
   const ConversationPatterns = IDL.Record({
+    'session_count' : IDL.Nat32,
     'question_types' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Nat32)),
     'avg_session_length' : IDL.Float32,
+    'completed_session_nanos' : IDL.Nat64,
     'common_topics' : IDL.Vec(IDL.Text),
+    'session_started_at' : IDL.Nat64,
     'time_patterns' : IDL.Vec(IDL.Nat32),
+    'last_active_at' : IDL.Nat64,
   });
   const FormalityLevel = IDL.Variant({
     'VeryFormal' : IDL.Null,
@@ -236,15 +486,21 @@ export const idlFactory = ({ IDL }) => {
     'formality_level' : FormalityLevel,
     'technical_level' : TechnicalLevel,
     'emoji_usage' : IDL.Bool,
+    'locked_fields' : IDL.Vec(IDL.Text),
     'detail_preference' : DetailLevel,
     'humor_preference' : IDL.Bool,
   });
   const UserProfile = IDL.Record({
+    'reminder_settings' : ReminderSettings,
     'preferred_name' : IDL.Opt(IDL.Text),
     'personality_traits' : IDL.Vec(IDL.Text),
+    'domain_confidence' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Float32)),
+    'style_evidence' : StyleEvidence,
+    'field_provenance' : IDL.Vec(IDL.Tuple(IDL.Text, FieldProvenance)),
     'interests' : IDL.Vec(IDL.Text),
     'name' : IDL.Opt(IDL.Text),
     'response_preferences' : ResponsePreferences,
+    'next_item_seq' : IDL.Nat64,
     'work_context' : IDL.Opt(WorkContext),
     'knowledge_domains' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Float32)),
     'goals' : IDL.Vec(PersonalGoal),
@@ -254,30 +510,247 @@ export const idlFactory = ({ IDL }) => {
     'conversation_patterns' : ConversationPatterns,
     'communication_style' : CommunicationStyle,
   });
+  const PendingConfirmation = IDL.Record({
+    'fact_key' : IDL.Text,
+    'proposed_node' : IDL.Text,
+    'asked_at' : IDL.Nat64,
+    'current_node' : IDL.Text,
+  });
   const PersonalKnowledgeGraph = IDL.Record({
+    'pending_goal_update' : IDL.Opt(PendingGoalUpdate),
+    'last_decay_at' : IDL.Nat64,
     'learning_patterns' : LearningHistory,
+    'notifications' : IDL.Vec(Notification),
     'last_updated' : IDL.Nat64,
+    'last_sentiment_check_in' : IDL.Nat64,
+    'entities' : IDL.Vec(IDL.Tuple(IDL.Text, EntityRecord)),
+    'next_node_seq' : IDL.Nat64,
+    'tag_vocabulary' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Nat32)),
     'relationships' : IDL.Vec(KnowledgeEdge),
     'context_threads' : IDL.Vec(IDL.Tuple(IDL.Text, ConversationContext)),
     'memory_nodes' : IDL.Vec(IDL.Tuple(IDL.Text, MemoryNode)),
     'user_profile' : UserProfile,
+    'pending_confirmations' : IDL.Vec(PendingConfirmation),
+  });
+  const ThreadTask = IDL.Record({
+    'topic' : IDL.Text,
+    'task' : Task,
+    'overdue' : IDL.Bool,
+    'thread_id' : IDL.Text,
+  });
+  const PromptTemplate = IDL.Record({
+    'body' : IDL.Text,
+    'kind' : PromptTemplateKind,
+    'note' : IDL.Opt(IDL.Text),
+    'created_at' : IDL.Nat64,
+    'created_by' : IDL.Principal,
+    'version' : IDL.Nat32,
+    'persona' : IDL.Text,
+  });
+  const UpcomingEvent = IDL.Record({
+    'occurs_on' : IDL.Nat64,
+    'days_until' : IDL.Nat32,
+    'event' : ImportantEvent,
+  });
+  const Result_20 = IDL.Variant({
+    'Ok' : CommunicationStyle,
+    'Err' : IDL.Text,
+  });
+  const PromptPreviewRequest = IDL.Record({
+    'question' : IDL.Text,
+    'body' : IDL.Opt(IDL.Text),
+    'kind' : PromptTemplateKind,
+    'user' : IDL.Opt(IDL.Principal),
+    'persona' : IDL.Opt(IDL.Text),
+  });
+  const ScoredMemory = IDL.Record({
+    'node' : MemoryNode,
+    'score' : IDL.Float32,
+  });
+  const Result_21 = IDL.Variant({ 'Ok' : ReminderSettings, 'Err' : IDL.Text });
+  const GoalUpdate = IDL.Record({
+    'status' : IDL.Opt(TaskStatus),
+    'goal' : IDL.Opt(IDL.Text),
+    'importance' : IDL.Opt(IDL.Float32),
+    'progress' : IDL.Opt(IDL.Float32),
+    'category' : IDL.Opt(IDL.Text),
+    'target_date' : IDL.Opt(IDL.Nat64),
+  });
+  const ImportantDateUpdate = IDL.Record({
+    'lead_days' : IDL.Opt(IDL.Vec(IDL.Nat32)),
+    'date' : IDL.Opt(IDL.Int64),
+    'importance' : IDL.Opt(IDL.Float32),
+    'recurrence' : IDL.Opt(EventRecurrence),
+    'event' : IDL.Opt(IDL.Text),
+    'category' : IDL.Opt(IDL.Text),
+  });
+  const MemoryUpdate = IDL.Record({
+    'node_type' : IDL.Opt(MemoryNodeType),
+    'content' : IDL.Opt(IDL.Text),
+    'tags' : IDL.Opt(IDL.Vec(IDL.Text)),
+    'importance_score' : IDL.Opt(IDL.Float32),
+  });
+  const PersonUpdate = IDL.Record({
+    'context' : IDL.Opt(IDL.Text),
+    'name' : IDL.Opt(IDL.Text),
+    'importance' : IDL.Opt(IDL.Float32),
+    'relationship_type' : IDL.Opt(IDL.Text),
+  });
+  const TaskUpdate = IDL.Record({
+    'description' : IDL.Opt(IDL.Text),
+    'due_date' : IDL.Opt(IDL.Nat64),
+  });
+  const TextListPatch = IDL.Variant({
+    'Add' : IDL.Vec(IDL.Text),
+    'Remove' : IDL.Vec(IDL.Text),
+    'Replace' : IDL.Vec(IDL.Text),
+  });
+  const WorkContextPatch = IDL.Record({
+    'job_title' : IDL.Opt(IDL.Text),
+    'company' : IDL.Opt(IDL.Text),
+    'current_projects' : IDL.Opt(TextListPatch),
+    'skills' : IDL.Opt(TextListPatch),
+    'industry' : IDL.Opt(IDL.Text),
+  });
+  const GoalListPatch = IDL.Variant({
+    'Add' : IDL.Vec(GoalInput),
+    'Remove' : IDL.Vec(IDL.Text),
+    'Replace' : IDL.Vec(GoalInput),
+  });
+  const ImportantDateListPatch = IDL.Variant({
+    'Add' : IDL.Vec(ImportantDateInput),
+    'Remove' : IDL.Vec(IDL.Text),
+    'Replace' : IDL.Vec(ImportantDateInput),
+  });
+  const PersonListPatch = IDL.Variant({
+    'Add' : IDL.Vec(PersonInput),
+    'Remove' : IDL.Vec(IDL.Text),
+    'Replace' : IDL.Vec(PersonInput),
+  });
+  const CommunicationStylePatch = IDL.Record({
+    'formality_level' : IDL.Opt(FormalityLevel),
+    'technical_level' : IDL.Opt(TechnicalLevel),
+    'emoji_usage' : IDL.Opt(IDL.Bool),
+    'detail_preference' : IDL.Opt(DetailLevel),
+    'humor_preference' : IDL.Opt(IDL.Bool),
   });
-  const Result_1 = IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text });
   const UserProfileUpdate = IDL.Record({
-    'interests' : IDL.Opt(IDL.Vec(IDL.Text)),
+    'preferred_name' : IDL.Opt(IDL.Text),
+    'personality_traits' : IDL.Opt(TextListPatch),
+    'interests' : IDL.Opt(TextListPatch),
     'name' : IDL.Opt(IDL.Text),
     'response_preferences' : IDL.Opt(ResponsePreferences),
-    'goals' : IDL.Opt(IDL.Vec(PersonalGoal)),
-  });
+    'work_context' : IDL.Opt(WorkContextPatch),
+    'goals' : IDL.Opt(GoalListPatch),
+    'important_dates' : IDL.Opt(ImportantDateListPatch),
+    'expertise_areas' : IDL.Opt(TextListPatch),
+    'relationships' : IDL.Opt(PersonListPatch),
+    'communication_style' : IDL.Opt(CommunicationStylePatch),
+  });
+  const Result_22 = IDL.Variant({ 'Ok' : UserProfile, 'Err' : IDL.Text });
+  const Result_23 = IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : IDL.Text });
   return IDL.Service({
+    'activate_prompt_template' : IDL.Func(
+        [IDL.Text, PromptTemplateKind, IDL.Opt(IDL.Nat32)],
+        [Result],
+        [],
+      ),
+    'add_edge' : IDL.Func(
+        [
+          IDL.Principal,
+          IDL.Text,
+          IDL.Text,
+          RelationshipType,
+          IDL.Opt(IDL.Float32),
+        ],
+        [Result_1],
+        [],
+      ),
+    'add_goal_milestone' : IDL.Func(
+        [IDL.Principal, IDL.Text, IDL.Text],
+        [Result_2],
+        [],
+      ),
+    'add_important_date' : IDL.Func(
+        [IDL.Principal, ImportantDateInput],
+        [Result_3],
+        [],
+      ),
+    'add_memory' : IDL.Func([IDL.Principal, MemoryInput], [Result_4], []),
+    'add_memory_tags' : IDL.Func(
+        [IDL.Principal, IDL.Text, IDL.Vec(IDL.Text)],
+        [Result_5],
+        [],
+      ),
+    'add_person' : IDL.Func([IDL.Principal, PersonInput], [Result_6], []),
+    'begin_upload' : IDL.Func([IDL.Text, IDL.Text, IDL.Nat64], [Result], []),
+    'consolidate_memories' : IDL.Func([IDL.Principal], [Result_7], []),
+    'create_goal' : IDL.Func([IDL.Principal, GoalInput], [Result_2], []),
+    'create_task' : IDL.Func(
+        [IDL.Principal, IDL.Opt(IDL.Text), IDL.Text, IDL.Opt(IDL.Nat64)],
+        [Result_8],
+        [],
+      ),
+    'delete_attachment' : IDL.Func([IDL.Text], [Result], []),
+    'delete_document' : IDL.Func([IDL.Text], [Result], []),
+    'delete_goal' : IDL.Func([IDL.Principal, IDL.Text], [Result], []),
+    'delete_important_date' : IDL.Func([IDL.Principal, IDL.Text], [Result], []),
+    'delete_memory' : IDL.Func([IDL.Principal, IDL.Text], [Result], []),
+    'delete_person' : IDL.Func([IDL.Principal, IDL.Text], [Result], []),
+    'delete_task' : IDL.Func([IDL.Principal, IDL.Text], [Result], []),
+    'finish_attachment_upload' : IDL.Func([IDL.Text], [Result_9], []),
+    'finish_document_upload' : IDL.Func(
+        [IDL.Text, IDL.Opt(IDL.Text)],
+        [Result_10],
+        [],
+      ),
     'get_available_providers' : IDL.Func([], [IDL.Vec(IDL.Text)], ['query']),
     'get_canister_metrics' : IDL.Func([], [CanisterMetrics], ['query']),
+    'get_central_memories' : IDL.Func(
+        [IDL.Opt(IDL.Nat32)],
+        [IDL.Vec(NodeCentrality)],
+        ['query'],
+      ),
+    'get_entity' : IDL.Func([IDL.Principal, IDL.Text], [Result_11], ['query']),
+    'get_fact_history' : IDL.Func(
+        [IDL.Principal, IDL.Text],
+        [Result_12],
+        ['query'],
+      ),
+    'get_goals_overview' : IDL.Func([IDL.Principal], [Result_13], ['query']),
+    'get_learning_insights' : IDL.Func([IDL.Principal], [Result_14], ['query']),
+    'get_neighbors' : IDL.Func(
+        [IDL.Text, IDL.Opt(IDL.Vec(RelationshipType)), IDL.Opt(IDL.Nat32)],
+        [Result_15],
+        ['query'],
+      ),
+    'get_notifications' : IDL.Func(
+        [IDL.Principal, IDL.Bool],
+        [IDL.Vec(Notification)],
+        ['query'],
+      ),
+    'get_person' : IDL.Func([IDL.Principal, IDL.Text], [Result_16], ['query']),
+    'get_sentiment_trends' : IDL.Func(
+        [IDL.Principal, IDL.Opt(TimeRange), TrendBucket],
+        [Result_17],
+        ['query'],
+      ),
+    'get_subgraph' : IDL.Func(
+        [IDL.Vec(IDL.Text), IDL.Nat32, IDL.Opt(IDL.Nat32)],
+        [Result_18],
+        ['query'],
+      ),
+    'get_tag_cloud' : IDL.Func(
+        [IDL.Principal, IDL.Opt(IDL.Nat32)],
+        [IDL.Vec(TagCount)],
+        ['query'],
+      ),
     'get_user_conversations' : IDL.Func(
         [IDL.Principal],
         [IDL.Vec(EnhancedChatMessage)],
         ['query'],
       ),
-    'get_user_dashboard' : IDL.Func([IDL.Principal], [Result], ['query']),
+    'get_user_dashboard' : IDL.Func([IDL.Principal], [Result_19], ['query']),
     'get_user_knowledge_graph' : IDL.Func(
         [IDL.Principal],
         [IDL.Opt(PersonalKnowledgeGraph)],
@@ -291,19 +764,156 @@ export const idlFactory = ({ IDL }) => {
     'greet' : IDL.Func([IDL.Text], [IDL.Text], ['query']),
     'icp_ai_prompt' : IDL.Func(
         [IDL.Text, IDL.Opt(IDL.Text), IDL.Opt(IDL.Text), IDL.Opt(IDL.Bool)],
-        [Result_1],
+        [Result],
+        [],
+      ),
+    'list_attachments' : IDL.Func([], [IDL.Vec(AttachmentInfo)], ['query']),
+    'list_documents' : IDL.Func([], [IDL.Vec(DocumentInfo)], ['query']),
+    'list_entities' : IDL.Func(
+        [IDL.Principal, IDL.Opt(EntityType)],
+        [IDL.Vec(EntityRecord)],
+        ['query'],
+      ),
+    'list_memories_by_tag' : IDL.Func(
+        [IDL.Principal, IDL.Text],
+        [IDL.Vec(MemoryNode)],
+        ['query'],
+      ),
+    'list_open_tasks' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Vec(ThreadTask)],
+        ['query'],
+      ),
+    'list_people' : IDL.Func(
+        [IDL.Principal],
+        [IDL.Vec(PersonalRelationship)],
+        ['query'],
+      ),
+    'list_prompt_templates' : IDL.Func(
+        [IDL.Opt(IDL.Text)],
+        [IDL.Vec(PromptTemplate)],
+        ['query'],
+      ),
+    'list_upcoming_dates' : IDL.Func(
+        [IDL.Principal, IDL.Opt(IDL.Nat32)],
+        [IDL.Vec(UpcomingEvent)],
+        ['query'],
+      ),
+    'lock_communication_style' : IDL.Func(
+        [IDL.Principal, IDL.Text, IDL.Bool],
+        [Result_20],
+        [],
+      ),
+    'mark_notifications_read' : IDL.Func(
+        [IDL.Principal, IDL.Vec(IDL.Text)],
+        [Result],
         [],
       ),
     'memory_mind_prompt' : IDL.Func(
         [IDL.Text, IDL.Opt(IDL.Text), IDL.Opt(IDL.Bool)],
-        [Result_1],
+        [Result],
+        [],
+      ),
+    'memory_mind_prompt_with_attachments' : IDL.Func(
+        [IDL.Text, IDL.Opt(IDL.Text), IDL.Vec(IDL.Text)],
+        [Result],
+        [],
+      ),
+    'merge_memories' : IDL.Func(
+        [IDL.Principal, IDL.Vec(IDL.Text)],
+        [Result_5],
         [],
       ),
-    'prompt' : IDL.Func([IDL.Text], [Result_1], []),
+    'pin_memory' : IDL.Func(
+        [IDL.Principal, IDL.Text, IDL.Bool],
+        [Result_5],
+        [],
+      ),
+    'preview_prompt_template' : IDL.Func(
+        [PromptPreviewRequest],
+        [Result],
+        ['query'],
+      ),
+    'prompt' : IDL.Func([IDL.Text], [Result], []),
+    'remove_edge' : IDL.Func(
+        [IDL.Principal, IDL.Text, IDL.Text, IDL.Opt(RelationshipType)],
+        [Result],
+        [],
+      ),
+    'remove_memory_tags' : IDL.Func(
+        [IDL.Principal, IDL.Text, IDL.Vec(IDL.Text)],
+        [Result_5],
+        [],
+      ),
+    'resolve_fact_conflict' : IDL.Func(
+        [IDL.Principal, IDL.Text, IDL.Text],
+        [Result_5],
+        [],
+      ),
+    'search_memories' : IDL.Func(
+        [IDL.Text, IDL.Opt(IDL.Nat32)],
+        [IDL.Vec(ScoredMemory)],
+        ['query'],
+      ),
+    'select_persona' : IDL.Func([IDL.Opt(IDL.Text)], [Result], []),
     'set_api_key' : IDL.Func([IDL.Text], [], []),
+    'set_default_persona' : IDL.Func([IDL.Text], [Result], []),
+    'set_milestone_completed' : IDL.Func(
+        [IDL.Principal, IDL.Text, IDL.Nat32, IDL.Bool],
+        [Result_2],
+        [],
+      ),
+    'set_model_sentiment' : IDL.Func([IDL.Bool], [Result], []),
+    'set_model_tagging' : IDL.Func([IDL.Bool], [Result], []),
+    'set_prompt_template' : IDL.Func(
+        [IDL.Text, PromptTemplateKind, IDL.Text, IDL.Opt(IDL.Text), IDL.Bool],
+        [Result_7],
+        [],
+      ),
+    'set_reminder_settings' : IDL.Func(
+        [IDL.Principal, ReminderSettings],
+        [Result_21],
+        [],
+      ),
+    'set_task_status' : IDL.Func(
+        [IDL.Principal, IDL.Text, TaskStatus],
+        [Result_8],
+        [],
+      ),
+    'shortest_path' : IDL.Func([IDL.Text, IDL.Text], [Result_18], ['query']),
+    'update_goal' : IDL.Func(
+        [IDL.Principal, IDL.Text, GoalUpdate],
+        [Result_2],
+        [],
+      ),
+    'update_important_date' : IDL.Func(
+        [IDL.Principal, IDL.Text, ImportantDateUpdate],
+        [Result_3],
+        [],
+      ),
+    'update_memory' : IDL.Func(
+        [IDL.Principal, IDL.Text, MemoryUpdate],
+        [Result_5],
+        [],
+      ),
+    'update_person' : IDL.Func(
+        [IDL.Principal, IDL.Text, PersonUpdate],
+        [Result_6],
+        [],
+      ),
+    'update_task' : IDL.Func(
+        [IDL.Principal, IDL.Text, TaskUpdate],
+        [Result_8],
+        [],
+      ),
     'update_user_profile' : IDL.Func(
         [IDL.Principal, UserProfileUpdate],
-        [Result_1],
+        [Result_22],
+        [],
+      ),
+    'upload_chunk' : IDL.Func(
+        [IDL.Text, IDL.Nat32, IDL.Vec(IDL.Nat8)],
+        [Result_23],
         [],
       ),
   });